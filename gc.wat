@@ -3,14 +3,16 @@
 ;;TODO @mark: edit: also allocate stack values through this, those are used for roots
 
 ;; - allocations are N pointers followed by M bytes if non-pointer data
-        ;;TODO @mark: how does this work with arrays? ^
+;;   arrays are either all pointers or all data, so they use the same layout,
+;;   with the element count stored as either N or M
 ;; - code only reads/writes allocated memory, and only while reachable from either roots or allocated pointers
+;; - pointers in heap objects that may be old are written through write_pointer (the write barrier)
 ;; - roots don't change during GC
 ;; - there is a single thread (or in the future perhaps one heap per thread)
 ;; - most data is immutable, and only mutable data can mutate
-;;   (this is important for e.g. the assumption that old gen cannot point to young gen)
 ;; - all allocations are multiples of 32 bytes
-;; - for now (can be lifted): at most 128 pointers and words of data
+;; - objects with more than 255 pointers and words of data together get a two-word metadata header
+;;   (the same threshold as gc.rs)
 ;; - objects do not know when they get GC'ed (no finalize/drop methods)
 ;;
 ;; priorities for the GC are:
@@ -27,62 +29,206 @@
 ;;   - 8: 0 if first half of young gen is active, 1 otherwise
 ;;   - 12: end of young gen active half
 ;;   - 16: end of old gen heap
-;; - stack (partial one for things with pointers or dynamically-sized objects)
+;;   - 20: nesting depth of alloc_gc_disable (0 means allocation may collect garbage)
+;;   - 24: max size of stack (can be changed with resize)
+;;   - 28: max size of each young gen half (can be changed with resize)
+;;   - 32: address of the top stack frame (0 if no frame was pushed)
+;;   - 36: top of the mark stack (only during gc_full)
+;;   - 40: max size of old gen (can be changed with resize)
+;;   - 44-63: reserved
+;; - stack (partial one for things with pointers or dynamically-sized objects);
+;;   each frame starts with a link word, which is the address of the previous frame (or 0)
 ;; - young gen heap, x2 active and GC-target
-;; - old gen heap
+;; - old gen heap, for objects that survived a few young collections (mutable or not); old
+;;   objects can point to young ones (if they were changed after promotion, or promoted before
+;;   what they reference), so young collections treat them as roots, but only scan cards
+;;   (blocks of old memory) that were written through write_pointer or still point to young data
+;; - card table, two words per card of the old gen: a dirty flag, and the address of the first
+;;   object that overlaps the card (or 0), so a dirty card can be scanned without walking from the start
+;; - scratch space for gc_full (the mark stack, then the break table), sized for the worst case
+;;   of the young half and old gen, so that the old gen can be any size
 ;;
 ;; Metadata:
 ;; - pointer cnt
 ;; - data word size
-;; - is array? length?
+;;   (if together over 255, there is an extra word before the metadata, which is
+;;    0x80 followed by the pointer cnt, and data size is 2 bytes; a flag tells the size)
+;; - is array? (length is pointer cnt or data word size)
 ;; - only for heap, not stack:
 ;;   - are the pointers mutable
 ;;   - reachable in current GC
-;;   - generation count
-;;   - is redirect in current GC
+;;   - GC age, the number of young collections survived (up to 7)
+;;   - during GC, the metadata word of a moved object is replaced by its new address
+;;     with the lowest two bits set (a forward), which no type byte has
 ;; Some of this is per-type instead of per-object, but might still be efficient to duplicate
+;;
+;; Errors are reported through $log_err_code right before trapping, with the codes from
+;; src/err_code.rs (the host shows the message), and codes from 100 up for failed test checks:
+;; 1 out of memory, 4 stack pop grows, 5 negative frame, 6 stack overflow, 7 too many pointers,
+;; 8 too much data, 11 bad metadata type, 12 mutable without pointers, 13 not an array,
+;; 14 alloc_gc_enable without alloc_gc_disable, 15 GC scratch space too small, 16 pointer to unmarked object
 
 (module
     (import "host" "log_i32" (func $log_i32 (param i32)))
-    (import "host" "log_i32x7" (func $log_i32x7 (param i32) (param i32) (param i32) (param i32) (param i32) (param i32) (param i32)))
     (import "host" "log_nl" (func $log_nl))
+    ;; the host decodes and prints the stack and heaps, which can help when debugging tests
+    (import "host" "log_heap" (func $log_heap))
+    ;; test checks, which report the values involved and trap if they fail; the first
+    ;; parameter is the check number (from 100 up, like the codes passed to $log_err_code)
+    (import "host" "assert_eq_i32" (func $assert_eq_i32 (param $code i32) (param $expected i32) (param $actual i32)))
+    (import "host" "assert_ne_i32" (func $assert_ne_i32 (param $code i32) (param $unexpected i32) (param $actual i32)))
+    (import "host" "assert_ptr_in_region" (func $assert_ptr_in_region (param $code i32) (param $pointer i32) (param $region i32)))
     (import "host" "log_err_code" (func $log_err_code (param i32)))
-    (memory 3 3)  ;; 2x 64k
+    (memory (export "memory") 7)  ;; 7x 64k, grows when regions are resized
     (func $alloc_init
         (i32.store (call $addr_stack_length) (i32.const 0))
         (i32.store (call $addr_young_side) (i32.const 0))
         (i32.store (call $addr_young_length) (i32.const 0))
-        (i32.store (call $addr_old_length) (i32.const 0)))
+        (i32.store (call $addr_old_length) (i32.const 0))
+        (i32.store (call $addr_gc_disabled) (i32.const 0))
+        (i32.store (call $addr_stack_top_frame) (i32.const 0))
+        (i32.store (call $addr_stack_max_size) (call $const_default_stack_max_size))
+        (i32.store (call $addr_young_side_max_size) (call $const_default_young_side_max_size))
+        (i32.store (call $addr_old_max_size) (call $const_default_old_max_size)))
     (start $alloc_init)
 
     ;; these are addresses (in bytes) but sizes at the addresses are in words
     (func $addr_stack_length (result i32) i32.const 4)
     (func $addr_young_side (result i32) i32.const 8)
     (func $addr_young_length (result i32) i32.const 12)
-    (func $addr_old_length (result i32) i32.const 12)
+    (func $addr_old_length (result i32) i32.const 16)
+    (func $addr_gc_disabled (result i32) i32.const 20)
+    (func $addr_stack_max_size (result i32) i32.const 24)
+    (func $addr_young_side_max_size (result i32) i32.const 28)
+    (func $addr_stack_top_frame (result i32) i32.const 32)
+    (func $addr_mark_stack_top (result i32) i32.const 36)
+    (func $addr_old_max_size (result i32) i32.const 40)
 
     ;; max size is in words
-    (func $const_stack_max_size (result i32) i32.const 1024)
-    (func $const_young_side_max_size (result i32) i32.const 16384)
-    (func $const_old_heap_max_size (result i32) i32.const 0)
+    (func $const_default_stack_max_size (result i32) i32.const 1024)
+    (func $const_default_young_side_max_size (result i32) i32.const 16384)
+    (func $const_default_old_max_size (result i32) i32.const 24576)
+    ;; young collections survived before an object is moved to the old gen
+    (func $const_tenure_gc_age (result i32) i32.const 3)
+    ;; words of old gen per card table entry (the same as gc.rs)
+    (func $const_card_size (result i32) i32.const 32)
+    (func $stack_max_size (result i32) (i32.load (call $addr_stack_max_size)))
+    (func $young_side_max_size (result i32) (i32.load (call $addr_young_side_max_size)))
+    (func $old_max_size (result i32) (i32.load (call $addr_old_max_size)))
+
+    ;; region ids for $assert_ptr_in_region
+    (func $region_stack (result i32) i32.const 0)
+    (func $region_young (result i32) i32.const 1)
+    (func $region_old (result i32) i32.const 2)
+
+    ;; metadata encoding, see the layout at the top (src/heap_inspect.rs decodes the same)
+    (func $type_struct (result i32) i32.const 1)
+    (func $type_array (result i32) i32.const 2)
+    (func $big_metadata_first_byte (result i32) i32.const 0x80)
+    (func $flag_gc_mark (result i32) i32.const 1)
+    (func $flag_pointers_mutable (result i32) i32.const 2)
+    (func $flag_big_metadata (result i32) i32.const 4)
+    (func $gc_age_offset_bits (result i32) i32.const 3)
+
+    (func $glob_stack_start_addr (result i32) i32.const 64)
+    (func $glob_young_start_addr (result i32)
+        (call $young_side_start_addr
+            (call $stack_max_size)
+            (call $young_side_max_size)
+            (i32.load (call $addr_young_side)))
+    )
 
-    (func $glob_stack_start_addr (result i32) i32.const 20)
-    (func $glob_young_start_addr (result i32) (local $res i32)
-        ;; start of stack + length of stack + currently used stack space
+    (func $young_side_start_addr
+            (param $stack_max_size i32)
+            (param $young_side_max_size i32)
+            (param $side i32)
+            (result i32)
+            (local $res i32)
+        ;; start of stack + max size of stack
 
         (local.set $res (i32.add
             (call $glob_stack_start_addr)
-            (i32.mul (i32.const 4) (call $const_stack_max_size))))
+            (i32.mul (i32.const 4) (local.get $stack_max_size))))
 
-        (if (i32.ne (i32.load (call $addr_young_side)) (i32.const 0)) (then
+        (if (i32.ne (local.get $side) (i32.const 0)) (then
             ;; when using 'other half' of young, add one half's size
             (local.set $res (i32.add
-                (i32.mul (i32.const 4) (call $const_young_side_max_size))
+                (i32.mul (i32.const 4) (local.get $young_side_max_size))
                 (local.get $res)))
         ))
         local.get $res
     )
 
+    ;; the old gen comes after both young halves
+    (func $old_start_addr
+            (param $stack_max_size i32)
+            (param $young_side_max_size i32)
+            (result i32)
+        (i32.add
+            (call $young_side_start_addr (local.get $stack_max_size) (local.get $young_side_max_size) (i32.const 1))
+            (i32.mul (i32.const 4) (local.get $young_side_max_size)))
+    )
+
+    (func $glob_old_start_addr (result i32)
+        (call $old_start_addr (call $stack_max_size) (call $young_side_max_size))
+    )
+
+    ;; the card table comes after the old gen
+    (func $card_table_start_addr
+            (param $stack_max_size i32)
+            (param $young_side_max_size i32)
+            (param $old_max_size i32)
+            (result i32)
+        (i32.add
+            (call $old_start_addr (local.get $stack_max_size) (local.get $young_side_max_size))
+            (i32.mul (i32.const 4) (local.get $old_max_size)))
+    )
+
+    (func $glob_card_table_start_addr (result i32)
+        (call $card_table_start_addr (call $stack_max_size) (call $young_side_max_size) (call $old_max_size))
+    )
+
+    ;; number of cards, the last of which may be partial
+    (func $card_cnt
+            (param $old_max_size i32)
+            (result i32)
+        (i32.div_u
+            (i32.add (local.get $old_max_size) (i32.sub (call $const_card_size) (i32.const 1)))
+            (call $const_card_size))
+    )
+
+    ;; the gc_full scratch space comes after the card table
+    (func $scratch_start_addr
+            (param $stack_max_size i32)
+            (param $young_side_max_size i32)
+            (param $old_max_size i32)
+            (result i32)
+        (i32.add
+            (call $card_table_start_addr (local.get $stack_max_size) (local.get $young_side_max_size) (local.get $old_max_size))
+            (i32.mul (i32.const 8) (call $card_cnt (local.get $old_max_size))))
+    )
+
+    (func $glob_scratch_start_addr (result i32)
+        (call $scratch_start_addr (call $stack_max_size) (call $young_side_max_size) (call $old_max_size))
+    )
+
+    ;; words of scratch space that gc_full needs in the worst case: the mark stack has a word for each
+    ;; reachable young or old object with pointers (which is at least two words), and the break table
+    ;; has two words for each live old object (which is at least one word)
+    (func $scratch_size
+            (param $young_side_max_size i32)
+            (param $old_max_size i32)
+            (result i32)
+            (local $mark_stack i32)
+            (local $break_table i32)
+        (local.set $mark_stack (i32.shr_u
+            (i32.add (i32.add (local.get $young_side_max_size) (local.get $old_max_size)) (i32.const 1))
+            (i32.const 1)))
+        (local.set $break_table (i32.mul (i32.const 2) (local.get $old_max_size)))
+        (select (local.get $mark_stack) (local.get $break_table)
+            (i32.gt_u (local.get $mark_stack) (local.get $break_table)))
+    )
+
     ;; default alloc, traps when OOM
     (func $alloc (export "alloc")
             (param $pointer_cnt i32)
@@ -105,10 +251,48 @@
             (param $data_size_32 i32)  ;; units are 32-bit words
             (param $pointers_mutable i32)
             (result i32)  ;; addr
+        (call $alloc0_typed (call $type_struct) (local.get $pointer_cnt) (local.get $data_size_32) (local.get $pointers_mutable))
+    )
+
+    ;; like $alloc0_array, but traps when OOM
+    (func $alloc_array (export "alloc_array")
+            (param $elem_cnt i32)
+            (param $elems_are_pointers i32)
+            (param $pointers_mutable i32)
+            (result i32)  ;; addr
+            (local $res i32)
+        (local.set $res (call $alloc0_array (local.get $elem_cnt) (local.get $elems_are_pointers) (local.get $pointers_mutable)))
+        (if (i32.eq (local.get $res) (i32.const 0)) (then
+            (call $log_err_code (i32.const 1))
+            unreachable
+        ))
+        local.get $res
+    )
+
+    ;; array of either only pointers or only data words, returns 0 when OOM;
+    ;; element count is stored as the pointer count or data size respectively
+    (func $alloc0_array (export "alloc0_array")
+            (param $elem_cnt i32)
+            (param $elems_are_pointers i32)
+            (param $pointers_mutable i32)
+            (result i32)  ;; addr
+        (if (i32.ne (local.get $elems_are_pointers) (i32.const 0)) (then
+            (return (call $alloc0_typed (call $type_array) (local.get $elem_cnt) (i32.const 0) (local.get $pointers_mutable)))
+        ))
+        (call $alloc0_typed (call $type_array) (i32.const 0) (local.get $elem_cnt) (local.get $pointers_mutable))
+    )
+
+    (func $alloc0_typed
+            (param $type i32)  ;; see $read_metadata_type
+            (param $pointer_cnt i32)
+            (param $data_size_32 i32)  ;; units are 32-bit words
+            (param $pointers_mutable i32)
+            (result i32)  ;; addr
             (local $alloc_size i32)
             (local $orig_young_length i32)
             (local $new_young_length i32)
             (local $orig_offset_addr i32)
+            (local $meta_size i32)
 
         ;; debug only?
         (if (i32.ne (local.get $pointers_mutable) (i32.const 0)) (then
@@ -118,17 +302,26 @@
         ))))
 
         ;; calculate the necessary size (words) including metadata
-        (local.set $alloc_size (i32.add (i32.const 1) (i32.add (local.get $pointer_cnt) (local.get $data_size_32))))
-        ;;TODO @mark: for now assume metadata is 1 word ^
+        (local.set $meta_size (call $metadata_size (local.get $pointer_cnt) (local.get $data_size_32)))
+        (local.set $alloc_size (i32.add (local.get $meta_size) (i32.add (local.get $pointer_cnt) (local.get $data_size_32))))
+
+        ;; check if enough memory, otherwise collect garbage (unless disabled) and try again,
+        ;; first only young, then everything; only report OOM if neither helps
+        (if (i32.eqz (call $young_has_room (local.get $alloc_size))) (then
+            (if (i32.ne (i32.load (call $addr_gc_disabled)) (i32.const 0)) (then
+                (return (i32.const 0)) ))
+            (call $gc_fast)
+            (if (i32.eqz (call $young_has_room (local.get $alloc_size))) (then
+                (call $gc_full)
+                (if (i32.eqz (call $young_has_room (local.get $alloc_size))) (then
+                    (return (i32.const 0)) ))
+            ))
+        ))
 
         ;; calculate new young heap size (but don't update yet)
         (local.set $orig_young_length (i32.load (call $addr_young_length)))
         (local.set $new_young_length (i32.add (local.get $orig_young_length) (local.get $alloc_size)))
 
-        ;; check if enough memory
-        (if (i32.gt_u (local.get $new_young_length) (call $const_young_side_max_size)) (then
-            (return (i32.const 0)) ))
-
         ;; find current top of young heap addr
         (local.set $orig_offset_addr (i32.add
             (call $glob_young_start_addr)
@@ -137,6 +330,7 @@
         ;; write metadata
         (call $write_metadata_heap
                 (local.get $orig_offset_addr)
+                (local.get $type)
                 (local.get $pointer_cnt)
                 (local.get $data_size_32)
                 (local.get $pointers_mutable))
@@ -145,38 +339,84 @@
         (i32.store (call $addr_young_length) (local.get $new_young_length))
 
         ;; return data address, which is after metadata
-        (return (i32.add (local.get $orig_offset_addr) (i32.const 4)))
+        (return (i32.add (local.get $orig_offset_addr) (i32.mul (i32.const 4) (local.get $meta_size))))
+    )
+
+    (func $young_has_room
+            (param $alloc_size i32)  ;; words, including metadata
+            (result i32)
+        (i32.le_u
+            (i32.add (i32.load (call $addr_young_length)) (local.get $alloc_size))
+            (call $young_side_max_size))
+    )
+
+    ;; stop allocation from collecting garbage, e.g. while some roots are not on the stack;
+    ;; calls can be nested, and each must be followed by alloc_gc_enable
+    (func $alloc_gc_disable (export "alloc_gc_disable")
+        (i32.store (call $addr_gc_disabled) (i32.add (i32.load (call $addr_gc_disabled)) (i32.const 1)))
+    )
+
+    (func $alloc_gc_enable (export "alloc_gc_enable")
+        (if (i32.eqz (i32.load (call $addr_gc_disabled))) (then
+            (call $log_err_code (i32.const 14)) unreachable ))
+        (i32.store (call $addr_gc_disabled) (i32.sub (i32.load (call $addr_gc_disabled)) (i32.const 1)))
+    )
+
+    ;; store a pointer in a heap object field; pointers in old objects must be written through this,
+    ;; so that the card is marked, otherwise gc_fast may not see the reference (stack and young
+    ;; objects can be written directly, but objects may become old during any allocation)
+    (func $write_pointer (export "write_pointer")
+            (param $field_addr i32)
+            (param $pointer i32)
+        (i32.store (local.get $field_addr) (local.get $pointer))
+        (if (i32.and
+                (i32.ge_u (local.get $field_addr) (call $glob_old_start_addr))
+                (i32.lt_u (local.get $field_addr) (call $old_end_addr))) (then
+            (i32.store (call $card_entry_addr (call $card_ix_of (local.get $field_addr))) (i32.const 1))
+        ))
     )
 
     ;; start a stack frame; can allocate with stack_alloc,
     ;; but only if doesn't live past stack_pop_to.
-    ;; Returns $frame_ix (in words) to pass to stack_pop_to.
+    ;; Returns the frame address to pass to stack_pop_to.
     (func $stack_push (export "stack_push")
             (result i32)
-        (i32.load (call $addr_young_side))
+            (local $frame i32)
+        (if (i32.ge_u (call $get_stack_size) (call $stack_max_size)) (then
+            (call $log_err_code (i32.const 6))
+            unreachable
+        ))
+
+        ;; the first word of the frame links to the previous frame
+        (local.set $frame (call $stack_end_addr))
+        (i32.store (local.get $frame) (i32.load (call $addr_stack_top_frame)))
+        (i32.store (call $addr_stack_top_frame) (local.get $frame))
+        (i32.store (call $addr_stack_length) (i32.add (call $get_stack_size) (i32.const 1)))
+        local.get $frame
     )
 
-    ;; drop stack frame started with stack_alloc; assumes all dropped
-    ;; memory is unreferenced. Must provide the ix returned by stack_push.
+    ;; drop stack frame started with stack_push, and any frames pushed after it; assumes
+    ;; all dropped memory is unreferenced. Must provide the frame returned by stack_push.
     (func $stack_pop_to (export "stack_pop")
-            (param $frame_ix i32)
-            (local $orig_size i32)
-        (local.set $orig_size (i32.load (call $addr_young_side)))
+            (param $frame i32)
         ;;TODO: should such safeties be disabled in production mode?
-        (if (i32.gt_u (local.get $frame_ix) (local.get $orig_size)) (then
+        (if (i32.ge_u (local.get $frame) (call $stack_end_addr)) (then
             ;; this must only shrink the stack, not grow
             (call $log_err_code (i32.const 4))
             unreachable
         ))
-        (if (i32.lt_s (local.get $frame_ix) (i32.const 0)) (then
+        (if (i32.lt_u (local.get $frame) (call $glob_stack_start_addr)) (then
             (call $log_err_code (i32.const 5))
             unreachable
         ))
-        (i32.store (call $addr_stack_length) (local.get $frame_ix))
+        (i32.store (call $addr_stack_top_frame) (i32.load (local.get $frame)))
+        (i32.store (call $addr_stack_length) (i32.shr_u
+            (i32.sub (local.get $frame) (call $glob_stack_start_addr))
+            (i32.const 2)))
     )
 
     ;; like $alloc_stack0, but traps when OOM
-    (func $alloc_stack (export "$alloc_stack")
+    (func $alloc_stack (export "stack_alloc")
             (param $pointer_cnt i32)
             (param $data_size_32 i32)  ;; units are 32-bit words
             (result i32)  ;; addr
@@ -201,18 +441,19 @@
             (local $orig_stack_length i32)
             (local $new_stack_length i32)
             (local $orig_offset_addr i32)
+            (local $meta_size i32)
         ;;TODO @mark: this should mirror alloc0 except mutability
 
         ;; calculate the necessary size (words) including metadata
-        (local.set $alloc_size (i32.add (i32.const 1) (i32.add (local.get $pointer_cnt) (local.get $data_size_32))))
-        ;;TODO @mark: for now assume metadata is 1 word ^
+        (local.set $meta_size (call $metadata_size (local.get $pointer_cnt) (local.get $data_size_32)))
+        (local.set $alloc_size (i32.add (local.get $meta_size) (i32.add (local.get $pointer_cnt) (local.get $data_size_32))))
 
         ;; calculate new stack size (but don't update yet)
         (local.set $orig_stack_length (i32.load (call $addr_stack_length)))
         (local.set $new_stack_length (i32.add (local.get $orig_stack_length) (local.get $alloc_size)))
 
         ;; check if enough memory
-        (if (i32.gt_u (local.get $new_stack_length) (call $const_stack_max_size)) (then
+        (if (i32.gt_u (local.get $new_stack_length) (call $stack_max_size)) (then
             (return (i32.const 0)) ))
 
         ;; find current top of young heap addr
@@ -231,62 +472,781 @@
         (i32.store (call $addr_stack_length) (local.get $new_stack_length))
 
         ;; return data address, which is after metadata
-        (return (i32.add (local.get $orig_offset_addr) (i32.const 4)))
+        (return (i32.add (local.get $orig_offset_addr) (i32.mul (i32.const 4) (local.get $meta_size))))
+    )
+
+    ;; change the max sizes (in words) of the stack, young halves and old gen, growing memory if needed
+    ;; (also for the gc_full scratch space); returns 0 (and changes nothing) if the current data does
+    ;; not fit, 1 otherwise
+    (func $resize (export "resize")
+            (param $new_stack_max_size i32)
+            (param $new_young_side_max_size i32)
+            (param $new_old_max_size i32)
+            (result i32)
+            (local $side i32)
+            (local $young_length i32)
+            (local $old_length i32)
+            (local $prev_young_start i32)
+            (local $new_young_start i32)
+            (local $prev_old_start i32)
+            (local $new_old_start i32)
+            (local $memory_bytes i32)
+            (local $memory_pages i32)
+
+        ;; collect both generations first (gc_full includes a young collection), so that as
+        ;; little as possible has to fit
+        (if (i32.eqz (i32.load (call $addr_gc_disabled))) (then
+            (call $gc_full) ))
+
+        (local.set $side (i32.load (call $addr_young_side)))
+        (local.set $young_length (i32.load (call $addr_young_length)))
+        (local.set $old_length (i32.load (call $addr_old_length)))
+        (if (i32.gt_u (call $get_stack_size) (local.get $new_stack_max_size)) (then
+            (return (i32.const 0)) ))
+        (if (i32.gt_u (local.get $young_length) (local.get $new_young_side_max_size)) (then
+            (return (i32.const 0)) ))
+        (if (i32.gt_u (local.get $old_length) (local.get $new_old_max_size)) (then
+            (return (i32.const 0)) ))
+
+        ;; grow memory (it cannot shrink)
+        (local.set $memory_bytes (i32.add
+            (call $scratch_start_addr (local.get $new_stack_max_size) (local.get $new_young_side_max_size) (local.get $new_old_max_size))
+            (i32.mul (i32.const 4) (call $scratch_size (local.get $new_young_side_max_size) (local.get $new_old_max_size)))))
+        (local.set $memory_pages (i32.div_u (i32.add (local.get $memory_bytes) (i32.const 65535)) (i32.const 65536)))
+        (if (i32.gt_u (local.get $memory_pages) (memory.size)) (then
+            (if (i32.eq (memory.grow (i32.sub (local.get $memory_pages) (memory.size))) (i32.const -1)) (then
+                (return (i32.const 0)) ))
+        ))
+
+        ;; move the active young half and the old gen (the stack stays in place); the new regions do
+        ;; not overlap each other, so when growing move the old gen first, otherwise the young half
+        (local.set $prev_young_start (call $glob_young_start_addr))
+        (local.set $new_young_start (call $young_side_start_addr
+            (local.get $new_stack_max_size)
+            (local.get $new_young_side_max_size)
+            (local.get $side)))
+        (local.set $prev_old_start (call $glob_old_start_addr))
+        (local.set $new_old_start (call $old_start_addr
+            (local.get $new_stack_max_size)
+            (local.get $new_young_side_max_size)))
+        (if (i32.gt_u (local.get $new_old_start) (local.get $prev_old_start)) (then
+            (memory.copy
+                (local.get $new_old_start)
+                (local.get $prev_old_start)
+                (i32.mul (i32.const 4) (local.get $old_length)))
+        ))
+        (memory.copy
+            (local.get $new_young_start)
+            (local.get $prev_young_start)
+            (i32.mul (i32.const 4) (local.get $young_length)))
+        (if (i32.le_u (local.get $new_old_start) (local.get $prev_old_start)) (then
+            (memory.copy
+                (local.get $new_old_start)
+                (local.get $prev_old_start)
+                (i32.mul (i32.const 4) (local.get $old_length)))
+        ))
+
+        ;; update pointers into either moved region
+        (call $relocate_pointers_in_stack
+            (local.get $prev_young_start) (local.get $young_length) (local.get $new_young_start)
+            (local.get $prev_old_start) (local.get $old_length) (local.get $new_old_start))
+        (call $relocate_pointers_in
+            (local.get $new_young_start)
+            (i32.add (local.get $new_young_start) (i32.mul (i32.const 4) (local.get $young_length)))
+            (local.get $prev_young_start) (local.get $young_length) (local.get $new_young_start)
+            (local.get $prev_old_start) (local.get $old_length) (local.get $new_old_start))
+        (call $relocate_pointers_in
+            (local.get $new_old_start)
+            (i32.add (local.get $new_old_start) (i32.mul (i32.const 4) (local.get $old_length)))
+            (local.get $prev_young_start) (local.get $young_length) (local.get $new_young_start)
+            (local.get $prev_old_start) (local.get $old_length) (local.get $new_old_start))
+
+        (i32.store (call $addr_stack_max_size) (local.get $new_stack_max_size))
+        (i32.store (call $addr_young_side_max_size) (local.get $new_young_side_max_size))
+        (i32.store (call $addr_old_max_size) (local.get $new_old_max_size))
+
+        ;; the card table moved (and has a different size), and old objects moved to other cards
+        (call $rebuild_card_table)
+        i32.const 1
+    )
+
+    ;; like $relocate_pointers_in, for every frame of the stack (skipping the link words)
+    (func $relocate_pointers_in_stack
+            (param $prev_young_start i32)
+            (param $young_length i32)
+            (param $new_young_start i32)
+            (param $prev_old_start i32)
+            (param $old_length i32)
+            (param $new_old_start i32)
+            (local $frame i32)
+            (local $after i32)
+        (local.set $frame (i32.load (call $addr_stack_top_frame)))
+        (local.set $after (call $stack_end_addr))
+        (block $done (loop $next_frame
+            (br_if $done (i32.eqz (local.get $frame)))
+            (call $relocate_pointers_in
+                (i32.add (local.get $frame) (i32.const 4))
+                (local.get $after)
+                (local.get $prev_young_start) (local.get $young_length) (local.get $new_young_start)
+                (local.get $prev_old_start) (local.get $old_length) (local.get $new_old_start))
+            (local.set $after (local.get $frame))
+            (local.set $frame (i32.load (local.get $frame)))
+            (br $next_frame)
+        ))
+        ;; objects allocated before the first frame
+        (call $relocate_pointers_in
+            (call $glob_stack_start_addr)
+            (local.get $after)
+            (local.get $prev_young_start) (local.get $young_length) (local.get $new_young_start)
+            (local.get $prev_old_start) (local.get $old_length) (local.get $new_old_start))
+    )
+
+    ;; walk the objects between $from and $to, and update pointer fields that point into the young
+    ;; half or old gen (each $length words at $prev_start), to the same offset from $new_start;
+    ;; both are checked against the original pointer, so a moved pointer is never moved twice
+    (func $relocate_pointers_in
+            (param $from i32)
+            (param $to i32)
+            (param $prev_young_start i32)
+            (param $young_length i32)
+            (param $new_young_start i32)
+            (param $prev_old_start i32)
+            (param $old_length i32)
+            (param $new_old_start i32)
+            (local $header_size i32)
+            (local $meta_addr i32)
+            (local $field_addr i32)
+            (local $fields_end i32)
+            (local $pointer i32)
+            (local $moved i32)
+        (block $done (loop $next_object
+            (br_if $done (i32.ge_u (local.get $from) (local.get $to)))
+            (local.set $header_size (call $read_header_size (local.get $from)))
+            (local.set $meta_addr (i32.add (local.get $from) (i32.mul (i32.const 4) (i32.sub (local.get $header_size) (i32.const 1)))))
+            (local.set $field_addr (i32.add (local.get $meta_addr) (i32.const 4)))
+            (local.set $fields_end (i32.add (local.get $field_addr)
+                (i32.mul (i32.const 4) (call $read_metadata_pointer_cnt (local.get $meta_addr)))))
+            (block $fields_done (loop $next_field
+                (br_if $fields_done (i32.ge_u (local.get $field_addr) (local.get $fields_end)))
+                (local.set $pointer (i32.load (local.get $field_addr)))
+                (local.set $moved (call $relocate_pointer (local.get $pointer)
+                    (local.get $prev_young_start) (local.get $young_length) (local.get $new_young_start)))
+                (if (i32.eq (local.get $moved) (local.get $pointer)) (then
+                    (local.set $moved (call $relocate_pointer (local.get $pointer)
+                        (local.get $prev_old_start) (local.get $old_length) (local.get $new_old_start)))
+                ))
+                (i32.store (local.get $field_addr) (local.get $moved))
+                (local.set $field_addr (i32.add (local.get $field_addr) (i32.const 4)))
+                (br $next_field)
+            ))
+            (local.set $from (i32.add (local.get $fields_end)
+                (i32.mul (i32.const 4) (call $read_metadata_data_word_cnt (local.get $meta_addr)))))
+            (br $next_object)
+        ))
+    )
+
+    ;; if $pointer points into the $length words at $prev_start, the same offset from $new_start
+    (func $relocate_pointer
+            (param $pointer i32)
+            (param $prev_start i32)
+            (param $length i32)
+            (param $new_start i32)
+            (result i32)
+        ;; pointers point after the metadata, so can equal the end but not the start
+        (if (i32.and
+                (i32.gt_u (local.get $pointer) (local.get $prev_start))
+                (i32.le_u (local.get $pointer) (i32.add (local.get $prev_start) (i32.mul (i32.const 4) (local.get $length))))) (then
+            (return (i32.add (local.get $new_start) (i32.sub (local.get $pointer) (local.get $prev_start))))
+        ))
+        local.get $pointer
     )
 
-    ;; do a small GC, e.g. young generation only
+    ;; collectors walk the stack and heap with one of these handlers for every pointer field,
+    ;; called with the field address and two handler-specific values
+    (type $field_handler (func (param i32) (param i32) (param i32)))
+    (table 3 funcref)
+    (elem (i32.const 0) $gc_fast_handle_pointer $gc_full_mark_pointer $gc_full_update_pointer)
+    (func $handler_gc_fast (result i32) i32.const 0)
+    (func $handler_gc_full_mark (result i32) i32.const 1)
+    (func $handler_gc_full_update (result i32) i32.const 2)
+
+    ;; call $handler for every pointer field of every object on the stack (skipping the link words)
+    (func $walk_stack_fields
+            (param $handler i32)
+            (param $a i32)
+            (param $b i32)
+            (local $frame i32)
+            (local $after i32)
+            (local $header_addr i32)
+        (local.set $frame (i32.load (call $addr_stack_top_frame)))
+        (local.set $after (call $stack_end_addr))
+        (block $done (loop $next_frame
+            ;; objects allocated before the first frame are included
+            (local.set $header_addr (call $glob_stack_start_addr))
+            (if (i32.ne (local.get $frame) (i32.const 0)) (then
+                (local.set $header_addr (i32.add (local.get $frame) (i32.const 4))) ))
+            (block $frame_done (loop $next_object
+                (br_if $frame_done (i32.ge_u (local.get $header_addr) (local.get $after)))
+                (local.set $header_addr (call $walk_object_fields
+                    (local.get $header_addr) (local.get $handler) (local.get $a) (local.get $b)))
+                (br $next_object)
+            ))
+            (br_if $done (i32.eqz (local.get $frame)))
+            (local.set $after (local.get $frame))
+            (local.set $frame (i32.load (local.get $frame)))
+            (br $next_frame)
+        ))
+    )
+
+    ;; call $handler for every pointer field of the object starting at $header_addr (stack or heap),
+    ;; returns the address of the next object
+    (func $walk_object_fields
+            (param $header_addr i32)
+            (param $handler i32)
+            (param $a i32)
+            (param $b i32)
+            (result i32)
+            (local $meta_addr i32)
+            (local $field_addr i32)
+            (local $fields_end i32)
+        (local.set $meta_addr (call $meta_addr_of_header (local.get $header_addr)))
+        (local.set $field_addr (i32.add (local.get $meta_addr) (i32.const 4)))
+        (local.set $fields_end (i32.add (local.get $field_addr)
+            (i32.mul (i32.const 4) (call $read_metadata_pointer_cnt (local.get $meta_addr)))))
+        (block $done (loop $next_field
+            (br_if $done (i32.ge_u (local.get $field_addr) (local.get $fields_end)))
+            (call_indirect (type $field_handler)
+                (local.get $field_addr) (local.get $a) (local.get $b) (local.get $handler))
+            (local.set $field_addr (i32.add (local.get $field_addr) (i32.const 4)))
+            (br $next_field)
+        ))
+        (i32.add (local.get $fields_end)
+            (i32.mul (i32.const 4) (call $read_metadata_data_word_cnt (local.get $meta_addr))))
+    )
+
+    ;; the card table entry of a card, which is a dirty flag, followed by the first object that
+    ;; overlaps the card (see the layout at the top)
+    (func $card_entry_addr
+            (param $card_ix i32)
+            (result i32)
+        (i32.add (call $glob_card_table_start_addr) (i32.shl (local.get $card_ix) (i32.const 3)))
+    )
+
+    ;; the card that an address in the old gen is on
+    (func $card_ix_of
+            (param $addr i32)
+            (result i32)
+        (i32.div_u
+            (i32.sub (local.get $addr) (call $glob_old_start_addr))
+            (i32.mul (i32.const 4) (call $const_card_size)))
+    )
+
+    (func $card_start_addr
+            (param $card_ix i32)
+            (result i32)
+        (i32.add (call $glob_old_start_addr) (i32.mul (local.get $card_ix) (i32.mul (i32.const 4) (call $const_card_size))))
+    )
+
+    ;; remember the old object between $header_addr and $end as the first object of every card
+    ;; it overlaps, unless an earlier object was remembered
+    (func $record_old_object
+            (param $header_addr i32)
+            (param $end i32)
+            (local $card_ix i32)
+            (local $last_card_ix i32)
+        (local.set $card_ix (call $card_ix_of (local.get $header_addr)))
+        (local.set $last_card_ix (call $card_ix_of (i32.sub (local.get $end) (i32.const 4))))
+        (block $done (loop $next_card
+            (br_if $done (i32.gt_u (local.get $card_ix) (local.get $last_card_ix)))
+            (if (i32.eqz (i32.load offset=4 (call $card_entry_addr (local.get $card_ix)))) (then
+                (i32.store offset=4 (call $card_entry_addr (local.get $card_ix)) (local.get $header_addr)) ))
+            (local.set $card_ix (i32.add (local.get $card_ix) (i32.const 1)))
+            (br $next_card)
+        ))
+    )
+
+    ;; clear the card table, then record all old objects, and mark cards that point to young data
+    (func $rebuild_card_table
+            (local $header_addr i32)
+            (local $meta_addr i32)
+            (local $field_addr i32)
+            (local $fields_end i32)
+        (memory.fill (call $glob_card_table_start_addr) (i32.const 0)
+            (i32.mul (i32.const 8) (call $card_cnt (call $old_max_size))))
+        (local.set $header_addr (call $glob_old_start_addr))
+        (block $done (loop $next_object
+            (br_if $done (i32.ge_u (local.get $header_addr) (call $old_end_addr)))
+            (call $record_old_object (local.get $header_addr) (call $next_object_addr (local.get $header_addr)))
+            (local.set $meta_addr (call $meta_addr_of_header (local.get $header_addr)))
+            (local.set $field_addr (i32.add (local.get $meta_addr) (i32.const 4)))
+            (local.set $fields_end (i32.add (local.get $field_addr)
+                (i32.mul (i32.const 4) (call $read_metadata_pointer_cnt (local.get $meta_addr)))))
+            (block $fields_done (loop $next_field
+                (br_if $fields_done (i32.ge_u (local.get $field_addr) (local.get $fields_end)))
+                (if (call $is_in_region (i32.load (local.get $field_addr)) (call $glob_young_start_addr) (call $young_end_addr)) (then
+                    (i32.store (call $card_entry_addr (call $card_ix_of (local.get $field_addr))) (i32.const 1)) ))
+                (local.set $field_addr (i32.add (local.get $field_addr) (i32.const 4)))
+                (br $next_field)
+            ))
+            (local.set $header_addr (call $next_object_addr (local.get $header_addr)))
+            (br $next_object)
+        ))
+    )
+
+    ;; like $gc_fast_handle_pointer for the pointer fields of the old object at $header_addr that are
+    ;; between $from and $to, and mark the card of any that still points to young data afterwards;
+    ;; returns the address of the next object
+    (func $gc_fast_scan_old_fields
+            (param $header_addr i32)
+            (param $from i32)
+            (param $to i32)
+            (param $from_start i32)
+            (param $from_end i32)
+            (result i32)
+            (local $meta_addr i32)
+            (local $field_addr i32)
+            (local $fields_end i32)
+            (local $next_addr i32)
+        (local.set $meta_addr (call $meta_addr_of_header (local.get $header_addr)))
+        (local.set $field_addr (i32.add (local.get $meta_addr) (i32.const 4)))
+        (local.set $fields_end (i32.add (local.get $field_addr)
+            (i32.mul (i32.const 4) (call $read_metadata_pointer_cnt (local.get $meta_addr)))))
+        (local.set $next_addr (i32.add (local.get $fields_end)
+            (i32.mul (i32.const 4) (call $read_metadata_data_word_cnt (local.get $meta_addr)))))
+        (if (i32.lt_u (local.get $field_addr) (local.get $from)) (then
+            (local.set $field_addr (local.get $from)) ))
+        (if (i32.gt_u (local.get $fields_end) (local.get $to)) (then
+            (local.set $fields_end (local.get $to)) ))
+        (block $done (loop $next_field
+            (br_if $done (i32.ge_u (local.get $field_addr) (local.get $fields_end)))
+            (call $gc_fast_handle_pointer (local.get $field_addr) (local.get $from_start) (local.get $from_end))
+            (if (call $is_in_region (i32.load (local.get $field_addr)) (call $glob_young_start_addr) (call $young_end_addr)) (then
+                (i32.store (call $card_entry_addr (call $card_ix_of (local.get $field_addr))) (i32.const 1)) ))
+            (local.set $field_addr (i32.add (local.get $field_addr) (i32.const 4)))
+            (br $next_field)
+        ))
+        local.get $next_addr
+    )
+
+    ;; do a small GC, e.g. young generation only: copy everything reachable from the stack
+    ;; or old gen to the other young half (Cheney-style), and make that the active half;
+    ;; objects old enough go to the old gen instead
     (func $gc_fast (export "gc_fast")
+            (local $from_start i32)
+            (local $from_end i32)
+            (local $old_end i32)
+            (local $card_ix i32)
+            (local $card_end i32)
+            (local $header_addr i32)
+            (local $young_scan i32)
+            (local $old_scan i32)
+
+        ;; flip sides first, so that copying is just bump allocation in the new active half
+        (local.set $from_start (call $glob_young_start_addr))
+        (local.set $from_end (call $young_end_addr))
+        (i32.store (call $addr_young_side) (i32.eqz (i32.load (call $addr_young_side))))
+        (i32.store (call $addr_young_length) (i32.const 0))
+
+        ;; the stack frames are the roots (objects promoted from here on are scanned below)
+        (local.set $old_end (call $old_end_addr))
+        (call $walk_stack_fields (call $handler_gc_fast) (local.get $from_start) (local.get $from_end))
+
+        ;; old objects are roots too, but only fields on dirty cards can point to young data; cards
+        ;; are cleaned, and marked again if they still point to young data after
+        (local.set $card_ix (i32.const 0))
+        (block $cards_done (loop $next_card
+            (br_if $cards_done (i32.ge_u (call $card_start_addr (local.get $card_ix)) (local.get $old_end)))
+            (if (i32.load (call $card_entry_addr (local.get $card_ix))) (then
+                (i32.store (call $card_entry_addr (local.get $card_ix)) (i32.const 0))
+                (local.set $card_end (call $card_start_addr (i32.add (local.get $card_ix) (i32.const 1))))
+                (local.set $header_addr (i32.load offset=4 (call $card_entry_addr (local.get $card_ix))))
+                (block $card_done (loop $next_object
+                    (br_if $card_done (i32.ge_u (local.get $header_addr) (local.get $card_end)))
+                    (br_if $card_done (i32.ge_u (local.get $header_addr) (local.get $old_end)))
+                    (local.set $header_addr (call $gc_fast_scan_old_fields (local.get $header_addr)
+                        (call $card_start_addr (local.get $card_ix)) (local.get $card_end)
+                        (local.get $from_start) (local.get $from_end)))
+                    (br $next_object)
+                ))
+            ))
+            (local.set $card_ix (i32.add (local.get $card_ix) (i32.const 1)))
+            (br $next_card)
+        ))
+
+        ;; scan the copied and promoted objects, which copies what they reference (so the ends keep
+        ;; moving); promoted objects are recorded in the card table, and their cards are marked if
+        ;; they still point to young data after
+        (local.set $young_scan (call $glob_young_start_addr))
+        (local.set $old_scan (local.get $old_end))
+        (block $done (loop $next_object
+            (if (i32.lt_u (local.get $young_scan) (call $young_end_addr)) (then
+                (local.set $young_scan (call $walk_object_fields
+                    (local.get $young_scan) (call $handler_gc_fast) (local.get $from_start) (local.get $from_end)))
+                (br $next_object)
+            ))
+            (br_if $done (i32.ge_u (local.get $old_scan) (call $old_end_addr)))
+            (call $record_old_object (local.get $old_scan) (call $next_object_addr (local.get $old_scan)))
+            (local.set $old_scan (call $gc_fast_scan_old_fields
+                (local.get $old_scan) (i32.const 0) (i32.const -1) (local.get $from_start) (local.get $from_end)))
+            (br $next_object)
+        ))
+    )
+
+    ;; if the pointer at $field_addr points into the inactive young half, copy the object to the
+    ;; end of the active half or old gen (unless done before), leave a forward, and update the pointer
+    (func $gc_fast_handle_pointer
+            (param $field_addr i32)
+            (param $from_start i32)
+            (param $from_end i32)
+            (local $pointer i32)
+            (local $meta_addr i32)
+            (local $meta i32)
+            (local $header_size i32)
+            (local $alloc_size i32)
+            (local $new_header_addr i32)
+            (local $new_pointer i32)
+
+        ;; pointers point after the metadata, so can equal the end but not the start
+        (local.set $pointer (i32.load (local.get $field_addr)))
+        (if (i32.or
+                (i32.le_u (local.get $pointer) (local.get $from_start))
+                (i32.gt_u (local.get $pointer) (local.get $from_end))) (then
+            (return) ))
+
+        ;; already copied, so only update the pointer
+        (local.set $meta_addr (i32.sub (local.get $pointer) (i32.const 4)))
+        (local.set $meta (i32.load (local.get $meta_addr)))
+        (if (i32.eq (i32.and (local.get $meta) (i32.const 3)) (i32.const 3)) (then
+            (i32.store (local.get $field_addr) (i32.xor (local.get $meta) (i32.const 3)))
+            (return) ))
+
+        ;; copy (including the age, which increases) to the old gen if old enough and there is room,
+        ;; otherwise to the end of the active young half
+        (call $increment_gc_age (local.get $meta_addr))
+        (local.set $header_size (i32.add (i32.const 1) (call $read_metadata_is_big (local.get $meta_addr))))
+        (local.set $alloc_size (i32.add (local.get $header_size) (i32.add
+            (call $read_metadata_pointer_cnt (local.get $meta_addr))
+            (call $read_metadata_data_word_cnt (local.get $meta_addr)))))
+        (if (i32.and
+                (i32.ge_u (call $read_metadata_gc_age (local.get $meta_addr)) (call $const_tenure_gc_age))
+                (i32.le_u
+                    (i32.add (call $get_old_size) (local.get $alloc_size))
+                    (call $old_max_size))) (then
+            (local.set $new_header_addr (call $old_end_addr))
+            (i32.store (call $addr_old_length) (i32.add (call $get_old_size) (local.get $alloc_size)))
+        ) (else
+            (local.set $new_header_addr (call $young_end_addr))
+            (i32.store (call $addr_young_length) (i32.add (call $get_young_size) (local.get $alloc_size)))
+        ))
+        (memory.copy
+            (local.get $new_header_addr)
+            (i32.sub (local.get $pointer) (i32.mul (i32.const 4) (local.get $header_size)))
+            (i32.mul (i32.const 4) (local.get $alloc_size)))
+
+        ;; leave a forward and update the pointer
+        (local.set $new_pointer (i32.add (local.get $new_header_addr) (i32.mul (i32.const 4) (local.get $header_size))))
+        (i32.store (local.get $meta_addr) (i32.or (local.get $new_pointer) (i32.const 3)))
+        (i32.store (local.get $field_addr) (local.get $new_pointer))
     )
 
-    ;; do a big GC, e.g. check all memory regions
+    ;; do a big GC, e.g. check all memory regions: mark everything reachable from the stack,
+    ;; slide live old objects down (mark-compact), then do a young collection. The scratch space
+    ;; after the card table holds the mark stack, and after that a break table of (old address,
+    ;; new address) pairs for live old objects; see $scratch_size for why both always fit.
     (func $gc_full (export "gc_full")
+            (local $scratch_start i32)
+            (local $scratch_end i32)
+            (local $top i32)
+            (local $table_end i32)
+            (local $header_addr i32)
+            (local $meta_addr i32)
+            (local $next_addr i32)
+            (local $new_header_addr i32)
+        (local.set $scratch_start (call $glob_scratch_start_addr))
+        (local.set $scratch_end (i32.add (local.get $scratch_start)
+            (i32.mul (i32.const 4) (call $scratch_size (call $young_side_max_size) (call $old_max_size)))))
+
+        ;; mark reachable young and old objects, pushing all fields of an object at once
+        (i32.store (call $addr_mark_stack_top) (local.get $scratch_start))
+        (call $walk_stack_fields (call $handler_gc_full_mark) (local.get $scratch_end) (i32.const 0))
+        (block $marked (loop $next_marked
+            (local.set $top (i32.load (call $addr_mark_stack_top)))
+            (br_if $marked (i32.le_u (local.get $top) (local.get $scratch_start)))
+            (local.set $top (i32.sub (local.get $top) (i32.const 4)))
+            (i32.store (call $addr_mark_stack_top) (local.get $top))
+            (drop (call $walk_object_fields
+                (call $header_addr_of_pointer (i32.load (local.get $top)))
+                (call $handler_gc_full_mark) (local.get $scratch_end) (i32.const 0)))
+            (br $next_marked)
+        ))
+
+        ;; compute new addresses of live old objects; walking in address order keeps the table sorted
+        (local.set $table_end (local.get $scratch_start))
+        (local.set $header_addr (call $glob_old_start_addr))
+        (local.set $new_header_addr (call $glob_old_start_addr))
+        (block $done (loop $next_object
+            (br_if $done (i32.ge_u (local.get $header_addr) (call $old_end_addr)))
+            (local.set $meta_addr (call $meta_addr_of_header (local.get $header_addr)))
+            (local.set $next_addr (call $next_object_addr (local.get $header_addr)))
+            (if (call $read_metadata_gc_mark (local.get $meta_addr)) (then
+                (if (i32.gt_u (i32.add (local.get $table_end) (i32.const 8)) (local.get $scratch_end)) (then
+                    (call $log_err_code (i32.const 15)) unreachable ))
+                (i32.store (local.get $table_end) (i32.add (local.get $meta_addr) (i32.const 4)))
+                (i32.store (i32.add (local.get $table_end) (i32.const 4)) (i32.add (local.get $new_header_addr)
+                    (i32.sub (i32.add (local.get $meta_addr) (i32.const 4)) (local.get $header_addr))))
+                (local.set $table_end (i32.add (local.get $table_end) (i32.const 8)))
+                (local.set $new_header_addr (i32.add (local.get $new_header_addr)
+                    (i32.sub (local.get $next_addr) (local.get $header_addr))))
+            ))
+            (local.set $header_addr (local.get $next_addr))
+            (br $next_object)
+        ))
+
+        ;; update pointers into the old gen, from the stack and from live young and old objects
+        (call $walk_stack_fields (call $handler_gc_full_update) (local.get $scratch_start) (local.get $table_end))
+        (local.set $header_addr (call $glob_young_start_addr))
+        (block $done (loop $next_object
+            (br_if $done (i32.ge_u (local.get $header_addr) (call $young_end_addr)))
+            (local.set $meta_addr (call $meta_addr_of_header (local.get $header_addr)))
+            (if (call $read_metadata_gc_mark (local.get $meta_addr)) (then
+                (call $write_metadata_gc_mark (local.get $meta_addr) (i32.const 0))
+                (drop (call $walk_object_fields (local.get $header_addr)
+                    (call $handler_gc_full_update) (local.get $scratch_start) (local.get $table_end)))
+            ))
+            (local.set $header_addr (call $next_object_addr (local.get $header_addr)))
+            (br $next_object)
+        ))
+        (local.set $header_addr (call $glob_old_start_addr))
+        (block $done (loop $next_object
+            (br_if $done (i32.ge_u (local.get $header_addr) (call $old_end_addr)))
+            (if (call $read_metadata_gc_mark (call $meta_addr_of_header (local.get $header_addr))) (then
+                (drop (call $walk_object_fields (local.get $header_addr)
+                    (call $handler_gc_full_update) (local.get $scratch_start) (local.get $table_end)))
+            ))
+            (local.set $header_addr (call $next_object_addr (local.get $header_addr)))
+            (br $next_object)
+        ))
+
+        ;; slide live old objects down; they only move to lower addresses, so the
+        ;; next object is never overwritten before it is read
+        (local.set $header_addr (call $glob_old_start_addr))
+        (local.set $new_header_addr (call $glob_old_start_addr))
+        (block $done (loop $next_object
+            (br_if $done (i32.ge_u (local.get $header_addr) (call $old_end_addr)))
+            (local.set $meta_addr (call $meta_addr_of_header (local.get $header_addr)))
+            (local.set $next_addr (call $next_object_addr (local.get $header_addr)))
+            (if (call $read_metadata_gc_mark (local.get $meta_addr)) (then
+                (call $write_metadata_gc_mark (local.get $meta_addr) (i32.const 0))
+                (memory.copy
+                    (local.get $new_header_addr)
+                    (local.get $header_addr)
+                    (i32.sub (local.get $next_addr) (local.get $header_addr)))
+                (local.set $new_header_addr (i32.add (local.get $new_header_addr)
+                    (i32.sub (local.get $next_addr) (local.get $header_addr))))
+            ))
+            (local.set $header_addr (local.get $next_addr))
+            (br $next_object)
+        ))
+        (i32.store (call $addr_old_length) (i32.shr_u
+            (i32.sub (local.get $new_header_addr) (call $glob_old_start_addr))
+            (i32.const 2)))
+
+        ;; old objects moved to other cards, and young objects were only marked, not moved
+        (call $rebuild_card_table)
+        (call $gc_fast)
     )
 
+    ;; mark the object that the pointer at $field_addr points to, and push it on the mark stack,
+    ;; unless it is not in the young or old heap, or was marked before
+    (func $gc_full_mark_pointer
+            (param $field_addr i32)
+            (param $scratch_end i32)
+            (param $unused i32)
+            (local $pointer i32)
+            (local $top i32)
+        (local.set $pointer (i32.load (local.get $field_addr)))
+        (if (i32.eqz (i32.or
+                (call $is_in_region (local.get $pointer) (call $glob_young_start_addr) (call $young_end_addr))
+                (call $is_in_region (local.get $pointer) (call $glob_old_start_addr) (call $old_end_addr)))) (then
+            (return) ))
+        (if (call $read_metadata_gc_mark (i32.sub (local.get $pointer) (i32.const 4))) (then
+            (return) ))
+        (call $write_metadata_gc_mark (i32.sub (local.get $pointer) (i32.const 4)) (i32.const 1))
+        (if (i32.eqz (call $read_metadata_pointer_cnt (i32.sub (local.get $pointer) (i32.const 4)))) (then
+            (return) ))
+        (local.set $top (i32.load (call $addr_mark_stack_top)))
+        (if (i32.ge_u (local.get $top) (local.get $scratch_end)) (then
+            (call $log_err_code (i32.const 15)) unreachable ))
+        (i32.store (local.get $top) (local.get $pointer))
+        (i32.store (call $addr_mark_stack_top) (i32.add (local.get $top) (i32.const 4)))
+    )
+
+    ;; if the pointer at $field_addr points into the old gen, replace it by the new address,
+    ;; using binary search in the break table between $table_start and $table_end
+    (func $gc_full_update_pointer
+            (param $field_addr i32)
+            (param $table_start i32)
+            (param $table_end i32)
+            (local $pointer i32)
+            (local $low i32)
+            (local $high i32)
+            (local $entry i32)
+        (local.set $pointer (i32.load (local.get $field_addr)))
+        (if (i32.eqz (call $is_in_region (local.get $pointer) (call $glob_old_start_addr) (call $old_end_addr))) (then
+            (return) ))
+        (local.set $low (i32.const 0))
+        (local.set $high (i32.shr_u (i32.sub (local.get $table_end) (local.get $table_start)) (i32.const 3)))
+        (block $done (loop $bisect
+            (br_if $done (i32.ge_u (local.get $low) (local.get $high)))
+            (local.set $entry (i32.add (local.get $table_start)
+                (i32.shl (i32.shr_u (i32.add (local.get $low) (local.get $high)) (i32.const 1)) (i32.const 3))))
+            (if (i32.eq (i32.load (local.get $entry)) (local.get $pointer)) (then
+                (i32.store (local.get $field_addr) (i32.load (i32.add (local.get $entry) (i32.const 4))))
+                (return)
+            ))
+            (if (i32.lt_u (i32.load (local.get $entry)) (local.get $pointer)) (then
+                (local.set $low (i32.add (i32.shr_u (i32.sub (local.get $entry) (local.get $table_start)) (i32.const 3)) (i32.const 1)))
+            ) (else
+                (local.set $high (i32.shr_u (i32.sub (local.get $entry) (local.get $table_start)) (i32.const 3)))
+            ))
+            (br $bisect)
+        ))
+        ;; every live object was marked, so this means the heap is corrupt
+        (call $log_err_code (i32.const 16)) unreachable
+    )
+
+    ;; whether $pointer points to an object between $start and $end (pointers point
+    ;; after the metadata, so can equal the end but not the start)
+    (func $is_in_region
+            (param $pointer i32)
+            (param $start i32)
+            (param $end i32)
+            (result i32)
+        (i32.and
+            (i32.gt_u (local.get $pointer) (local.get $start))
+            (i32.le_u (local.get $pointer) (local.get $end)))
+    )
+
+    ;; number of metadata words (1, or 2 if the counts together do not fit in a byte, like gc.rs)
+    (func $metadata_size
+            (param $pointer_cnt i32)
+            (param $data_size_32 i32)
+            (result i32)
+        (i32.add (i32.const 1)
+            (i32.gt_u (i32.add (local.get $pointer_cnt) (local.get $data_size_32)) (i32.const 255)))
+    )
+
+    ;; $header_addr is the start of the metadata, which may be 1 or 2 words
     (func $write_metadata_heap
-            (param $meta_addr i32)
+            (param $header_addr i32)
+            (param $type i32)
             (param $pointer_cnt i32)
             (param $data_size_32 i32)
             (param $pointers_mutable i32)
+            (local $meta_addr i32)
             (local $flags i32)
 
-        ;; reset all bits to 0
-        (i32.store (local.get $meta_addr) (i32.const 0))
-
-        ;; sizes
-        (if (i32.gt_u (local.get $pointer_cnt) (i32.const 127)) (then (call $log_err_code (i32.const 7)) unreachable ))
-        (if (i32.gt_u (local.get $data_size_32) (i32.const 127)) (then (call $log_err_code (i32.const 8)) unreachable ))
-
-        (i32.store8 (i32.add (local.get $meta_addr) (i32.const 2)) (local.get $pointer_cnt))
-        (i32.store8 (i32.add (local.get $meta_addr) (i32.const 3)) (local.get $data_size_32))
+        ;; sizes (also resets all bits to 0)
+        (local.set $meta_addr (call $write_metadata_sizes (local.get $header_addr) (local.get $pointer_cnt) (local.get $data_size_32)))
 
         ;; flags
-        (local.set $flags (i32.const 0))
+        (local.set $flags (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 1))))
 
-        ;; first bit of this byte is for GC flag, 2nd for pointer mutability
+        ;; first bit of this byte is for GC flag, 2nd for pointer mutability, 3rd for big metadata,
+        ;; and the next 3 for GC age
         (if (i32.ne (local.get $pointers_mutable) (i32.const 0)) (then
-                (local.set $flags (i32.or (local.get $flags) (i32.const 2)))))
+                (local.set $flags (i32.or (local.get $flags) (call $flag_pointers_mutable)))))
 
         (i32.store8 (i32.add (local.get $meta_addr) (i32.const 1)) (local.get $flags))
 
         ;; type, see $read_metadata_type
-        (i32.store8 (local.get $meta_addr) (i32.const 1))
+        (i32.store8 (local.get $meta_addr) (local.get $type))
     )
 
     (func $write_metadata_stack
-            (param $meta_addr i32)
+            (param $header_addr i32)
             (param $pointer_cnt i32)
             (param $data_size_32 i32)
-        (if (i32.gt_u (local.get $pointer_cnt) (i32.const 127)) (then (call $log_err_code (i32.const 9)) unreachable ))
-        (if (i32.gt_u (local.get $data_size_32) (i32.const 127)) (then (call $log_err_code (i32.const 10)) unreachable ))
+        (drop (call $write_metadata_sizes (local.get $header_addr) (local.get $pointer_cnt) (local.get $data_size_32)))
+    )
 
-        (i32.store8 (i32.add (local.get $meta_addr) (i32.const 2)) (local.get $pointer_cnt))
-        (i32.store8 (i32.add (local.get $meta_addr) (i32.const 3)) (local.get $data_size_32))
+    ;; write the counts and big-metadata flag, returns the address of the last metadata word
+    (func $write_metadata_sizes
+            (param $header_addr i32)
+            (param $pointer_cnt i32)
+            (param $data_size_32 i32)
+            (result i32)
+            (local $meta_addr i32)
+        (if (i32.eq (call $metadata_size (local.get $pointer_cnt) (local.get $data_size_32)) (i32.const 1)) (then
+            (i32.store (local.get $header_addr) (i32.const 0))
+            (i32.store8 (i32.add (local.get $header_addr) (i32.const 2)) (local.get $pointer_cnt))
+            (i32.store8 (i32.add (local.get $header_addr) (i32.const 3)) (local.get $data_size_32))
+            (return (local.get $header_addr))
+        ))
+
+        ;; big metadata, first word can be recognized when walking memory
+        (if (i32.gt_u (local.get $pointer_cnt) (i32.const 0xFFFFFF)) (then (call $log_err_code (i32.const 7)) unreachable ))
+        (if (i32.gt_u (local.get $data_size_32) (i32.const 0xFFFF)) (then (call $log_err_code (i32.const 8)) unreachable ))
+        (local.set $meta_addr (i32.add (local.get $header_addr) (i32.const 4)))
+        (i32.store (local.get $header_addr) (i32.or (call $big_metadata_first_byte) (i32.shl (local.get $pointer_cnt) (i32.const 8))))
+        (i32.store (local.get $meta_addr) (i32.const 0))
+        (i32.store8 (i32.add (local.get $meta_addr) (i32.const 1)) (call $flag_big_metadata))
+        (i32.store16 (i32.add (local.get $meta_addr) (i32.const 2)) (local.get $data_size_32))
+        local.get $meta_addr
+    )
+
+    ;; whether the metadata word at $meta_addr is preceded by a size word
+    (func $read_metadata_is_big
+            (param $meta_addr i32)
+            (result i32)
+        (i32.ne (i32.const 0)
+            (i32.and
+                (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 1)))
+                (call $flag_big_metadata)))
+    )
+
+    ;; metadata words, from the first word of an object (e.g. when walking memory)
+    (func $read_header_size
+            (param $header_addr i32)
+            (result i32)
+        (i32.add (i32.const 1)
+            (i32.eq (i32.load8_u (local.get $header_addr)) (call $big_metadata_first_byte)))
+    )
+
+    ;; the last metadata word, from the first word of an object
+    (func $meta_addr_of_header
+            (param $header_addr i32)
+            (result i32)
+        (i32.add (local.get $header_addr)
+            (i32.mul (i32.const 4) (i32.sub (call $read_header_size (local.get $header_addr)) (i32.const 1))))
+    )
+
+    ;; the first word of an object, from a pointer to it (which is after the metadata)
+    (func $header_addr_of_pointer
+            (param $pointer i32)
+            (result i32)
+        (i32.sub (local.get $pointer) (i32.mul (i32.const 4)
+            (i32.add (i32.const 1) (call $read_metadata_is_big (i32.sub (local.get $pointer) (i32.const 4))))))
+    )
+
+    ;; the first word of the next object, from the first word of an object
+    (func $next_object_addr
+            (param $header_addr i32)
+            (result i32)
+            (local $meta_addr i32)
+        (local.set $meta_addr (call $meta_addr_of_header (local.get $header_addr)))
+        (i32.add (i32.add (local.get $meta_addr) (i32.const 4)) (i32.mul (i32.const 4) (i32.add
+            (call $read_metadata_pointer_cnt (local.get $meta_addr))
+            (call $read_metadata_data_word_cnt (local.get $meta_addr)))))
     )
 
     ;; same for stack and heap
     (func $read_metadata_pointer_cnt
             (param $meta_addr i32)
             (result i32)
+        (if (call $read_metadata_is_big (local.get $meta_addr)) (then
+            (return (i32.shr_u (i32.load (i32.sub (local.get $meta_addr) (i32.const 4))) (i32.const 8)))
+        ))
         (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 2)))
     )
 
@@ -294,25 +1254,63 @@
     (func $read_metadata_data_word_cnt
             (param $meta_addr i32)
             (result i32)
+        (if (call $read_metadata_is_big (local.get $meta_addr)) (then
+            (return (i32.load16_u (i32.add (local.get $meta_addr) (i32.const 2))))
+        ))
         (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 3)))
     )
 
     ;; only for heap, not stack
-    ;; type (1=struct, 2=array, 127=GC-redirect)
+    ;; type (1=struct, 2=array; forwards are only seen during GC and are not a type)
     (func $read_metadata_type
             (param $meta_addr i32)
             (result i32)
             (local $res i32)
         (local.set $res (i32.load8_u (local.get $meta_addr)))
         ;; check in debug mode only:
-        (if (i32.and (i32.ne (local.get $res) (i32.const 1))
-                (i32.and (i32.ne (local.get $res) (i32.const 2))
-                    (i32.ne (local.get $res) (i32.const 127)))) (then
+        (if (i32.and (i32.ne (local.get $res) (call $type_struct))
+                (i32.ne (local.get $res) (call $type_array))) (then
             (call $log_err_code (i32.const 11)) unreachable
         ))
         local.get $res
     )
 
+    ;; only for heap, not stack; whether marked as reachable during gc_full (first bit of flags)
+    (func $read_metadata_gc_mark
+            (param $meta_addr i32)
+            (result i32)
+        (i32.ne (i32.const 0)
+            (i32.and (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 1))) (call $flag_gc_mark)))
+    )
+
+    (func $write_metadata_gc_mark
+            (param $meta_addr i32)
+            (param $is_marked i32)
+        (i32.store8 (i32.add (local.get $meta_addr) (i32.const 1)) (i32.or
+            (i32.and (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 1))) (i32.xor (call $flag_gc_mark) (i32.const -1)))
+            (select (call $flag_gc_mark) (i32.const 0) (local.get $is_marked))))
+    )
+
+    ;; only for heap, not stack; 4th to 6th bit of the flags byte
+    (func $read_metadata_gc_age
+            (param $meta_addr i32)
+            (result i32)
+        (i32.and
+            (i32.shr_u (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 1))) (call $gc_age_offset_bits))
+            (i32.const 7))
+    )
+
+    ;; add one to the GC age, unless it is already at the max of 7
+    (func $increment_gc_age
+            (param $meta_addr i32)
+            (local $flags i32)
+        (local.set $flags (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 1))))
+        (if (i32.lt_u (call $read_metadata_gc_age (local.get $meta_addr)) (i32.const 7)) (then
+            (i32.store8 (i32.add (local.get $meta_addr) (i32.const 1))
+                (i32.add (local.get $flags) (i32.shl (i32.const 1) (call $gc_age_offset_bits))))
+        ))
+    )
+
     ;; only for heap, not stack
     (func $read_metadata_pointers_mutable
             (param $meta_addr i32)
@@ -320,128 +1318,80 @@
         (i32.ne (i32.const 0)
             (i32.and
                 (i32.load8_u (i32.add (local.get $meta_addr) (i32.const 1)))
-                (i32.const 2)))
+                (call $flag_pointers_mutable)))
     )
 
     ;;
     ;; some internals, perhaps mostly for testing, as they make it hard to change impl
     ;;
 
-    (func $get_young_size
+    (func $get_young_size (export "get_young_size")
             (result i32)
         (i32.load (call $addr_young_length))
     )
 
+    ;; element count of an array on the heap
+    (func $get_array_len
+            (param $addr i32)
+            (result i32)
+            (local $meta_addr i32)
+        (local.set $meta_addr (i32.sub (local.get $addr) (i32.const 4)))
+        (if (i32.ne (call $read_metadata_type (local.get $meta_addr)) (call $type_array)) (then
+            (call $log_err_code (i32.const 13)) unreachable))
+        (i32.add
+            (call $read_metadata_pointer_cnt (local.get $meta_addr))
+            (call $read_metadata_data_word_cnt (local.get $meta_addr)))
+    )
+
     (func $get_stack_size
             (result i32)
         (i32.load (call $addr_stack_length))
     )
 
-    (func $print_memory
-        call $print_stack
-        call $print_heap
+    ;; address after the last stack object, where the next one goes
+    (func $stack_end_addr
+            (result i32)
+        (i32.add (call $glob_stack_start_addr) (i32.mul (i32.const 4) (call $get_stack_size)))
     )
 
-    (func $print_stack
-            (local $i i32)
-            (local $p i32)
-            (local $start i32)
-            (local $upto i32)
-        (local.set $start (call $glob_stack_start_addr))
-        (local.set $upto (i32.mul (i32.const 4) (call $get_stack_size)))
-        (local.set $i (i32.const 0))
-        (block $outer (loop $continue
-            (i32.ge_u (local.get $i) (local.get $upto))
-            br_if $outer
-            (local.set $p (i32.add (local.get $i) (local.get $start)))
-            (call $log_i32x7
-                    (i32.div_s (local.get $i) (i32.const -4))
-                    (i32.load8_u (i32.add (local.get $p) (i32.const 0)))
-                    (i32.load8_u (i32.add (local.get $p) (i32.const 1)))
-                    (i32.load8_u (i32.add (local.get $p) (i32.const 2)))
-                    (i32.load8_u (i32.add (local.get $p) (i32.const 3)))
-                    (i32.load (local.get $p))
-                    (local.get $p))
-            (local.set $i (i32.add (local.get $i) (i32.const 4)))
-            (br $continue)
-        ))
+    ;; address after the last object in the active young half
+    (func $young_end_addr
+            (result i32)
+        (i32.add (call $glob_young_start_addr) (i32.mul (i32.const 4) (call $get_young_size)))
     )
 
-    (func $print_heap
-            (local $i i32)
-            (local $p i32)
-            (local $start i32)
-            (local $upto i32)
-        (local.set $start (call $glob_young_start_addr))
-        (local.set $upto (i32.mul (i32.const 4) (call $get_young_size)))
-        (local.set $i (i32.const 0))
-        (block $outer (loop $continue
-            (i32.ge_u (local.get $i) (local.get $upto))
-            br_if $outer
-            (local.set $p (i32.add (local.get $i) (local.get $start)))
-            (call $log_i32x7
-                    (i32.div_s (local.get $i) (i32.const 4))
-                    (i32.load8_u (i32.add (local.get $p) (i32.const 0)))
-                    (i32.load8_u (i32.add (local.get $p) (i32.const 1)))
-                    (i32.load8_u (i32.add (local.get $p) (i32.const 2)))
-                    (i32.load8_u (i32.add (local.get $p) (i32.const 3)))
-                    (i32.load (local.get $p))
-                    (local.get $p))
-            (local.set $i (i32.add (local.get $i) (i32.const 4)))
-            (br $continue)
-        ))
+    (func $get_old_size (export "get_old_size")
+            (result i32)
+        (i32.load (call $addr_old_length))
+    )
+
+    ;; address after the last object in the old gen
+    (func $old_end_addr
+            (result i32)
+        (i32.add (call $glob_old_start_addr) (i32.mul (i32.const 4) (call $get_old_size)))
     )
 
     ;;
     ;; TESTS
+    ;; exports named test_* are found by the host, which runs each in a fresh instance
     ;;
 
-    (func $gc_tests (export "tests")
-        (call $test_empty_heap)
-
-        (call $test_double_data_alloc)
-        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $alloc_init)  ;; reset heap
-
-        (call $test_mut_pointer_alloc)
-        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $alloc_init)  ;; reset heap
-
-        (call $test_double_stack_alloc)
-        ;;TODO @mark: not printing?
-        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
-        (call $test_alloc_full_heap_GC)
-        (call $alloc_init)  ;; reset heap
-
-        (call $test_compact_alive_in_full_GC)
-        (call $alloc_init)  ;; reset heap
+    (func $test_empty_heap (export "test_empty_heap")
+        (call $assert_eq_i32 (i32.const 107) (i32.const 0) (call $get_young_size))
     )
 
-    (func $test_empty_heap
-        (call $alloc_init)  ;; reset heap
-        (if (i32.ne (call $get_young_size) (i32.const 0))
-            (then (call $log_err_code (i32.const 107)) unreachable))
-    )
-
-    (func $test_double_data_alloc
+    (func $test_double_data_alloc (export "test_double_data_alloc")
 
         ;; first allocation
         (drop (call $alloc (i32.const 0) (i32.const 2) (i32.const 0)))
-        (if (i32.ne (call $get_young_size) (i32.const 3)) (then
-            (call $log_err_code (i32.const 100))
-            unreachable
-        ))
+        (call $assert_eq_i32 (i32.const 100) (i32.const 3) (call $get_young_size))
 
         ;; what if we do it again
         (drop (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
-        (if (i32.ne (call $get_young_size) (i32.const 5)) (then
-            (call $log_err_code (i32.const 101))
-            unreachable
-        ))
+        (call $assert_eq_i32 (i32.const 101) (i32.const 5) (call $get_young_size))
     )
 
-    (func $test_mut_pointer_alloc
+    (func $test_mut_pointer_alloc (export "test_mut_pointer_alloc")
             (local $addr i32)
 
         ;; allocation with mutable pointers and data twice
@@ -449,11 +1399,99 @@
         (local.set $addr (call $alloc (i32.const 2) (i32.const 2) (i32.const 1)))
 
         ;; check the size and properties
-        (if (i32.ne (call $get_young_size) (i32.const 7)) (then (
-            call $log_err_code (i32.const 112)) unreachable ))
+        (call $assert_eq_i32 (i32.const 112) (i32.const 7) (call $get_young_size))
+        (call $assert_ptr_in_region (i32.const 112) (local.get $addr) (call $region_young))
+        (call $assert_ne_i32 (i32.const 112) (i32.const 0)
+            (call $read_metadata_pointers_mutable (i32.sub (local.get $addr) (i32.const 4))))
+    )
+
+    (func $test_array_alloc (export "test_array_alloc")
+            (local $pointers i32)
+            (local $values i32)
+
+        ;; one array of pointers and one of data
+        (local.set $pointers (call $alloc_array (i32.const 3) (i32.const 1) (i32.const 1)))
+        (local.set $values (call $alloc_array (i32.const 4) (i32.const 0) (i32.const 0)))
+
+        ;; check size, element counts and which elements are pointers
+        (if (i32.ne (call $get_young_size) (i32.const 9)) (then
+            (call $log_err_code (i32.const 120)) unreachable ))
+        (if (i32.ne (call $get_array_len (local.get $pointers)) (i32.const 3)) (then
+            (call $log_err_code (i32.const 121)) unreachable ))
+        (if (i32.ne (call $get_array_len (local.get $values)) (i32.const 4)) (then
+            (call $log_err_code (i32.const 122)) unreachable ))
+        (if (i32.ne (call $read_metadata_pointer_cnt (i32.sub (local.get $values) (i32.const 4))) (i32.const 0)) (then
+            (call $log_err_code (i32.const 123)) unreachable ))
+    )
+
+    (func $test_big_alloc (export "test_big_alloc")
+            (local $big i32)
+            (local $after i32)
+            (local $array i32)
+
+        ;; objects with over 255 pointers and data words together get two metadata words
+        (local.set $big (call $alloc (i32.const 300) (i32.const 3) (i32.const 1)))
+        (local.set $after (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
+        (local.set $array (call $alloc_array (i32.const 1000) (i32.const 0) (i32.const 0)))
+        (call $assert_eq_i32 (i32.const 124) (i32.const 1309) (call $get_young_size))
+        (call $assert_eq_i32 (i32.const 125) (i32.const 1216) (i32.sub (local.get $after) (local.get $big)))
+        (call $assert_eq_i32 (i32.const 126) (i32.const 300)
+            (call $read_metadata_pointer_cnt (i32.sub (local.get $big) (i32.const 4))))
+        (call $assert_eq_i32 (i32.const 137) (i32.const 3)
+            (call $read_metadata_data_word_cnt (i32.sub (local.get $big) (i32.const 4))))
+        (call $assert_eq_i32 (i32.const 138) (i32.const 1)
+            (call $read_metadata_pointers_mutable (i32.sub (local.get $big) (i32.const 4))))
+        (call $assert_eq_i32 (i32.const 127) (i32.const 2) (call $read_header_size (i32.sub (local.get $big) (i32.const 8))))
+        (call $assert_eq_i32 (i32.const 139) (i32.const 1) (call $read_header_size (i32.sub (local.get $after) (i32.const 4))))
+        (call $assert_eq_i32 (i32.const 128) (i32.const 1000) (call $get_array_len (local.get $array)))
+
+        ;; even if neither count is over 255 by itself
+        (local.set $big (call $alloc (i32.const 200) (i32.const 56) (i32.const 0)))
+        (call $assert_eq_i32 (i32.const 192) (i32.const 2) (call $read_header_size (i32.sub (local.get $big) (i32.const 8))))
+        (local.set $after (call $alloc (i32.const 200) (i32.const 55) (i32.const 0)))
+        (call $assert_eq_i32 (i32.const 193) (i32.const 1) (call $read_header_size (i32.sub (local.get $after) (i32.const 4))))
+
+        ;; same on the stack
+        (drop (call $alloc_stack (i32.const 1) (i32.const 300)))
+        (call $assert_eq_i32 (i32.const 129) (i32.const 303) (call $get_stack_size))
     )
 
-    (func $test_double_stack_alloc
+    (func $test_resize (export "test_resize")
+            (local $stack_addr i32)
+            (local $heap_addr i32)
+            (local $new_heap_addr i32)
+
+        ;; a stack object pointing to a heap object, and one heap object pointing to another
+        (local.set $stack_addr (call $alloc_stack (i32.const 1) (i32.const 0)))
+        (drop (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
+        (local.set $heap_addr (call $alloc (i32.const 1) (i32.const 1) (i32.const 0)))
+        (i32.store (local.get $stack_addr) (local.get $heap_addr))
+        (i32.store (local.get $heap_addr) (i32.sub (local.get $heap_addr) (i32.const 8)))
+        (i32.store (i32.add (local.get $heap_addr) (i32.const 4)) (i32.const 42))
+
+        ;; too small fails, growing beyond initial memory works and moves the young heap
+        ;; (without collecting first, which would also move objects)
+        (call $alloc_gc_disable)
+        (if (i32.ne (call $resize (i32.const 1024) (i32.const 3) (i32.const 8192)) (i32.const 0)) (then
+            (call $log_err_code (i32.const 131)) unreachable ))
+        (if (i32.ne (call $resize (i32.const 30000) (i32.const 20000) (i32.const 8192)) (i32.const 1)) (then
+            (call $log_err_code (i32.const 132)) unreachable ))
+        (call $alloc_gc_enable)
+        (local.set $new_heap_addr (i32.load (local.get $stack_addr)))
+        (if (i32.ne (local.get $new_heap_addr) (i32.add (local.get $heap_addr) (i32.const 115904))) (then
+            (call $log_err_code (i32.const 133)) unreachable ))
+        (if (i32.ne (i32.load (i32.add (local.get $new_heap_addr) (i32.const 4))) (i32.const 42)) (then
+            (call $log_err_code (i32.const 134)) unreachable ))
+        (if (i32.ne (i32.load (local.get $new_heap_addr)) (i32.sub (local.get $new_heap_addr) (i32.const 8))) (then
+            (call $log_err_code (i32.const 135)) unreachable ))
+        (if (i32.ne (call $get_young_size) (i32.const 5)) (then
+            (call $log_err_code (i32.const 136)) unreachable ))
+
+        ;; allocation that did not fit before
+        (drop (call $alloc (i32.const 0) (i32.const 19000) (i32.const 0)))
+    )
+
+    (func $test_double_stack_alloc (export "test_double_stack_alloc")
             (local $top1 i32)
             (local $top2 i32)
 
@@ -463,12 +1501,9 @@
         (call $stack_pop_to (local.get $top2))
         (local.set $top2 (call $stack_push))
 
-        ;; first allocation
+        ;; first allocation (after the link words of the two frames)
         (drop (call $alloc_stack (i32.const 0) (i32.const 2)))
-        (if (i32.ne (call $get_stack_size) (i32.const 3)) (then
-            (call $log_err_code (i32.const 108))
-            unreachable
-        ))
+        (call $assert_eq_i32 (i32.const 108) (i32.const 5) (call $get_stack_size))
 
         ;; prev frame
         (call $stack_pop_to (local.get $top2))
@@ -476,24 +1511,91 @@
         ;; what if we do it again
         (drop (call $alloc_stack (i32.const 0) (i32.const 1)))
         (drop (call $alloc_stack (i32.const 0) (i32.const 8)))
-        (if (i32.ne (call $get_stack_size) (i32.const 11)) (then
-            (call $log_err_code (i32.const 109))
-            unreachable
-        ))
+        (call $assert_eq_i32 (i32.const 109) (i32.const 12) (call $get_stack_size))
 
         ;; empty stack
         (call $stack_pop_to (local.get $top1))
-        (if (i32.ne (call $get_stack_size) (i32.const 0)) (then
-            (call $log_err_code (i32.const 110))
-            unreachable
-        ))
+        (call $assert_eq_i32 (i32.const 110) (i32.const 0) (call $get_stack_size))
 
         ;; leave some data for heap test
         (local.set $top1 (call $stack_push))
         (drop (call $alloc_stack (i32.const 0) (i32.const 2)))
     )
 
-    (func $test_alloc_full_heap_GC
+    (func $test_gc_fast (export "test_gc_fast")
+            (local $frame i32)
+            (local $root i32)
+            (local $shared i32)
+            (local $big i32)
+            (local $side i32)
+
+        ;; without an old gen, objects stay young and keep aging
+        (call $assert_eq_i32 (i32.const 183) (i32.const 1) (call $resize (i32.const 1024) (i32.const 16384) (i32.const 0)))
+
+        ;; garbage, then a shared object referenced twice from a mutable one, which is on the stack
+        (local.set $frame (call $stack_push))
+        (local.set $root (call $alloc_stack (i32.const 1) (i32.const 0)))
+        (drop (call $alloc (i32.const 0) (i32.const 5) (i32.const 0)))
+        (local.set $shared (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
+        (i32.store (local.get $shared) (i32.const 42))
+        (local.set $big (call $alloc (i32.const 2) (i32.const 300) (i32.const 1)))
+        (i32.store (local.get $big) (local.get $shared))
+        (i32.store (i32.add (local.get $big) (i32.const 4)) (local.get $shared))
+        (i32.store (i32.add (local.get $big) (i32.const 8)) (i32.const 43))
+        (i32.store (local.get $root) (local.get $big))
+        (drop (call $alloc (i32.const 0) (i32.const 7) (i32.const 0)))
+        (local.set $side (i32.load (call $addr_young_side)))
+
+        ;; only the reachable objects survive, in the other half, with their data
+        (call $gc_fast)
+        (call $assert_ne_i32 (i32.const 140) (local.get $side) (i32.load (call $addr_young_side)))
+        (call $assert_eq_i32 (i32.const 141) (i32.const 306) (call $get_young_size))
+        (local.set $big (i32.load (local.get $root)))
+        (call $assert_eq_i32 (i32.const 142) (i32.add (call $glob_young_start_addr) (i32.const 8)) (local.get $big))
+        (call $assert_eq_i32 (i32.const 143) (i32.const 43) (i32.load (i32.add (local.get $big) (i32.const 8))))
+        (local.set $shared (i32.load (local.get $big)))
+        (call $assert_eq_i32 (i32.const 144) (local.get $shared) (i32.load (i32.add (local.get $big) (i32.const 4))))
+        (call $assert_eq_i32 (i32.const 145) (i32.const 42) (i32.load (local.get $shared)))
+        (call $assert_eq_i32 (i32.const 146) (i32.const 1)
+            (call $read_metadata_pointers_mutable (i32.sub (local.get $big) (i32.const 4))))
+
+        ;; every collection adds one to the age, up to 7
+        (call $assert_eq_i32 (i32.const 147) (i32.const 1) (call $read_metadata_gc_age (i32.sub (local.get $shared) (i32.const 4))))
+        (call $gc_fast)
+        (call $assert_eq_i32 (i32.const 155) (local.get $side) (i32.load (call $addr_young_side)))
+        (local.set $big (i32.load (local.get $root)))
+        (call $assert_eq_i32 (i32.const 156) (i32.const 2) (call $read_metadata_gc_age (i32.sub (local.get $big) (i32.const 4))))
+        (call $gc_fast) (call $gc_fast) (call $gc_fast) (call $gc_fast) (call $gc_fast)
+        (local.set $big (i32.load (local.get $root)))
+        (call $assert_eq_i32 (i32.const 157) (i32.const 7) (call $read_metadata_gc_age (i32.sub (local.get $big) (i32.const 4))))
+        (call $assert_eq_i32 (i32.const 158) (i32.const 42) (i32.load (i32.load (local.get $big))))
+
+        ;; after popping the frame, nothing is reachable
+        (call $stack_pop_to (local.get $frame))
+        (call $gc_fast)
+        (call $assert_eq_i32 (i32.const 148) (i32.const 0) (call $get_young_size))
+    )
+
+    (func $test_alloc_fills_young_then_collects (export "test_alloc_fills_young_then_collects")
+            (local $i i32)
+            (local $root i32)
+
+        ;; allocating many times the young size works if only little is kept
+        (drop (call $stack_push))
+        (local.set $root (call $alloc_stack (i32.const 1) (i32.const 0)))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (i32.const 1000)))
+            (i32.store (local.get $root) (call $alloc (i32.const 1) (i32.const 60) (i32.const 0)))
+            (i32.store (i32.add (i32.load (local.get $root)) (i32.const 4)) (local.get $i))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (if (i32.ne (i32.load (i32.add (i32.load (local.get $root)) (i32.const 4))) (i32.const 999)) (then
+            (call $log_err_code (i32.const 149)) unreachable ))
+    )
+
+    (func $test_alloc_full_heap_GC (export "test_alloc_full_heap_GC")
             (local $i i32)
             (local $orig_stack_size i32)
 
@@ -507,10 +1609,12 @@
             br $continue
         ))
 
-        ;; test that alloc fails
-        (call $alloc0 (i32.const 0) (i32.const 127) (i32.const 0))
-        (if (i32.ne (i32.const 0)) (then
-            (call $log_err_code (i32.const 105)) unreachable))
+        ;; test that alloc fails (if it is not allowed to collect garbage)
+        (call $alloc_gc_disable)
+        (call $assert_eq_i32 (i32.const 105) (i32.const 0) (call $alloc0 (i32.const 0) (i32.const 127) (i32.const 0)))
+        (call $alloc_gc_enable)
+        (if (i32.ne (i32.load (call $addr_gc_disabled)) (i32.const 0)) (then
+            (call $log_err_code (i32.const 130)) unreachable))
 
         ;; create some stack allocs so GC isn't too easy
         (drop (call $stack_push))
@@ -524,16 +1628,13 @@
         call $gc_full
 
         ;; test that GC does not clcean stack
-        (if (i32.ne (call $get_stack_size) (local.get $orig_stack_size)) (then
-            (call $log_err_code (i32.const 111)) unreachable))
+        (call $assert_eq_i32 (i32.const 111) (local.get $orig_stack_size) (call $get_stack_size))
 
         ;; test that GC cleans heap
-        call $alloc_init  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (if (i32.ne (call $get_young_size) (i32.const 0)) (then
-            (call $log_err_code (i32.const 106)) unreachable))
+        (call $assert_eq_i32 (i32.const 106) (i32.const 0) (call $get_young_size))
     )
 
-    (func $test_compact_alive_in_full_GC
+    (func $test_compact_alive_in_full_GC (export "test_compact_alive_in_full_GC")
             (local $orig_heap_size i32)
             (local $stack_top i32)
             (local $ref_on_stack_addr_1 i32)
@@ -546,59 +1647,23 @@
             (local $new_heap_shallow_addr i32)
             (local $new_heap_deep_addr i32)
 
-;;        (call $log_i32 (i32.const -1))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         ;; fill some unreferences heap memory
-;;        (call $log_i32 (call $glob_young_start_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
         (local.set $heap_selfref_addr (call $alloc (i32.const 1) (i32.const 2) (i32.const 1)))
-
-;;        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (i32.const -2))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (local.get $heap_selfref_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (call $glob_young_start_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (i32.add (local.get $heap_selfref_addr) (i32.const 4)))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (local.get $heap_selfref_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         (i32.store (local.get $heap_selfref_addr) (local.get $heap_selfref_addr))
-
-;;        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (i32.const -3))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         (local.set $heap_popped_addr (call $alloc (i32.const 1) (i32.const 1) (i32.const 1)))
 
-;;        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (i32.const -4))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (local.get $heap_popped_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         (i32.store (local.get $heap_popped_addr) (local.get $heap_popped_addr))
         ;;TODO @mark: make sure this gets referenced from the heap, but cleaned ^
 
-;;        (call $log_i32 (i32.const -5))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_nl)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         ;; fill some more heap memory that we'll reference
         (local.set $heap_deep_addr (call $alloc (i32.const 1) (i32.const 1) (i32.const 1)))
         (i32.store (i32.add (local.get $heap_deep_addr) (i32.const 4)) (i32.const -1))
 
-;;        (call $log_i32 (i32.const -6))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_nl)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         (local.set $heap_shallow_addr (call $alloc (i32.const 1) (i32.const 2) (i32.const 1)))
         (i32.store (local.get $heap_shallow_addr) (local.get $heap_deep_addr))
         (i32.store (i32.add (local.get $heap_shallow_addr) (i32.const 4)) (local.get $heap_deep_addr))
         (i32.store (i32.add (local.get $heap_shallow_addr) (i32.const 8)) (i32.const -2))
 
-;;        (call $log_i32 (i32.const -7))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (local.get $heap_deep_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_i32 (local.get $heap_shallow_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $log_nl)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-;;        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
-        (call $log_nl)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         ;; add some references to the heap on the stack:
         ;;; reference to heap
         (drop (call $stack_push))
@@ -606,12 +1671,6 @@
         (i32.store (local.get $ref_on_stack_addr_1) (local.get $heap_shallow_addr))
         (i32.store (i32.add (local.get $ref_on_stack_addr_1) (i32.const 4)) (i32.const -3))
 
-        (call $log_i32 (i32.const -8))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $log_i32 (local.get $ref_on_stack_addr_1))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $log_i32 (local.get $heap_shallow_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $log_nl)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         ;;; will be popped before GC
         (local.set $stack_top (call $stack_push))
         (local.set $ref_on_stack_addr_2 (call $alloc_stack (i32.const 1) (i32.const 5)))
@@ -621,8 +1680,6 @@
         (local.set $ref_on_stack_addr_3 (call $alloc_stack (i32.const 1) (i32.const 8)))
         (i32.store (local.get $ref_on_stack_addr_3) (local.get $ref_on_stack_addr_1))
 
-        return  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
         ;; run GC
         (local.set $orig_heap_size (call $get_young_size))
         call $gc_full
@@ -630,31 +1687,18 @@
         ;; check that memory usage decreased
         (if (i32.eq (call $get_young_size) (i32.const 0)) (then
             (call $log_err_code (i32.const 113)) unreachable))
-;;        (if (i32.ge_s (call $get_young_size) (local.get $orig_heap_size)) (then
-;;            (call $log_err_code (i32.const 114)) unreachable))
-        ;;TODO @mark: ON ^
-
-        (call $log_nl)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $print_memory)  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-
-        (call $log_i32 (local.get $ref_on_stack_addr_1))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $log_i32 (i32.load (i32.add (local.get $ref_on_stack_addr_1) (i32.const 4))))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $log_i32 (local.get $heap_shallow_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $log_i32 (local.get $new_heap_shallow_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $log_i32 (local.get $heap_deep_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
-        (call $log_i32 (local.get $new_heap_deep_addr))  ;;TODO @mark: TEMPORARY! REMOVE THIS!
+        (if (i32.ge_s (call $get_young_size) (local.get $orig_heap_size)) (then
+            (call $log_err_code (i32.const 114)) unreachable))
 
         ;; check that referenced memory still exists
         (if (i32.ne (i32.load (i32.add (local.get $ref_on_stack_addr_1) (i32.const 4))) (i32.const -3)) (then
             (call $log_err_code (i32.const 115)) unreachable))
         (local.set $new_heap_shallow_addr (i32.load (local.get $ref_on_stack_addr_1)))
-;;        (if (i32.ne (i32.load (i32.add (local.get $new_heap_shallow_addr) (i32.const 8))) (i32.const -2)) (then
-;;            (call $log_err_code (i32.const 116)) unreachable))
-        ;;TODO @mark: ON ^
+        (if (i32.ne (i32.load (i32.add (local.get $new_heap_shallow_addr) (i32.const 8))) (i32.const -2)) (then
+            (call $log_err_code (i32.const 116)) unreachable))
         (local.set $new_heap_deep_addr (i32.load (local.get $new_heap_shallow_addr)))
-;;        (if (i32.ne (i32.load (i32.add (local.get $new_heap_deep_addr) (i32.const 4))) (i32.const -1)) (then
-;;            (call $log_err_code (i32.const 117)) unreachable))
-        ;;TODO @mark: ON ^
+        (if (i32.ne (i32.load (i32.add (local.get $new_heap_deep_addr) (i32.const 4))) (i32.const -1)) (then
+            (call $log_err_code (i32.const 117)) unreachable))
 
         ;; check that referenced memory has moved (which in combination
         ;; with above probably means compacted)
@@ -664,5 +1708,235 @@
             (call $log_err_code (i32.const 119)) unreachable))
     )
 
-    ;;TODO @mark: test that mutable pointer data doesn't go to old heap
+    (func $test_promote_mutable (export "test_promote_mutable")
+            (local $root i32)
+            (local $mutable i32)
+            (local $young i32)
+            (local $i i32)
+
+        ;; a mutable object and some garbage
+        (drop (call $stack_push))
+        (local.set $root (call $alloc_stack (i32.const 1) (i32.const 0)))
+        (local.set $mutable (call $alloc (i32.const 1) (i32.const 1) (i32.const 1)))
+        (i32.store (i32.add (local.get $mutable) (i32.const 4)) (i32.const 7))
+        (drop (call $alloc (i32.const 0) (i32.const 3) (i32.const 0)))
+        (i32.store (local.get $root) (local.get $mutable))
+
+        ;; after enough collections, it moves to the old gen like immutable objects do
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (call $const_tenure_gc_age)))
+            (call $gc_fast)
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (local.set $mutable (i32.load (local.get $root)))
+        (call $assert_eq_i32 (i32.const 150) (i32.const 3) (call $get_old_size))
+        (call $assert_eq_i32 (i32.const 151) (i32.const 0) (call $get_young_size))
+        (call $assert_ptr_in_region (i32.const 152) (local.get $mutable) (call $region_old))
+
+        ;; then it is changed to point to a new young object, which the old gen keeps alive
+        (local.set $young (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
+        (i32.store (local.get $young) (i32.const 8))
+        (call $write_pointer (local.get $mutable) (local.get $young))
+        (call $gc_fast)
+        (call $assert_ptr_in_region (i32.const 153) (i32.load (local.get $mutable)) (call $region_young))
+        (call $assert_ne_i32 (i32.const 154) (local.get $young) (i32.load (local.get $mutable)))
+        (call $assert_eq_i32 (i32.const 181) (i32.const 8) (i32.load (i32.load (local.get $mutable))))
+        (call $assert_eq_i32 (i32.const 182) (i32.const 7) (i32.load (i32.add (local.get $mutable) (i32.const 4))))
+    )
+
+    (func $test_write_pointer_marks_card (export "test_write_pointer_marks_card")
+            (local $root i32)
+            (local $old i32)
+            (local $young i32)
+            (local $i i32)
+
+        ;; promote an object with two pointers
+        (drop (call $stack_push))
+        (local.set $root (call $alloc_stack (i32.const 1) (i32.const 0)))
+        (local.set $old (call $alloc (i32.const 2) (i32.const 0) (i32.const 1)))
+        (i32.store (local.get $root) (local.get $old))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (call $const_tenure_gc_age)))
+            (call $gc_fast)
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (local.set $old (i32.load (local.get $root)))
+        (call $assert_ptr_in_region (i32.const 184) (local.get $old) (call $region_old))
+
+        ;; a plain store does not mark the card, so gc_fast does not see the young object
+        (local.set $young (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
+        (i32.store (local.get $old) (local.get $young))
+        (call $assert_eq_i32 (i32.const 185) (i32.const 0) (i32.load (call $card_entry_addr (call $card_ix_of (local.get $old)))))
+        (call $gc_fast)
+        (call $assert_eq_i32 (i32.const 186) (local.get $young) (i32.load (local.get $old)))
+        (i32.store (local.get $old) (i32.const 0))
+
+        ;; write_pointer does, and the card stays marked while the object it points to is young
+        (local.set $young (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
+        (call $write_pointer (i32.add (local.get $old) (i32.const 4)) (local.get $young))
+        (call $assert_eq_i32 (i32.const 187) (i32.const 1) (i32.load (call $card_entry_addr (call $card_ix_of (local.get $old)))))
+        (call $gc_fast)
+        (call $assert_ptr_in_region (i32.const 188) (i32.load (i32.add (local.get $old) (i32.const 4))) (call $region_young))
+        (call $assert_eq_i32 (i32.const 189) (i32.const 1) (i32.load (call $card_entry_addr (call $card_ix_of (local.get $old)))))
+
+        ;; once that object is promoted, the card is clean again
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (call $const_tenure_gc_age)))
+            (call $gc_fast)
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (call $assert_ptr_in_region (i32.const 190) (i32.load (i32.add (local.get $old) (i32.const 4))) (call $region_old))
+        (call $assert_eq_i32 (i32.const 191) (i32.const 0) (i32.load (call $card_entry_addr (call $card_ix_of (local.get $old)))))
+    )
+
+    (func $test_gc_full_compacts_old (export "test_gc_full_compacts_old")
+            (local $root i32)
+            (local $garbage i32)
+            (local $kept i32)
+            (local $i i32)
+
+        ;; promote a garbage object and a kept one, which points to old and young data
+        (drop (call $stack_push))
+        (local.set $root (call $alloc_stack (i32.const 2) (i32.const 0)))
+        (local.set $garbage (call $alloc (i32.const 0) (i32.const 20) (i32.const 0)))
+        (i32.store (local.get $root) (local.get $garbage))
+        (local.set $kept (call $alloc (i32.const 0) (i32.const 2) (i32.const 0)))
+        (i32.store (local.get $kept) (i32.const 11))
+        (local.set $kept (call $alloc (i32.const 1) (i32.const 1) (i32.const 0)))
+        (i32.store (local.get $kept) (i32.sub (local.get $kept) (i32.const 12)))
+        (i32.store (i32.add (local.get $kept) (i32.const 4)) (i32.const 12))
+        (i32.store (i32.add (local.get $root) (i32.const 4)) (local.get $kept))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (call $const_tenure_gc_age)))
+            (call $gc_fast)
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (if (i32.ne (call $get_old_size) (i32.const 27)) (then
+            (call $log_err_code (i32.const 160)) unreachable ))
+
+        ;; drop the garbage reference, and add a young object
+        (i32.store (local.get $root) (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
+        (call $gc_full)
+
+        ;; only the kept objects are left in the old gen, moved down and still connected
+        (if (i32.ne (call $get_old_size) (i32.const 6)) (then
+            (call $log_err_code (i32.const 161)) unreachable ))
+        (local.set $kept (i32.load (i32.add (local.get $root) (i32.const 4))))
+        (if (i32.ne (local.get $kept) (i32.add (call $glob_old_start_addr) (i32.const 4))) (then
+            (call $log_err_code (i32.const 162)) unreachable ))
+        (if (i32.ne (i32.load (i32.add (local.get $kept) (i32.const 4))) (i32.const 12)) (then
+            (call $log_err_code (i32.const 163)) unreachable ))
+        (if (i32.ne (i32.load (i32.load (local.get $kept))) (i32.const 11)) (then
+            (call $log_err_code (i32.const 164)) unreachable ))
+        (if (i32.ne (call $get_young_size) (i32.const 2)) (then
+            (call $log_err_code (i32.const 165)) unreachable ))
+        (if (call $read_metadata_gc_mark (i32.sub (local.get $kept) (i32.const 4))) (then
+            (call $log_err_code (i32.const 166)) unreachable ))
+    )
+
+    (func $test_resize_old (export "test_resize_old")
+            (local $root i32)
+            (local $i i32)
+
+        ;; promote a garbage object and a kept one
+        (drop (call $stack_push))
+        (local.set $root (call $alloc_stack (i32.const 2) (i32.const 0)))
+        (i32.store (local.get $root) (call $alloc (i32.const 0) (i32.const 20) (i32.const 0)))
+        (i32.store (i32.add (local.get $root) (i32.const 4)) (call $alloc (i32.const 0) (i32.const 2) (i32.const 0)))
+        (i32.store (i32.load (i32.add (local.get $root) (i32.const 4))) (i32.const 11))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (call $const_tenure_gc_age)))
+            (call $gc_fast)
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (call $assert_eq_i32 (i32.const 167) (i32.const 24) (call $get_old_size))
+
+        ;; resizing collects the garbage first, but the kept object still does not fit in 2 words
+        (i32.store (local.get $root) (i32.const 0))
+        (call $assert_eq_i32 (i32.const 168) (i32.const 0) (call $resize (i32.const 1024) (i32.const 16384) (i32.const 2)))
+        (call $assert_eq_i32 (i32.const 169) (i32.const 3) (call $get_old_size))
+        (call $assert_eq_i32 (i32.const 170) (i32.const 1) (call $resize (i32.const 1024) (i32.const 16384) (i32.const 3)))
+
+        ;; the old gen is full, so objects old enough to promote stay young
+        (i32.store (local.get $root) (call $alloc (i32.const 0) (i32.const 1) (i32.const 0)))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (call $const_tenure_gc_age)))
+            (call $gc_fast)
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (call $assert_ptr_in_region (i32.const 171) (i32.load (local.get $root)) (call $region_young))
+        (call $assert_eq_i32 (i32.const 172) (i32.const 3) (call $get_old_size))
+        (call $assert_eq_i32 (i32.const 173) (i32.const 11) (i32.load (i32.load (i32.add (local.get $root) (i32.const 4)))))
+    )
+
+    (func $test_resize_then_gc_full (export "test_resize_then_gc_full")
+            (local $root i32)
+            (local $node i32)
+            (local $i i32)
+
+        ;; the old gen can be bigger than a young half, since the break table has its own space
+        (call $assert_eq_i32 (i32.const 174) (i32.const 1) (call $resize (i32.const 1024) (i32.const 1024) (i32.const 4096)))
+        (call $assert_eq_i32 (i32.const 175) (i32.const 4096) (call $old_max_size))
+
+        ;; promote a list of 1500 small objects, whose break table is bigger than a young half
+        ;; (collecting every 100 objects, so that the young half never fills up)
+        (drop (call $stack_push))
+        (local.set $root (call $alloc_stack (i32.const 1) (i32.const 0)))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (i32.const 1500)))
+            (if (i32.eqz (i32.rem_u (local.get $i) (i32.const 100))) (then
+                (call $gc_fast) ))
+            (local.set $node (call $alloc (i32.const 1) (i32.const 0) (i32.const 0)))
+            (i32.store (local.get $node) (i32.load (local.get $root)))
+            (i32.store (local.get $root) (local.get $node))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (call $const_tenure_gc_age)))
+            (call $gc_fast)
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (call $assert_eq_i32 (i32.const 176) (i32.const 3000) (call $get_old_size))
+
+        ;; all of them are live, then drop the newest half and compact the rest
+        (call $gc_full)
+        (call $assert_eq_i32 (i32.const 177) (i32.const 3000) (call $get_old_size))
+        (local.set $node (i32.load (local.get $root)))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.ge_u (local.get $i) (i32.const 750)))
+            (local.set $node (i32.load (local.get $node)))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (i32.store (local.get $root) (local.get $node))
+        (call $gc_full)
+        (call $assert_eq_i32 (i32.const 178) (i32.const 1500) (call $get_old_size))
+        (local.set $node (i32.load (local.get $root)))
+        (local.set $i (i32.const 0))
+        (block $outer (loop $continue
+            (br_if $outer (i32.eqz (local.get $node)))
+            (call $assert_ptr_in_region (i32.const 179) (local.get $node) (call $region_old))
+            (local.set $node (i32.load (local.get $node)))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            br $continue
+        ))
+        (call $assert_eq_i32 (i32.const 180) (i32.const 750) (local.get $i))
+    )
 )
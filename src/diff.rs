@@ -0,0 +1,654 @@
+//! Run the same script of operations on gc.rs and gc.wat, and report the first step where they
+//! disagree, with the shortest script that still reproduces it.
+//!
+//! Every allocated object is stored in a new root slot (a one-pointer stack object) in the current
+//! frame, and its first data word is set to the object's index in the script, so that objects can
+//! be recognized after they are moved. Roots are referred to by their index among all live roots.
+
+use ::std::collections::BTreeMap;
+use ::std::collections::HashSet;
+use ::std::fmt;
+use ::std::panic;
+use ::std::panic::AssertUnwindSafe;
+
+use ::wasmer::Value;
+
+use crate::gc;
+use crate::gc::GcConf;
+use crate::gc::Pointer;
+use crate::gc::WordSize;
+use crate::wasm_prog::WasmProg;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Alloc { pointer_cnt: i32, data_size_32: i32, pointers_mutable: bool },
+    Write { root: usize, field: i32, target: Option<usize> },
+    Push,
+    Pop,
+    GcFast,
+    GcFull,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Alloc { pointer_cnt, data_size_32, pointers_mutable } =>
+                write!(f, "alloc {pointer_cnt} {data_size_32} {}", if *pointers_mutable { "mut" } else { "imm" }),
+            Op::Write { root, field, target: Some(target) } => write!(f, "write {root} {field} {target}"),
+            Op::Write { root, field, target: None } => write!(f, "write {root} {field} null"),
+            Op::Push => write!(f, "push"),
+            Op::Pop => write!(f, "pop"),
+            Op::GcFast => write!(f, "gc_fast"),
+            Op::GcFull => write!(f, "gc_full"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Script {
+    pub ops: Vec<Op>,
+}
+
+impl Script {
+    /// One op per line, in the format of `Display`; empty lines and lines starting with `#` are skipped
+    pub fn parse(text: &str) -> Result<Script, String> {
+        let mut ops = Vec::new();
+        for (line_ix, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let int = |word: &str| word.parse::<i32>()
+                .map_err(|_| format!("line {}: expected a number, got '{word}'", line_ix + 1));
+            let ix = |word: &str| word.parse::<usize>()
+                .map_err(|_| format!("line {}: expected a root index, got '{word}'", line_ix + 1));
+            ops.push(match words.as_slice() {
+                ["alloc", pointer_cnt, data_size_32, "mut"] =>
+                    Op::Alloc { pointer_cnt: int(pointer_cnt)?, data_size_32: int(data_size_32)?, pointers_mutable: true },
+                ["alloc", pointer_cnt, data_size_32, "imm"] =>
+                    Op::Alloc { pointer_cnt: int(pointer_cnt)?, data_size_32: int(data_size_32)?, pointers_mutable: false },
+                ["write", root, field, "null"] => Op::Write { root: ix(root)?, field: int(field)?, target: None },
+                ["write", root, field, target] => Op::Write { root: ix(root)?, field: int(field)?, target: Some(ix(target)?) },
+                ["push"] => Op::Push,
+                ["pop"] => Op::Pop,
+                ["gc_fast"] => Op::GcFast,
+                ["gc_full"] => Op::GcFull,
+                _ => return Err(format!("line {}: unknown operation '{line}'", line_ix + 1)),
+            });
+        }
+        Ok(Script { ops })
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for op in &self.ops {
+            writeln!(f, "{op}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The operations the harness needs from a GC implementation. Errors are traps or panics, and
+/// allocations return 0 when out of memory.
+pub trait GcImpl {
+    fn name(&self) -> &str;
+    /// Discard all state, so the next script starts from an empty heap
+    fn reset(&mut self);
+    fn alloc0(&mut self, pointer_cnt: i32, data_size_32: i32, pointers_mutable: bool) -> Result<i32, String>;
+    fn stack_push(&mut self) -> Result<i32, String>;
+    fn stack_pop(&mut self, frame: i32) -> Result<(), String>;
+    fn stack_alloc0(&mut self, pointer_cnt: i32, data_size_32: i32) -> Result<i32, String>;
+    fn gc_fast(&mut self) -> Result<(), String>;
+    fn gc_full(&mut self) -> Result<(), String>;
+    /// Words of pointers and data in the young heap, without headers, since those are encoded differently
+    fn young_field_size(&mut self) -> i32;
+    fn read(&mut self, addr: i32) -> i32;
+    fn write_word(&mut self, addr: i32, value: i32);
+    fn write_pointer(&mut self, addr: i32, value: i32);
+}
+
+/// gc.rs, run natively in this process; panics are reported as traps
+pub struct NativeGc;
+
+fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|err| {
+        err.downcast_ref::<String>().cloned()
+            .or_else(|| err.downcast_ref::<&str>().map(|msg| msg.to_string()))
+            .unwrap_or_else(|| "panic".to_owned())
+    })
+}
+
+impl GcImpl for NativeGc {
+    fn name(&self) -> &str {
+        "gc.rs"
+    }
+
+    fn reset(&mut self) {
+        gc::init(GcConf::builder().build().expect("default conf must be valid"));
+        // stack objects are only roots if they are in a frame
+        gc::stack_frame_push();
+    }
+
+    fn alloc0(&mut self, pointer_cnt: i32, data_size_32: i32, pointers_mutable: bool) -> Result<i32, String> {
+        catch_panic(|| gc::alloc0_heap(WordSize::new(pointer_cnt), WordSize::new(pointer_cnt + data_size_32), pointers_mutable)
+            .map(Pointer::as_data)
+            .unwrap_or(0))
+    }
+
+    fn stack_push(&mut self) -> Result<i32, String> {
+        catch_panic(|| gc::stack_frame_push().as_data())
+    }
+
+    fn stack_pop(&mut self, frame: i32) -> Result<(), String> {
+        catch_panic(|| gc::stack_frame_pop_to(Pointer::from_data(frame)))
+    }
+
+    fn stack_alloc0(&mut self, pointer_cnt: i32, data_size_32: i32) -> Result<i32, String> {
+        catch_panic(|| gc::alloc0_stack(WordSize::new(pointer_cnt), WordSize::new(pointer_cnt + data_size_32))
+            .map(Pointer::as_data)
+            .unwrap_or(0))
+    }
+
+    fn gc_fast(&mut self) -> Result<(), String> {
+        catch_panic(|| { gc::collect_fast(); })
+    }
+
+    /// gc.wat's full collection ends with a young one, so do the same here
+    fn gc_full(&mut self) -> Result<(), String> {
+        catch_panic(|| {
+            gc::collect_full();
+            gc::collect_fast();
+        })
+    }
+
+    fn young_field_size(&mut self) -> i32 {
+        gc::young_heap_field_size().words()
+    }
+
+    fn read(&mut self, addr: i32) -> i32 {
+        gc::read_word(Pointer::from_data(addr))
+    }
+
+    fn write_word(&mut self, addr: i32, value: i32) {
+        gc::write_word(Pointer::from_data(addr), value)
+    }
+
+    fn write_pointer(&mut self, addr: i32, value: i32) {
+        gc::write_pointer(Pointer::from_data(addr), Pointer::from_data(value))
+    }
+}
+
+/// gc.wat (or any module with the same exports), run through `WasmProg`
+pub struct WatGc {
+    prog: WasmProg,
+}
+
+impl WatGc {
+    pub fn load(wat_pth: &str) -> Self {
+        Self::try_load(wat_pth).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    pub fn try_load(wat_pth: &str) -> Result<Self, String> {
+        Ok(WatGc { prog: WasmProg::try_load(wat_pth)? })
+    }
+
+    fn call_i32(&mut self, func: &str, args: &[i32]) -> Result<i32, String> {
+        let args = args.iter().map(|arg| Value::I32(*arg)).collect::<Vec<_>>();
+        let res = self.prog.call(func, &args)?;
+        Ok(res[0].unwrap_i32())
+    }
+
+    fn call_void(&mut self, func: &str, args: &[i32]) -> Result<(), String> {
+        let args = args.iter().map(|arg| Value::I32(*arg)).collect::<Vec<_>>();
+        self.prog.call(func, &args).map(|_| ())
+    }
+}
+
+impl GcImpl for WatGc {
+    fn name(&self) -> &str {
+        "gc.wat"
+    }
+
+    fn reset(&mut self) {
+        self.prog.reset()
+    }
+
+    fn alloc0(&mut self, pointer_cnt: i32, data_size_32: i32, pointers_mutable: bool) -> Result<i32, String> {
+        self.call_i32("alloc0", &[pointer_cnt, data_size_32, pointers_mutable as i32])
+    }
+
+    fn stack_push(&mut self) -> Result<i32, String> {
+        self.call_i32("stack_push", &[])
+    }
+
+    fn stack_pop(&mut self, frame: i32) -> Result<(), String> {
+        self.call_void("stack_pop", &[frame])
+    }
+
+    fn stack_alloc0(&mut self, pointer_cnt: i32, data_size_32: i32) -> Result<i32, String> {
+        self.call_i32("stack_alloc0", &[pointer_cnt, data_size_32])
+    }
+
+    fn gc_fast(&mut self) -> Result<(), String> {
+        self.call_void("gc_fast", &[])
+    }
+
+    fn gc_full(&mut self) -> Result<(), String> {
+        self.call_void("gc_full", &[])
+    }
+
+    fn young_field_size(&mut self) -> i32 {
+        self.prog.young_field_size().expect("young heap metadata should be readable") as i32
+    }
+
+    fn read(&mut self, addr: i32) -> i32 {
+        self.prog.read_i32(addr)
+    }
+
+    fn write_word(&mut self, addr: i32, value: i32) {
+        self.prog.write_i32(addr, value)
+    }
+
+    fn write_pointer(&mut self, addr: i32, value: i32) {
+        self.call_void("write_pointer", &[addr, value]).expect("write_pointer should not trap")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    OutOfMemory,
+    Trap,
+}
+
+/// Objects reachable from the roots, by the index stored in their first data word. Fields are
+/// `None` for null, objects with an unexpected index are recorded but not followed, and pointers
+/// where null was expected are recorded as -1.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Graph {
+    pub roots: Vec<Option<i32>>,
+    pub objects: BTreeMap<i32, Vec<Option<i32>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    Outcome { left: Outcome, right: Outcome },
+    /// Words of pointers and data in the young heap, without headers
+    YoungFieldSize { left: i32, right: i32 },
+    Graph { left: Graph, right: Graph },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub left: String,
+    pub right: String,
+    /// Index of the op after which the implementations disagreed
+    pub step: usize,
+    pub mismatch: Mismatch,
+    /// Shortest script found that still diverges, ending with the diverging op
+    pub script: Script,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.mismatch {
+            Mismatch::Outcome { left, right } =>
+                writeln!(f, "outcome differs: {left:?} in {} vs {right:?} in {}", self.left, self.right)?,
+            Mismatch::YoungFieldSize { left, right } =>
+                writeln!(f, "young heap size (without headers) differs: {left} in {} vs {right} in {}", self.left, self.right)?,
+            Mismatch::Graph { left, right } =>
+                writeln!(f, "reachable graph differs:\n  {}: {left:?}\n  {}: {right:?}", self.left, self.right)?,
+        }
+        writeln!(f, "minimal script ({} ops, diverges at the last):", self.script.ops.len())?;
+        write!(f, "{}", self.script)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompareError {
+    /// The op at `step` is not valid at that point, e.g. it refers to a root that does not exist
+    InvalidScript { step: usize, op: Op },
+    /// Boxed because it contains the minimal script, which makes it large
+    Diverged(Box<Divergence>),
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompareError::InvalidScript { step, op } => write!(f, "op {step} ('{op}') is not valid at that point in the script"),
+            CompareError::Diverged(divergence) => write!(f, "{divergence}"),
+        }
+    }
+}
+
+/// Shape of the objects and roots, the same for both implementations
+#[derive(Debug, Default)]
+struct Model {
+    pointer_cnts: Vec<i32>,
+    /// Object index that each pointer field should point to, per object
+    fields: Vec<Vec<Option<usize>>>,
+    /// Object index per root, per frame; the first frame cannot be popped
+    frames: Vec<Vec<usize>>,
+}
+
+impl Model {
+    fn new() -> Self {
+        Model { pointer_cnts: Vec::new(), fields: Vec::new(), frames: vec![Vec::new()] }
+    }
+
+    fn roots(&self) -> impl Iterator<Item=usize> + '_ {
+        self.frames.iter().flatten().copied()
+    }
+
+    fn root_cnt(&self) -> usize {
+        self.frames.iter().map(|frame| frame.len()).sum()
+    }
+
+    fn is_valid(&self, op: Op) -> bool {
+        match op {
+            Op::Alloc { pointer_cnt, data_size_32, pointers_mutable } =>
+                pointer_cnt >= 0 && data_size_32 >= 1 && (pointer_cnt > 0 || !pointers_mutable),
+            Op::Write { root, field, target } => root < self.root_cnt()
+                && field >= 0 && field < self.pointer_cnts[self.roots().nth(root).unwrap()]
+                && target.is_none_or(|target| target < self.root_cnt()),
+            Op::Pop => self.frames.len() > 1,
+            Op::Push | Op::GcFast | Op::GcFull => true,
+        }
+    }
+}
+
+/// Addresses of one implementation's frames and root slots, parallel to `Model::frames`
+struct Runner<'a> {
+    gc: &'a mut dyn GcImpl,
+    frames: Vec<(i32, Vec<i32>)>,
+}
+
+impl<'a> Runner<'a> {
+    fn new(gc: &'a mut dyn GcImpl) -> Self {
+        gc.reset();
+        Runner { gc, frames: vec![(0, Vec::new())] }
+    }
+
+    fn root_slot(&self, root: usize) -> i32 {
+        *self.frames.iter().flat_map(|(_, slots)| slots).nth(root).unwrap()
+    }
+
+    fn apply(&mut self, model: &Model, op: Op) -> Result<Outcome, String> {
+        match op {
+            Op::Alloc { pointer_cnt, data_size_32, pointers_mutable } => {
+                // slot first, because heap allocation may collect garbage
+                let slot = self.gc.stack_alloc0(1, 0)?;
+                if slot == 0 {
+                    return Ok(Outcome::OutOfMemory)
+                }
+                self.gc.write_pointer(slot, 0);
+                self.frames.last_mut().unwrap().1.push(slot);
+                let obj = self.gc.alloc0(pointer_cnt, data_size_32, pointers_mutable)?;
+                if obj == 0 {
+                    return Ok(Outcome::OutOfMemory)
+                }
+                for field in 0 .. pointer_cnt {
+                    self.gc.write_pointer(obj + 4 * field, 0);
+                }
+                self.gc.write_word(obj + 4 * pointer_cnt, model.pointer_cnts.len() as i32);
+                self.gc.write_pointer(slot, obj);
+            }
+            Op::Write { root, field, target } => {
+                let obj = self.gc.read(self.root_slot(root));
+                let value = target.map(|target| self.gc.read(self.root_slot(target))).unwrap_or(0);
+                self.gc.write_pointer(obj + 4 * field, value);
+            }
+            Op::Push => {
+                let frame = self.gc.stack_push()?;
+                self.frames.push((frame, Vec::new()));
+            }
+            Op::Pop => {
+                let (frame, _) = self.frames.pop().unwrap();
+                self.gc.stack_pop(frame)?;
+            }
+            Op::GcFast => self.gc.gc_fast()?,
+            Op::GcFull => self.gc.gc_full()?,
+        }
+        Ok(Outcome::Done)
+    }
+
+    /// Undo the root slot of an allocation that ran out of heap memory, so both sides have the same roots
+    fn forget_failed_alloc(&mut self, model: &Model) {
+        let frame = &mut self.frames.last_mut().unwrap().1;
+        if frame.len() > model.frames.last().unwrap().len() {
+            frame.pop();
+        }
+    }
+
+    fn graph(&mut self, model: &Model) -> Graph {
+        let mut graph = Graph::default();
+        let mut seen = HashSet::new();
+        let roots = model.roots().collect::<Vec<_>>();
+        for (root, expected) in roots.into_iter().enumerate() {
+            let addr = self.gc.read(self.root_slot(root));
+            let id = self.walk(model, addr, Some(expected), &mut graph, &mut seen);
+            graph.roots.push(id);
+        }
+        graph
+    }
+
+    /// The model's shape of the expected object is used to find the index, so if the pointer is
+    /// wrong, the index will most likely not match.
+    fn walk(&mut self, model: &Model, addr: i32, expected: Option<usize>, graph: &mut Graph, seen: &mut HashSet<i32>) -> Option<i32> {
+        if addr == 0 {
+            return None
+        }
+        let Some(expected) = expected else {
+            return Some(-1)
+        };
+        let pointer_cnt = model.pointer_cnts[expected];
+        let id = self.gc.read(addr + 4 * pointer_cnt);
+        if id != expected as i32 || !seen.insert(addr) {
+            return Some(id)
+        }
+        let mut fields = Vec::new();
+        for field in 0 .. pointer_cnt {
+            let target = self.gc.read(addr + 4 * field);
+            fields.push(self.walk(model, target, model.fields[expected][field as usize], graph, seen));
+        }
+        graph.objects.insert(id, fields);
+        Some(id)
+    }
+}
+
+/// Run the script on both, returning the first step where outcome, young heap size (without
+/// headers) or reachable graph differ. Returns `Err` if the script is invalid, e.g. refers to a root that does not exist.
+fn run_once(script: &Script, left: &mut dyn GcImpl, right: &mut dyn GcImpl) -> Result<Option<(usize, Mismatch)>, usize> {
+    let mut model = Model::new();
+    let mut left = Runner::new(left);
+    let mut right = Runner::new(right);
+    for (step, &op) in script.ops.iter().enumerate() {
+        if !model.is_valid(op) {
+            return Err(step)
+        }
+        let left_outcome = left.apply(&model, op).unwrap_or(Outcome::Trap);
+        let right_outcome = right.apply(&model, op).unwrap_or(Outcome::Trap);
+        if left_outcome != right_outcome {
+            return Ok(Some((step, Mismatch::Outcome { left: left_outcome, right: right_outcome })))
+        }
+        match (left_outcome, op) {
+            (Outcome::Trap, _) => return Ok(None),
+            (Outcome::Done, Op::Alloc { pointer_cnt, .. }) => {
+                model.pointer_cnts.push(pointer_cnt);
+                model.fields.push(vec![None; pointer_cnt as usize]);
+                let id = model.pointer_cnts.len() - 1;
+                model.frames.last_mut().unwrap().push(id);
+            }
+            (Outcome::OutOfMemory, _) => {
+                left.forget_failed_alloc(&model);
+                right.forget_failed_alloc(&model);
+            }
+            (_, Op::Write { root, field, target }) => {
+                let obj = model.roots().nth(root).unwrap();
+                model.fields[obj][field as usize] = target.map(|target| model.roots().nth(target).unwrap());
+            }
+            (_, Op::Push) => model.frames.push(Vec::new()),
+            (_, Op::Pop) => { model.frames.pop(); }
+            _ => {}
+        }
+        let (left_size, right_size) = (left.gc.young_field_size(), right.gc.young_field_size());
+        if left_size != right_size {
+            return Ok(Some((step, Mismatch::YoungFieldSize { left: left_size, right: right_size })))
+        }
+        let (left_graph, right_graph) = (left.graph(&model), right.graph(&model));
+        if left_graph != right_graph {
+            return Ok(Some((step, Mismatch::Graph { left: left_graph, right: right_graph })))
+        }
+    }
+    Ok(None)
+}
+
+/// Run `script` on both implementations, and if they diverge, shrink the script by dropping ops
+/// for as long as it still diverges in the same way.
+pub fn compare(script: &Script, left: &mut dyn GcImpl, right: &mut dyn GcImpl) -> Result<(), CompareError> {
+    let (step, mismatch) = match run_once(script, left, right) {
+        Ok(Some(found)) => found,
+        Ok(None) => return Ok(()),
+        Err(step) => return Err(CompareError::InvalidScript { step, op: script.ops[step] }),
+    };
+    let same_kind = |found: &Mismatch| ::std::mem::discriminant(found) == ::std::mem::discriminant(&mismatch);
+    let mut minimal = Script { ops: script.ops[..=step].to_vec() };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut ix = 0;
+        while ix < minimal.ops.len() {
+            let mut candidate = minimal.clone();
+            candidate.ops.remove(ix);
+            match run_once(&candidate, left, right) {
+                Ok(Some((cand_step, found))) if same_kind(&found) => {
+                    candidate.ops.truncate(cand_step + 1);
+                    minimal = candidate;
+                    changed = true;
+                }
+                _ => ix += 1,
+            }
+        }
+    }
+    let Ok(Some((_, mismatch))) = run_once(&minimal, left, right) else {
+        unreachable!("minimized script should still diverge")
+    };
+    Err(CompareError::Diverged(Box::new(Divergence { left: left.name().to_owned(), right: right.name().to_owned(), step, mismatch, script: minimal })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bump allocator with gc.rs-sized headers that never collects, as a stand-in for gc.wat
+    struct LeakGc {
+        mem: Vec<i32>,
+        stack_top: i32,
+        young_top: i32,
+        young_field_size: i32,
+    }
+
+    const LEAK_YOUNG_START: i32 = 4096;
+
+    impl LeakGc {
+        fn new() -> Self {
+            LeakGc { mem: Vec::new(), stack_top: 0, young_top: 0, young_field_size: 0 }
+        }
+    }
+
+    impl GcImpl for LeakGc {
+        fn name(&self) -> &str {
+            "leak"
+        }
+
+        fn reset(&mut self) {
+            self.mem = vec![0; 8192];
+            self.stack_top = 8;
+            self.young_top = LEAK_YOUNG_START;
+            self.young_field_size = 0;
+        }
+
+        fn alloc0(&mut self, pointer_cnt: i32, data_size_32: i32, _pointers_mutable: bool) -> Result<i32, String> {
+            self.young_top += 4 * (1 + pointer_cnt + data_size_32);
+            self.young_field_size += pointer_cnt + data_size_32;
+            Ok(self.young_top - 4 * (pointer_cnt + data_size_32))
+        }
+
+        fn stack_push(&mut self) -> Result<i32, String> {
+            self.stack_top += 4;
+            Ok(self.stack_top - 4)
+        }
+
+        fn stack_pop(&mut self, frame: i32) -> Result<(), String> {
+            self.stack_top = frame;
+            Ok(())
+        }
+
+        fn stack_alloc0(&mut self, pointer_cnt: i32, data_size_32: i32) -> Result<i32, String> {
+            self.stack_top += 4 * (1 + pointer_cnt + data_size_32);
+            Ok(self.stack_top - 4 * (pointer_cnt + data_size_32))
+        }
+
+        fn gc_fast(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn gc_full(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn young_field_size(&mut self) -> i32 {
+            self.young_field_size
+        }
+
+        fn read(&mut self, addr: i32) -> i32 {
+            self.mem[addr as usize / 4]
+        }
+
+        fn write_word(&mut self, addr: i32, value: i32) {
+            self.mem[addr as usize / 4] = value
+        }
+
+        fn write_pointer(&mut self, addr: i32, value: i32) {
+            self.mem[addr as usize / 4] = value
+        }
+    }
+
+    #[test]
+    fn parse_and_display_roundtrip() {
+        let text = "alloc 2 1 mut\nalloc 0 3 imm\nwrite 0 1 1\nwrite 0 0 null\npush\npop\ngc_fast\ngc_full\n";
+        let script = Script::parse(&format!("# comment\n\n{text}")).unwrap();
+        assert_eq!(script.ops.len(), 8);
+        assert_eq!(script.ops[2], Op::Write { root: 0, field: 1, target: Some(1) });
+        assert_eq!(script.to_string(), text);
+        assert!(Script::parse("alloc 1 x imm").is_err());
+    }
+
+    #[test]
+    fn same_when_nothing_is_collected() {
+        let script = Script::parse("alloc 2 1 mut\nalloc 0 1 imm\nwrite 0 1 1\npush\nalloc 1 2 imm\nwrite 2 0 0\npop\n").unwrap();
+        assert_eq!(compare(&script, &mut NativeGc, &mut LeakGc::new()), Ok(()));
+    }
+
+    #[test]
+    fn collecting_garbage_diverges_with_minimal_script() {
+        let script = Script::parse("alloc 1 1 mut\npush\nalloc 0 1 imm\nalloc 0 4 imm\nwrite 0 0 1\npop\nalloc 0 1 imm\ngc_fast\ngc_full\n").unwrap();
+        let Err(CompareError::Diverged(divergence)) = compare(&script, &mut NativeGc, &mut LeakGc::new()) else {
+            panic!("should diverge")
+        };
+        assert_eq!(divergence.step, 7);
+        assert!(matches!(divergence.mismatch, Mismatch::YoungFieldSize { .. }));
+        assert_eq!(divergence.script.to_string(), "push\nalloc 0 4 imm\npop\ngc_fast\n");
+    }
+
+    #[test]
+    fn invalid_ops_are_rejected() {
+        let script = Script::parse("write 0 0 null\n").unwrap();
+        assert_eq!(run_once(&script, &mut NativeGc, &mut LeakGc::new()), Err(0));
+        let script = Script::parse("alloc 1 1 imm\nwrite 0 0 3\n").unwrap();
+        assert_eq!(compare(&script, &mut NativeGc, &mut LeakGc::new()),
+            Err(CompareError::InvalidScript { step: 1, op: Op::Write { root: 0, field: 0, target: Some(3) } }));
+    }
+}
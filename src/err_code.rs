@@ -0,0 +1,122 @@
+//! Codes that gc.wat (and gc.rs compiled to wasm) pass to the host's `log_err_code` right before
+//! trapping, so the host can say what went wrong.
+
+use ::std::fmt;
+use ::std::fmt::Formatter;
+
+/// Codes from this number up are failed checks in the WAT tests, numbered per check
+const FIRST_TEST_CODE: i32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrCode {
+    OutOfMemory,
+    /// `stack_pop` was given a frame above the current stack top
+    StackPopGrows,
+    /// `stack_pop` was given a frame below the start of the stack
+    NegativeFrame,
+    StackOverflow,
+    TooManyPointers,
+    TooMuchData,
+    BadMetadataType,
+    /// Objects without pointers cannot have mutable pointers
+    MutableWithoutPointers,
+    NotAnArray,
+    /// `alloc_gc_enable` without matching `alloc_gc_disable`
+    UnbalancedGcEnable,
+    /// The inactive young half is too small for the mark stack or break table of `gc_full`
+    GcScratchTooSmall,
+    /// `gc_full` found a pointer to an old object that was not marked, so the heap is corrupt
+    UnmarkedObject,
+    TestFailed(i32),
+    Unknown(i32),
+}
+
+impl ErrCode {
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            1 => ErrCode::OutOfMemory,
+            4 => ErrCode::StackPopGrows,
+            5 => ErrCode::NegativeFrame,
+            6 => ErrCode::StackOverflow,
+            7 => ErrCode::TooManyPointers,
+            8 => ErrCode::TooMuchData,
+            11 => ErrCode::BadMetadataType,
+            12 => ErrCode::MutableWithoutPointers,
+            13 => ErrCode::NotAnArray,
+            14 => ErrCode::UnbalancedGcEnable,
+            15 => ErrCode::GcScratchTooSmall,
+            16 => ErrCode::UnmarkedObject,
+            code if code >= FIRST_TEST_CODE => ErrCode::TestFailed(code),
+            code => ErrCode::Unknown(code),
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        match self {
+            ErrCode::OutOfMemory => 1,
+            ErrCode::StackPopGrows => 4,
+            ErrCode::NegativeFrame => 5,
+            ErrCode::StackOverflow => 6,
+            ErrCode::TooManyPointers => 7,
+            ErrCode::TooMuchData => 8,
+            ErrCode::BadMetadataType => 11,
+            ErrCode::MutableWithoutPointers => 12,
+            ErrCode::NotAnArray => 13,
+            ErrCode::UnbalancedGcEnable => 14,
+            ErrCode::GcScratchTooSmall => 15,
+            ErrCode::UnmarkedObject => 16,
+            ErrCode::TestFailed(code) => code,
+            ErrCode::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for ErrCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrCode::OutOfMemory => write!(f, "out of heap memory"),
+            ErrCode::StackPopGrows => write!(f, "stack pop to a frame above the stack top"),
+            ErrCode::NegativeFrame => write!(f, "stack pop to a frame below the stack start"),
+            ErrCode::StackOverflow => write!(f, "stack overflow"),
+            ErrCode::TooManyPointers => write!(f, "struct has too many pointers"),
+            ErrCode::TooMuchData => write!(f, "struct has too much data"),
+            ErrCode::BadMetadataType => write!(f, "object header has an invalid type"),
+            ErrCode::MutableWithoutPointers => write!(f, "object without pointers cannot have mutable pointers"),
+            ErrCode::NotAnArray => write!(f, "array length of an object that is not an array"),
+            ErrCode::UnbalancedGcEnable => write!(f, "alloc_gc_enable without alloc_gc_disable"),
+            ErrCode::GcScratchTooSmall => write!(f, "young heap is too small for full collection bookkeeping"),
+            ErrCode::UnmarkedObject => write!(f, "pointer to an unmarked object during full collection"),
+            ErrCode::TestFailed(code) => write!(f, "test check {} failed", code),
+            ErrCode::Unknown(code) => write!(f, "unknown error code {}", code),
+        }?;
+        write!(f, " (code {})", self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_roundtrip() {
+        for code in 0 .. 140 {
+            assert_eq!(ErrCode::from_code(code).code(), code);
+        }
+        assert_eq!(ErrCode::from_code(6), ErrCode::StackOverflow);
+        assert_eq!(ErrCode::from_code(104).to_string(), "test check 104 failed (code 104)");
+    }
+
+    #[test]
+    fn gc_wat_uses_known_codes() {
+        let wat = include_str!("../gc.wat");
+        let marker = "(call $log_err_code (i32.const ";
+        let mut found = 0;
+        for (ix, _) in wat.match_indices(marker) {
+            let rest = &wat[ix + marker.len() ..];
+            let code = rest[.. rest.find(')').unwrap()].parse::<i32>().unwrap();
+            assert!(!matches!(ErrCode::from_code(code), ErrCode::Unknown(_)), "gc.wat uses unknown error code {code}");
+            found += 1;
+        }
+        assert!(found > 10);
+    }
+}
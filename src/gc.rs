@@ -1,8 +1,12 @@
 #![allow(unused)]  //TODO @mark:
 
 use ::std::cell::RefCell;
+use ::std::collections::HashSet;
 use ::std::fmt;
 use ::std::fmt::Formatter;
+use ::std::fmt::Write;
+use ::std::fs;
+use ::std::io;
 use ::std::mem::size_of;
 use ::std::ops::Add;
 use ::std::ops::Index;
@@ -11,6 +15,8 @@ use ::std::ops::Mul;
 use ::std::ops::Sub;
 use ::std::io::SeekFrom::Start;
 use ::std::ops::Range;
+use ::std::path::Path;
+use ::std::rc::Rc;
 
 type Nr = i32;
 
@@ -18,11 +24,28 @@ const WORD_SIZE: ByteSize = ByteSize(4);
 const START_FLAG_OFFSET_BITS: u8 = 8;
 const GC_REACHABLE_FLAG_BIT: u8 = 7;
 const POINTER_MUTABLE_FLAG_BIT: u8 = 6;
+/// Young objects that survive this many fast collections are promoted to the old heap
+const TENURE_GC_AGE: Nr = 3;
+/// Mutable old memory and large object memory are divided into cards, which are marked dirty when
+/// a pointer in them is written, so that `collect_fast` only needs to scan those for references to young data
+const CARD_SIZE: ByteSize = ByteSize(128);
+const CARD_CLEAN: Nr = 0;
+const CARD_DIRTY: Nr = 1;
+/// Large objects are in blocks, which start with a word containing the block length and whether it is in use
+const LARGE_BLOCK_USED: Nr = 1;
+/// Minimum capacity of the stack, each young side and the metadata region
+const MIN_REGION_CAPACITY: WordSize = WordSize(16);
+
+/// Objects too large for a one-word header get a two-word one: a size word followed by the usual
+/// kind and flags word. The size word has this as its lowest byte, and the kind byte has this bit,
+/// so the header length can be found from the first word (walking a region) and from the last word
+/// (following a pointer, which points right after the header).
+const BIG_HEADER_MARK: u8 = 0x10;
+/// Pointer count in a big header when all fields are pointers, so it does not have to fit in 16 bits
+const BIG_HEADER_ALL_POINTERS: u16 = u16::MAX;
 
 // TODO how to handle 0-byte allocations? is there reference equality anywhere?
 // TODO have some post-GC handler?
-// TODO we need to read headers from end (following roots) and from start (compacting old heap), but they are variable length, so must be able to know the length from first and from last byte
-//   TODO ^ would it be easier to just return pointer to second word, and e.g. put array length there?
 
 #[derive(Debug)]
 struct StackHeader {
@@ -38,21 +61,78 @@ enum HeaderEnc { Small(Nr), Big(Nr, Nr) }
 impl HeaderEnc {
     fn of_struct(flags: u8, pointer_cnt: WordSize, size_32: WordSize, kind: DataKind) -> Self {
         debug_assert!(pointer_cnt <= size_32, "pointer size cannot exceed total size");
-        let size_32_u8: u8 = size_32.0.try_into().unwrap_or_else(|_| panic!("struct too large: {size_32}"));
-        let pointer_cnt_u8: u8 = pointer_cnt.0.try_into().expect("should never be reached since pointer <= total");
-        HeaderEnc::Small(i32::from_le_bytes([
-            kind.to_u8(),
-            flags,
-            pointer_cnt_u8,
-            size_32_u8,
-        ]))
+        if let (Ok(size_32_u8), Ok(pointer_cnt_u8)) = (u8::try_from(size_32.0), u8::try_from(pointer_cnt.0)) {
+            return HeaderEnc::Small(i32::from_le_bytes([
+                kind.to_u8(),
+                flags,
+                pointer_cnt_u8,
+                size_32_u8,
+            ]))
+        }
+        if let Err(err) = check_object_size(pointer_cnt, size_32) {
+            panic!("{err}")
+        }
+        let pointer_cnt_u16 = if pointer_cnt == size_32 {
+            BIG_HEADER_ALL_POINTERS
+        } else {
+            pointer_cnt.0 as u16
+        };
+        let [pointer_cnt_lo, pointer_cnt_hi] = pointer_cnt_u16.to_le_bytes();
+        HeaderEnc::Big(
+            (size_32.0 << 8) | BIG_HEADER_MARK as Nr,
+            i32::from_le_bytes([
+                kind.to_u8() | BIG_HEADER_MARK,
+                flags,
+                pointer_cnt_lo,
+                pointer_cnt_hi,
+            ]))
+    }
+
+    /// Arrays use the same layout as structs, with the element count as size, which
+    /// is also the pointer count if the elements are pointers (and 0 otherwise).
+    fn decode(self) -> (DataKind, u8, WordSize, WordSize) {
+        match self {
+            HeaderEnc::Small(data) => {
+                let [typ, flags, pointer_cnt_u8, size_32_u8] = data.to_le_bytes();
+                debug_assert!(DataKind::try_as_forward(typ as Nr).is_none(), "not a type, found GC forward");
+                let kind = DataKind::from_u8(typ);
+                debug_assert!(pointer_cnt_u8 == 0 || pointer_cnt_u8 == size_32_u8 || kind == DataKind::Struct, "array elements must be all pointers or all data");
+                (kind, flags, WordSize(pointer_cnt_u8.into()), WordSize(size_32_u8.into()))
+            }
+            HeaderEnc::Big(size_data, data) => {
+                let [typ, flags, pointer_cnt_lo, pointer_cnt_hi] = data.to_le_bytes();
+                debug_assert!(DataKind::try_as_forward(typ as Nr).is_none(), "not a type, found GC forward");
+                debug_assert!(size_data & 0xFF == BIG_HEADER_MARK as Nr, "big header without size word");
+                let kind = DataKind::from_u8(typ & !BIG_HEADER_MARK);
+                let size_32 = WordSize(size_data >> 8);
+                let pointer_cnt = match u16::from_le_bytes([pointer_cnt_lo, pointer_cnt_hi]) {
+                    BIG_HEADER_ALL_POINTERS => size_32,
+                    cnt => WordSize(cnt.into()),
+                };
+                (kind, flags, pointer_cnt, size_32)
+            }
+        }
     }
 
-    fn decode_struct(self, data: Nr) -> (u8, WordSize, WordSize) {
-        let [typ, flags, pointer_cnt_u8, size_32_u8] = data.to_le_bytes();
-        debug_assert!(DataKind::try_as_forward(typ as Nr).is_none(), "not a type, found GC forward");
-        debug_assert!(typ == DataKind::Struct.to_u8(), "unknown type {typ}");
-        (flags, WordSize(pointer_cnt_u8.into()), WordSize(size_32_u8.into()))
+    /// Read the header that starts at `header_ix`, e.g. when walking over a region
+    fn read_at(data: &Data, header_ix: Pointer) -> Self {
+        let first = data[header_ix];
+        if first & 0xFF == BIG_HEADER_MARK as Nr {
+            HeaderEnc::Big(first, data[header_ix + WORD_SIZE])
+        } else {
+            HeaderEnc::Small(first)
+        }
+    }
+
+    /// Read the header of the object at `pointer`, e.g. when following a reference.
+    /// The flags are always in the word right before the pointer (for either length).
+    fn read_before(data: &Data, pointer: Pointer) -> Self {
+        let last = data[pointer - WORD_SIZE];
+        if last & BIG_HEADER_MARK as Nr != 0 {
+            HeaderEnc::Big(data[pointer - WORD_SIZE * 2], last)
+        } else {
+            HeaderEnc::Small(last)
+        }
     }
 
     fn len(self) -> ByteSize {
@@ -79,6 +159,14 @@ fn mark_reachable(header: &mut Nr) {
     *header |= mask(true, GC_REACHABLE_FLAG_BIT + START_FLAG_OFFSET_BITS);
 }
 
+fn clear_reachable(header: &mut Nr) {
+    *header &= !mask(true, GC_REACHABLE_FLAG_BIT + START_FLAG_OFFSET_BITS);
+}
+
+fn is_reachable(header: Nr) -> bool {
+    header & mask(true, GC_REACHABLE_FLAG_BIT + START_FLAG_OFFSET_BITS) != 0
+}
+
 fn get_gc_age(header: Nr) -> i32 {
     // the number if in the lowest 3 bits of flag
     (header >> START_FLAG_OFFSET_BITS) & 0x7
@@ -99,10 +187,10 @@ impl StackHeader {
         HeaderEnc::of_struct(flags, self.pointer_cnt, self.size_32, self.data_kind)
     }
 
-    fn decode(data: Nr) -> Self {
-        let (flags, pointer_cnt, size_32) = HeaderEnc::Small(data).decode_struct(data);
+    fn decode(enc: HeaderEnc) -> Self {
+        let (data_kind, flags, pointer_cnt, size_32) = enc.decode();
         StackHeader {
-            data_kind: DataKind::Struct,
+            data_kind,
             pointer_cnt,
             size_32,
         }
@@ -110,18 +198,21 @@ impl StackHeader {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum DataKind { Struct, Array, Forward }
+pub(crate) enum DataKind { Struct, Array, Forward }
 //TODO @mark: dynamic dispatch?
-//TODO @mark: special kind for structs with more than 256 fields, and arrays of the same?
 
 impl DataKind {
     fn from_u8(byte: u8) -> Self {
+        Self::try_from_u8(byte).unwrap_or_else(|| panic!("not supported type nr: {byte}"))
+    }
+
+    fn try_from_u8(byte: u8) -> Option<Self> {
         // none of these except froward may use last 2 bits
         match byte {
-            4 => DataKind::Struct,
-            8 => DataKind::Array,
-            1 => DataKind::Forward,
-            nr => panic!("not supported type nr: {nr}"),
+            4 => Some(DataKind::Struct),
+            8 => Some(DataKind::Array),
+            1 => Some(DataKind::Forward),
+            _ => None,
         }
     }
 
@@ -147,12 +238,12 @@ fn new_forward(pointer: Pointer) -> Nr {
     pointer.0 | 0x1
 }
 
-#[derive(Debug)]
-struct YoungHeapHeader {
-    data_kind: DataKind,
-    pointers_mutable: bool,
-    pointer_cnt: WordSize,
-    size_32: WordSize,
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct YoungHeapHeader {
+    pub(crate) data_kind: DataKind,
+    pub(crate) pointers_mutable: bool,
+    pub(crate) pointer_cnt: WordSize,
+    pub(crate) size_32: WordSize,
 }
 
 const fn mask(is_on: bool, ix: u8) -> Nr {
@@ -176,38 +267,66 @@ impl YoungHeapHeader {
         HeaderEnc::of_struct(flags, self.pointer_cnt, self.size_32, self.data_kind)
     }
 
-    fn decode(data: Nr) -> Self {
-        let (flags, pointer_cnt, size_32) = HeaderEnc::Small(data).decode_struct(data);
+    fn decode(enc: HeaderEnc) -> Self {
+        let (data_kind, flags, pointer_cnt, size_32) = enc.decode();
         YoungHeapHeader {
-            data_kind: DataKind::Struct,
-            pointers_mutable: false,
+            data_kind,
+            pointers_mutable: flags & (1 << POINTER_MUTABLE_FLAG_BIT) != 0,
             pointer_cnt,
             size_32,
         }
     }
 }
 
+/// Same layout as the young header, but old objects do not track a GC age
 #[derive(Debug)]
-struct OldHeapHeader {}
+struct OldHeapHeader {
+    data_kind: DataKind,
+    pointers_mutable: bool,
+    pointer_cnt: WordSize,
+    size_32: WordSize,
+}
 
 impl OldHeapHeader {
     fn encode(self) -> HeaderEnc {
-        unimplemented!()  //TODO @mark: use u32 instead of Addr?
+        let mut flags: u8 = 0;
+        if self.pointers_mutable {
+            flags |= 1 << POINTER_MUTABLE_FLAG_BIT;
+        }
+        HeaderEnc::of_struct(flags, self.pointer_cnt, self.size_32, self.data_kind)
+    }
+
+    fn decode(enc: HeaderEnc) -> Self {
+        let (data_kind, flags, pointer_cnt, size_32) = enc.decode();
+        OldHeapHeader {
+            data_kind,
+            pointers_mutable: flags & (1 << POINTER_MUTABLE_FLAG_BIT) != 0,
+            pointer_cnt,
+            size_32,
+        }
     }
 }
 
 const OFFSET: Pointer = Pointer((size_of::<GcConf>() + size_of::<GcState>()) as Nr + WORD_SIZE.0);
 
-#[derive(Debug)]
-struct GcConf {
+/// Sizes of the memory regions, see `GcConf::builder` and `init`
+#[derive(Debug, Clone)]
+pub struct GcConf {
     stack_capacity: WordSize,
     young_side_capacity: WordSize,
-    old_capacity: WordSize,
+    old_mut_capacity: WordSize,
+    old_immut_capacity: WordSize,
+    large_capacity: WordSize,
+    /// Heap objects with at least this many fields (pointers and data) are allocated in the large object region
+    large_object_threshold: WordSize,
+    meta_capacity: WordSize,
+    /// Where the first region starts, which is chosen when memory is allocated, see `Data::allocate`
+    memory_start: Pointer,
 }
 
 impl GcConf {
     fn stack_start(&self) -> Pointer {
-        OFFSET
+        self.memory_start
     }
 
     fn stack_end(&self) -> Pointer {
@@ -229,16 +348,232 @@ impl GcConf {
         self.young_side_start(side) + self.young_side_capacity.bytes()
     }
 
-    fn old_start(&self) -> Pointer {
+    /// Old objects with mutable pointers, which can point to younger data, so are roots for `collect_fast`
+    fn old_mut_start(&self) -> Pointer {
         self.young_overall_start() + self.young_side_capacity.bytes() * 2
     }
 
-    fn old_end(&self) -> Pointer {
-        self.old_start() + self.old_capacity.bytes()
+    fn old_mut_end(&self) -> Pointer {
+        self.old_mut_start() + self.old_mut_capacity.bytes()
+    }
+
+    /// Old objects with immutable pointers, which only point to data at least as old, so `collect_fast` skips them
+    fn old_immut_start(&self) -> Pointer {
+        self.old_mut_end()
+    }
+
+    fn old_immut_end(&self) -> Pointer {
+        self.old_immut_start() + self.old_immut_capacity.bytes()
+    }
+
+    /// Large objects of any age, which are never moved, so that `collect_fast` does not copy them
+    fn large_start(&self) -> Pointer {
+        self.old_immut_end()
+    }
+
+    fn large_end(&self) -> Pointer {
+        self.large_start() + self.large_capacity.bytes()
+    }
+
+    /// GC metadata, starting with the card table for the mutable old heap and large object heap
+    fn meta_start(&self) -> Pointer {
+        self.large_end()
+    }
+
+    /// Cards of the mutable old heap come first in the card table
+    fn old_mut_card_cnt(&self) -> Nr {
+        (self.old_mut_capacity.bytes().0 + CARD_SIZE.0 - 1) / CARD_SIZE.0
+    }
+
+    /// Cards of the large object heap come after those of the mutable old heap; the last may be partial
+    fn large_card_cnt(&self) -> Nr {
+        (self.large_capacity.bytes().0 + CARD_SIZE.0 - 1) / CARD_SIZE.0
+    }
+
+    fn card_cnt(&self) -> Nr {
+        self.old_mut_card_cnt() + self.large_card_cnt()
+    }
+
+    fn card_ix(&self, addr: Pointer) -> Nr {
+        if addr >= self.large_start() {
+            self.old_mut_card_cnt() + (addr - self.large_start()).0 / CARD_SIZE.0
+        } else {
+            (addr - self.old_mut_start()).0 / CARD_SIZE.0
+        }
+    }
+
+    fn card_start(&self, card_ix: Nr) -> Pointer {
+        if card_ix >= self.old_mut_card_cnt() {
+            self.large_start() + CARD_SIZE * (card_ix - self.old_mut_card_cnt())
+        } else {
+            self.old_mut_start() + CARD_SIZE * card_ix
+        }
+    }
+
+    fn card_end(&self, card_ix: Nr) -> Pointer {
+        self.card_start(card_ix) + CARD_SIZE
+    }
+
+    fn card_table_entry(&self, card_ix: Nr) -> Pointer {
+        self.meta_start() + WORD_SIZE * card_ix
+    }
+
+    /// For each card, the header of the first object that overlaps it (or null)
+    fn card_first_object_entry(&self, card_ix: Nr) -> Pointer {
+        self.meta_start() + WORD_SIZE * (self.card_cnt() + card_ix)
+    }
+
+    /// Scratch space used during collections, e.g. for the mark stack and break table
+    fn scratch_start(&self) -> Pointer {
+        self.meta_start() + WORD_SIZE * (2 * self.card_cnt())
+    }
+
+    fn meta_end(&self) -> Pointer {
+        self.scratch_start() + self.meta_capacity.bytes()
     }
 
     fn end_of_memory(&self) -> Pointer {
-        self.old_end()
+        self.meta_end()
+    }
+
+    pub fn builder() -> GcConfBuilder {
+        GcConfBuilder {
+            conf: GcConf {
+                stack_capacity: WordSize(1024),
+                young_side_capacity: WordSize(16384),
+                old_mut_capacity: WordSize(8192),
+                old_immut_capacity: WordSize(16384),
+                large_capacity: WordSize(16384),
+                large_object_threshold: WordSize(2048),
+                meta_capacity: WordSize(57344),
+                memory_start: OFFSET,
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfError> {
+        let minimums = [
+            (Region::Stack, self.stack_capacity, MIN_REGION_CAPACITY),
+            (Region::Young, self.young_side_capacity, MIN_REGION_CAPACITY),
+            (Region::OldMutable, self.old_mut_capacity, WordSize(0)),
+            (Region::OldImmutable, self.old_immut_capacity, WordSize(0)),
+            (Region::Large, self.large_capacity, WordSize(0)),
+            (Region::Meta, self.meta_capacity, MIN_REGION_CAPACITY),
+        ];
+        for (region, capacity, minimum) in minimums {
+            if capacity < minimum {
+                return Err(ConfError::TooSmall { region, capacity, minimum });
+            }
+        }
+        let card_words = CARD_SIZE.whole_words();
+        if self.old_mut_capacity.0 % card_words.0 != 0 {
+            return Err(ConfError::NotCardAligned { capacity: self.old_mut_capacity, card_size: card_words });
+        }
+        if self.large_object_threshold < WordSize(1) {
+            return Err(ConfError::InvalidLargeObjectThreshold { threshold: self.large_object_threshold });
+        }
+        // Computed without the layout methods, since those could overflow
+        let card_cnt = (self.old_mut_capacity.0 as i64 * WORD_SIZE.0 as i64 + CARD_SIZE.0 as i64 - 1) / CARD_SIZE.0 as i64
+            + (self.large_capacity.0 as i64 * WORD_SIZE.0 as i64 + CARD_SIZE.0 as i64 - 1) / CARD_SIZE.0 as i64;
+        let total_words = self.stack_capacity.0 as i64 + 2 * self.young_side_capacity.0 as i64
+            + self.old_mut_capacity.0 as i64 + self.old_immut_capacity.0 as i64
+            + self.large_capacity.0 as i64 + 2 * card_cnt + self.meta_capacity.0 as i64;
+        let total_bytes = OFFSET.0 as i64 + total_words * WORD_SIZE.0 as i64;
+        if total_bytes > Nr::MAX as i64 {
+            return Err(ConfError::TooLarge { total_bytes });
+        }
+        let minimum = self.min_meta_capacity();
+        if self.meta_capacity < minimum {
+            return Err(ConfError::TooSmall { region: Region::Meta, capacity: self.meta_capacity, minimum });
+        }
+        Ok(())
+    }
+
+    /// Scratch space that `collect_full` needs in the worst case. Every object is at least one word.
+    /// The mark stack holds each reachable young, old or large object at most once, and afterwards
+    /// the break table takes its place, with two words for each live old object.
+    fn min_meta_capacity(&self) -> WordSize {
+        let old_words = self.old_mut_capacity + self.old_immut_capacity;
+        let mark_stack = self.young_side_capacity + old_words + self.large_capacity;
+        let break_table = old_words + old_words;
+        let minimum = if mark_stack > break_table { mark_stack } else { break_table };
+        if minimum > MIN_REGION_CAPACITY { minimum } else { MIN_REGION_CAPACITY }
+    }
+}
+
+/// Sizes start at defaults that are suitable for small programs
+pub struct GcConfBuilder {
+    conf: GcConf,
+}
+
+impl GcConfBuilder {
+    pub fn stack_capacity(mut self, capacity: WordSize) -> Self {
+        self.conf.stack_capacity = capacity;
+        self
+    }
+
+    /// Capacity of each of the two young sides
+    pub fn young_side_capacity(mut self, capacity: WordSize) -> Self {
+        self.conf.young_side_capacity = capacity;
+        self
+    }
+
+    /// Must be a whole number of cards
+    pub fn old_mut_capacity(mut self, capacity: WordSize) -> Self {
+        self.conf.old_mut_capacity = capacity;
+        self
+    }
+
+    pub fn old_immut_capacity(mut self, capacity: WordSize) -> Self {
+        self.conf.old_immut_capacity = capacity;
+        self
+    }
+
+    pub fn large_capacity(mut self, capacity: WordSize) -> Self {
+        self.conf.large_capacity = capacity;
+        self
+    }
+
+    /// Heap objects with at least this many fields are allocated in the large object region
+    pub fn large_object_threshold(mut self, threshold: WordSize) -> Self {
+        self.conf.large_object_threshold = threshold;
+        self
+    }
+
+    /// Scratch space for collections; must fit the mark stack and break table for full heaps
+    pub fn meta_capacity(mut self, capacity: WordSize) -> Self {
+        self.conf.meta_capacity = capacity;
+        self
+    }
+
+    pub fn build(self) -> Result<GcConf, ConfError> {
+        self.conf.validate()?;
+        Ok(self.conf)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfError {
+    TooSmall { region: Region, capacity: WordSize, minimum: WordSize },
+    NotCardAligned { capacity: WordSize, card_size: WordSize },
+    /// Threshold must be positive, otherwise even empty objects would be large
+    InvalidLargeObjectThreshold { threshold: WordSize },
+    /// All regions together do not fit in addressable memory
+    TooLarge { total_bytes: i64 },
+}
+
+impl fmt::Display for ConfError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfError::TooSmall { region, capacity, minimum } =>
+                write!(f, "{:?} region capacity is {} words, but must be at least {}", region, capacity, minimum),
+            ConfError::NotCardAligned { capacity, card_size } =>
+                write!(f, "mutable old region capacity is {} words, but must be a multiple of the card size {}", capacity, card_size),
+            ConfError::InvalidLargeObjectThreshold { threshold } =>
+                write!(f, "large object threshold is {} words, but must be at least 1", threshold),
+            ConfError::TooLarge { total_bytes } =>
+                write!(f, "regions need {} bytes in total, which is more than addressable memory", total_bytes),
+        }
     }
 }
 
@@ -248,7 +583,18 @@ struct GcState {
     stack_top_data: Pointer,
     young_side: Side,
     young_top: Pointer,
-    old_top: Pointer,
+    old_mut_top: Pointer,
+    old_immut_top: Pointer,
+    /// Whether an immutable old object may point to young data, which happens if it was promoted
+    /// while an object it references could not be (e.g. because its old region was full). The
+    /// immutable old heap is then a root for `collect_fast`, until nothing in it points to young data.
+    old_immut_to_young: bool,
+    /// End of the last large object block; blocks before this can be free
+    large_top: Pointer,
+    /// Nesting depth of `alloc_gc_disable`; allocation only collects garbage if this is 0
+    alloc_gc_disabled: Nr,
+    initialized: bool,
+    totals: GcStats,
 }
 
 impl GcState {
@@ -260,8 +606,16 @@ impl GcState {
         (self.young_top - conf.young_side_start(self.young_side)).whole_words()
     }
 
-    fn old_len(&self) -> WordSize {
-        unimplemented!()
+    fn old_mut_len(&self, conf: &GcConf) -> WordSize {
+        (self.old_mut_top - conf.old_mut_start()).whole_words()
+    }
+
+    fn old_immut_len(&self, conf: &GcConf) -> WordSize {
+        (self.old_immut_top - conf.old_immut_start()).whole_words()
+    }
+
+    fn old_len(&self, conf: &GcConf) -> WordSize {
+        self.old_mut_len(conf) + self.old_immut_len(conf)
     }
 }
 
@@ -279,6 +633,14 @@ impl ByteSize {
 pub struct WordSize(Nr);
 
 impl WordSize {
+    pub const fn new(words: Nr) -> Self {
+        WordSize(words)
+    }
+
+    pub fn words(self) -> Nr {
+        self.0
+    }
+
     fn bytes(self) -> ByteSize {
         ByteSize(WORD_SIZE.0 * self.0)
     }
@@ -302,10 +664,14 @@ impl fmt::Display for WordSize {
 pub struct Pointer(Nr);
 
 impl Pointer {
-    fn as_data(self) -> Nr {
+    pub fn as_data(self) -> Nr {
         self.0
     }
 
+    pub fn from_data(data: Nr) -> Self {
+        Pointer(data)
+    }
+
     fn null() -> Self {
         return Pointer(0);
     }
@@ -373,11 +739,60 @@ impl Side {
     }
 }
 
+/// Natively, memory is a `Vec` that is indexed by address (so the start is unused)
+#[cfg(not(target_arch = "wasm32"))]
 struct Data {
     mem: Vec<Nr>,
 }
 
+/// In wasm, memory is the module's own linear memory, so that the caller can use pointers directly
+#[cfg(target_arch = "wasm32")]
+struct Data {
+    end: Pointer,
+}
+
+#[cfg(target_arch = "wasm32")]
+const WASM_PAGE_SIZE: usize = 65536;
+
 impl Data {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn empty() -> Self {
+        Data { mem: Vec::new() }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn empty() -> Self {
+        Data { end: Pointer::null() }
+    }
+
+    /// Reserve memory for all regions in `conf`, and set where they start
+    #[cfg(not(target_arch = "wasm32"))]
+    fn allocate(conf: &mut GcConf, fill: Nr) -> Self {
+        conf.memory_start = OFFSET;
+        Data { mem: vec![fill; conf.end_of_memory().0 as usize] }
+    }
+
+    /// Reserve memory for all regions in `conf`, and set where they start. This grows the
+    /// linear memory, which cannot shrink, so memory from before a resize is not reused.
+    /// Every call takes fresh pages, so all regions (including the stack) get a new address,
+    /// and `resize` relocates every pointer and frame link into them.
+    #[cfg(target_arch = "wasm32")]
+    fn allocate(conf: &mut GcConf, fill: Nr) -> Self {
+        let len = conf.end_of_memory() - conf.stack_start();
+        let pages = (len.0 as usize + WASM_PAGE_SIZE - 1) / WASM_PAGE_SIZE;
+        let prev_pages = ::core::arch::wasm32::memory_grow(0, pages);
+        assert!(prev_pages != usize::MAX, "could not grow wasm memory by {pages} pages");
+        conf.memory_start = Pointer((prev_pages * WASM_PAGE_SIZE) as Nr);
+        let mut data = Data { end: conf.end_of_memory() };
+        let mut ix = conf.stack_start();
+        while fill != 0 && ix < data.end {
+            data[ix] = fill;
+            ix = ix + WORD_SIZE;
+        }
+        data
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn len(&self) -> WordSize {
         WordSize((self.mem.len() / 4).try_into().unwrap())
     }
@@ -393,7 +808,14 @@ impl Index<Pointer> for Data {
     fn index(&self, index: Pointer) -> &Self::Output {
         debug_assert!(index != Pointer::null(), "cannot read from null pointer");
         assert!(index.0 % WORD_SIZE.0 == 0, "unaligned read not impl yet (might not be needed even though wasm can do it)");
-        &self.mem[(index.0 / WORD_SIZE.0) as usize]
+        #[cfg(not(target_arch = "wasm32"))]
+        return &self.mem[(index.0 / WORD_SIZE.0) as usize];
+        #[cfg(target_arch = "wasm32")]
+        {
+            debug_assert!(index < self.end, "read outside of GC memory");
+            // Safety: memory up to `end` was reserved by `allocate`, and is only used by the GC
+            unsafe { &*(index.0 as usize as *const Nr) }
+        }
     }
 }
 
@@ -401,7 +823,14 @@ impl IndexMut<Pointer> for Data {
     fn index_mut(&mut self, index: Pointer) -> &mut Self::Output {
         debug_assert!(index != Pointer::null(), "cannot write to null pointer");
         assert!(index.0 % WORD_SIZE.0 == 0, "unaligned read not impl yet (might not be needed even though wasm can do it)");
-        &mut self.mem[(index.0 / WORD_SIZE.0) as usize]
+        #[cfg(not(target_arch = "wasm32"))]
+        return &mut self.mem[(index.0 / WORD_SIZE.0) as usize];
+        #[cfg(target_arch = "wasm32")]
+        {
+            debug_assert!(index < self.end, "write outside of GC memory");
+            // Safety: memory up to `end` was reserved by `allocate`, and is only used by the GC
+            unsafe { &mut *(index.0 as usize as *mut Nr) }
+        }
     }
 }
 
@@ -410,7 +839,12 @@ thread_local! {
         RefCell::new(GcConf {
             stack_capacity: WordSize(0),
             young_side_capacity: WordSize(0),
-            old_capacity: WordSize(0),
+            old_mut_capacity: WordSize(0),
+            old_immut_capacity: WordSize(0),
+            large_capacity: WordSize(0),
+            large_object_threshold: WordSize(0),
+            meta_capacity: WordSize(0),
+            memory_start: OFFSET,
         })
     ;
     static GC_STATE: RefCell<GcState> = {
@@ -419,14 +853,53 @@ thread_local! {
             stack_top_data: Pointer::null(),
             young_side: Side::Left,
             young_top: Pointer::null(),
-            old_top: Pointer::null(),
+            old_mut_top: Pointer::null(),
+            old_immut_top: Pointer::null(),
+            old_immut_to_young: false,
+            large_top: Pointer::null(),
+            alloc_gc_disabled: 0,
+            initialized: false,
+            totals: GcStats::default(),
         })
     };
     static DATA: RefCell<Data> = {
-        RefCell::new(Data { mem: Vec::new() })
+        RefCell::new(Data::empty())
     };
 }
 
+/// Set up all regions, discarding any previous objects and state. Other functions panic if this
+/// was not called first.
+pub fn init(mut conf: GcConf) {
+    DATA.with_borrow_mut(|data| {
+        // in debug mode 0x0F0F0F0F, to make reading uninitialized memory more obvious
+        let fill = if cfg!(debug_assertions) { 0x0F0F0F0F } else { 0 };
+        *data = Data::allocate(&mut conf, fill);
+        reset_card_table(&conf, data);
+    });
+    GC_STATE.with_borrow_mut(|state| *state = GcState {
+        stack_top_frame: Pointer::null(),
+        stack_top_data: conf.stack_start(),
+        young_side: Side::Left,
+        young_top: conf.young_side_start(Side::Left),
+        old_mut_top: conf.old_mut_start(),
+        old_immut_top: conf.old_immut_start(),
+        old_immut_to_young: false,
+        large_top: conf.large_start(),
+        alloc_gc_disabled: 0,
+        initialized: true,
+        totals: GcStats::default(),
+    });
+    GC_CONF.with_borrow_mut(|current| *current = conf);
+}
+
+pub fn is_initialized() -> bool {
+    GC_STATE.with_borrow(|state| state.initialized)
+}
+
+fn assert_initialized() {
+    assert!(GC_STATE.with_borrow(|state| state.initialized), "GC is used before initialization, call gc::init first");
+}
+
 pub fn alloc_heap(
     pointer_cnt: WordSize,
     size_32: WordSize,
@@ -441,34 +914,178 @@ pub fn alloc0_heap(
     size_32: WordSize,
     pointers_mutable: bool,
 ) -> Option<Pointer> {
+    alloc0_heap_object(YoungHeapHeader {
+        data_kind: DataKind::Struct,
+        pointers_mutable,
+        pointer_cnt,
+        size_32,
+    })
+}
+
+pub fn alloc_array(
+    elem_cnt: WordSize,
+    elems_are_pointers: bool,
+    pointers_mutable: bool,
+) -> Pointer {
+    alloc0_array(elem_cnt, elems_are_pointers, pointers_mutable)
+        .expect("out of memory (heap)")
+}
+
+/// Array of either only pointers or only data words; use `array_len` to get the element count.
+pub fn alloc0_array(
+    elem_cnt: WordSize,
+    elems_are_pointers: bool,
+    pointers_mutable: bool,
+) -> Option<Pointer> {
+    alloc0_heap_object(YoungHeapHeader {
+        data_kind: DataKind::Array,
+        pointers_mutable,
+        pointer_cnt: if elems_are_pointers { elem_cnt } else { WordSize(0) },
+        size_32: elem_cnt,
+    })
+}
+
+/// If there is no room, collect garbage and try again, first only young memory and then
+/// everything. Only returns None if that did not help, if collection is disabled,
+/// or if no header can describe the object.
+fn alloc0_heap_object(header: YoungHeapHeader) -> Option<Pointer> {
+    assert_initialized();
+    check_object_size(header.pointer_cnt, header.size_32).ok()?;
+    let pointer = alloc0_heap_object_collecting(header)?;
+    let bytes = header.encode().len() + header.size_32.bytes();
+    GC_STATE.with_borrow_mut(|state| state.totals.bytes_allocated += bytes.0 as u64);
+    Some(pointer)
+}
+
+fn alloc0_heap_object_collecting(header: YoungHeapHeader) -> Option<Pointer> {
+    if !fits_in_empty_region(header) {
+        return None;
+    }
+    if let Some(pointer) = try_alloc0_heap_object(header) {
+        return Some(pointer);
+    }
+    if GC_STATE.with_borrow(|state| state.alloc_gc_disabled > 0) {
+        return None;
+    }
+    collect_fast();
+    if let Some(pointer) = try_alloc0_heap_object(header) {
+        return Some(pointer);
+    }
+    collect_full();
+    collect_fast();
+    try_alloc0_heap_object(header)
+}
+
+/// Whether the object fits in its region once that is empty; if not, collecting cannot make room for it
+fn fits_in_empty_region(header: YoungHeapHeader) -> bool {
+    GC_CONF.with_borrow(|conf| {
+        let len = (header.encode().len() + header.size_32.bytes()).whole_words();
+        let (region, words, capacity) = if header.size_32 >= conf.large_object_threshold {
+            (Region::Large, len + WordSize(1), conf.large_capacity)
+        } else {
+            (Region::Young, len, conf.young_side_capacity)
+        };
+        if words > capacity {
+            emit(|| GcEvent::AllocationFailed { region, words });
+            return false;
+        }
+        true
+    })
+}
+
+fn try_alloc0_heap_object(header: YoungHeapHeader) -> Option<Pointer> {
+    if header.size_32 >= GC_CONF.with_borrow(|conf| conf.large_object_threshold) {
+        return alloc0_large_object(header);
+    }
     GC_STATE.with_borrow_mut(|state| {
         let young_side_end = GC_CONF.with_borrow(|conf|
             conf.young_side_end(state.young_side));
         DATA.with_borrow_mut(|data| {
             let p_init = state.young_top;
-            let header = YoungHeapHeader {
-                data_kind: DataKind::Struct,
-                pointers_mutable,
-                pointer_cnt,
-                size_32,
-            };
+            let size_32 = header.size_32;
             let header_enc = header.encode();
             let p_return = p_init + header_enc.len();
             let p_end = p_return + size_32.bytes();
             if p_end > young_side_end {
-                //TODO @mark: this should GC to cleanup / move to old heap
-                println!("debug: young heap {:?} is full, {} > {}", state.young_side, p_end, young_side_end);
+                emit(|| GcEvent::AllocationFailed { region: Region::Young, words: (p_end - p_init).whole_words() });
                 return None;
             }
             header_enc.write_to(p_init, data);
             state.young_top = p_end;
-            debug_assert!(p_end > p_return);
+            debug_assert!(p_end >= p_return);
             debug_assert!(p_return > p_init);
             Some(p_return)
         })
     })
 }
 
+/// Large objects are never moved, so instead of bumping, find the first free block that fits.
+/// If that block is bigger than needed, the rest is split off as a new free block.
+fn alloc0_large_object(header: YoungHeapHeader) -> Option<Pointer> {
+    GC_CONF.with_borrow(|conf| GC_STATE.with_borrow_mut(|state| {
+        DATA.with_borrow_mut(|data| {
+            let size_32 = header.size_32;
+            let header_enc = header.encode();
+            let block_len = (WORD_SIZE + header_enc.len() + size_32.bytes()).whole_words();
+            let mut block = conf.large_start();
+            while block < state.large_top {
+                let (free_len, used) = decode_large_block(data[block]);
+                if !used && free_len >= block_len {
+                    if free_len > block_len {
+                        data[block + block_len.bytes()] = encode_large_block(WordSize(free_len.0 - block_len.0), false);
+                    }
+                    break;
+                }
+                block = block + free_len.bytes();
+            }
+            if block == state.large_top {
+                if block + block_len.bytes() > conf.large_end() {
+                    emit(|| GcEvent::AllocationFailed { region: Region::Large, words: block_len });
+                    return None;
+                }
+                state.large_top = block + block_len.bytes();
+            }
+            data[block] = encode_large_block(block_len, true);
+            header_enc.write_to(block + WORD_SIZE, data);
+            record_card_object(conf, data, block, block + block_len.bytes());
+            Some(block + WORD_SIZE + header_enc.len())
+        })
+    }))
+}
+
+/// Stop allocation from collecting garbage, so it returns None when out of memory instead.
+/// Use this while some roots are temporarily not on the shadow stack. Can be nested, but
+/// every call must be followed by `alloc_gc_enable`.
+pub fn alloc_gc_disable() {
+    assert_initialized();
+    GC_STATE.with_borrow_mut(|state| state.alloc_gc_disabled += 1)
+}
+
+pub fn alloc_gc_enable() {
+    assert_initialized();
+    GC_STATE.with_borrow_mut(|state| {
+        assert!(state.alloc_gc_disabled > 0, "alloc_gc_enable without alloc_gc_disable");
+        state.alloc_gc_disabled -= 1
+    })
+}
+
+/// Calls `alloc_gc_enable` when dropped, so that it also happens when unwinding
+struct AllocGcEnableGuard;
+
+impl Drop for AllocGcEnableGuard {
+    fn drop(&mut self) {
+        alloc_gc_enable()
+    }
+}
+
+/// Run `f` with allocation-triggered collection disabled, see `alloc_gc_disable`.
+/// Collection is enabled again afterwards, also if `f` panics.
+pub fn without_alloc_gc<T>(f: impl FnOnce() -> T) -> T {
+    alloc_gc_disable();
+    let _guard = AllocGcEnableGuard;
+    f()
+}
+
 pub fn alloc_stack(
     pointer_cnt: WordSize,
     size_32: WordSize,
@@ -482,44 +1099,83 @@ pub fn alloc0_stack(
     pointer_cnt: WordSize,
     size_32: WordSize,
 ) -> Option<Pointer> {
+    alloc0_stack_object(StackHeader {
+        data_kind: DataKind::Struct,
+        pointer_cnt,
+        size_32,
+    })
+}
+
+pub fn alloc_stack_array(
+    elem_cnt: WordSize,
+    elems_are_pointers: bool,
+) -> Pointer {
+    alloc0_stack_array(elem_cnt, elems_are_pointers)
+        .expect("stack overflow")
+}
+
+pub fn alloc0_stack_array(
+    elem_cnt: WordSize,
+    elems_are_pointers: bool,
+) -> Option<Pointer> {
+    alloc0_stack_object(StackHeader {
+        data_kind: DataKind::Array,
+        pointer_cnt: if elems_are_pointers { elem_cnt } else { WordSize(0) },
+        size_32: elem_cnt,
+    })
+}
+
+fn alloc0_stack_object(header: StackHeader) -> Option<Pointer> {
+    assert_initialized();
+    check_object_size(header.pointer_cnt, header.size_32).ok()?;
     GC_STATE.with_borrow_mut(|state| {
         let stack_end = GC_CONF.with_borrow_mut(|conf| conf.stack_end());
         DATA.with_borrow_mut(|data| {
             let p_init = state.stack_top_data;
-            let header = StackHeader {
-                data_kind: DataKind::Struct,
-                pointer_cnt,
-                size_32,
-            };
+            let size_32 = header.size_32;
             let header_enc = header.encode();
             let p_return = p_init + header_enc.len();
             let p_end = p_return + size_32.bytes();
             if p_end > stack_end {
-                println!("debug: stack overflowed, {} > {}", p_end, stack_end);
+                emit(|| GcEvent::AllocationFailed { region: Region::Stack, words: (p_end - p_init).whole_words() });
                 return None;
             }
             header_enc.write_to(p_init, data);
             state.stack_top_data = p_end;
-            debug_assert!(p_end > p_return);
+            debug_assert!(p_end >= p_return);
             debug_assert!(p_return > p_init);
             Some(p_return)
         })
     })
 }
 
+/// Number of elements of an array allocated on the heap or stack
+pub fn array_len(array: Pointer) -> WordSize {
+    assert_initialized();
+    DATA.with_borrow(|data| {
+        let (kind, _, _, size_32) = HeaderEnc::read_before(data, array).decode();
+        debug_assert!(kind == DataKind::Array, "not an array: {array}");
+        size_32
+    })
+}
+
 /// The first word of a stack frame is the address of the previous one (0x0 for bottom)
 /// Note that it is _not_ assumed that stack frames have statically known size
-pub fn stack_frame_push() {
+/// Returns the new frame, which can be passed to `stack_frame_pop_to`.
+pub fn stack_frame_push() -> Pointer {
+    assert_initialized();
     GC_STATE.with_borrow_mut(|state| {
         DATA.with_borrow_mut(|data| {
             data[state.stack_top_data] = state.stack_top_frame.as_data();
             state.stack_top_frame = state.stack_top_data;
             state.stack_top_data = state.stack_top_data + WORD_SIZE;
-        });
-    });
+            state.stack_top_frame
+        })
+    })
 }
 
 pub fn stack_frame_pop() {
+    assert_initialized();
     GC_STATE.with_borrow_mut(|state| {
         DATA.with_borrow_mut(|data| {
             let prev_frame = data.read_pointer(state.stack_top_frame);
@@ -530,156 +1186,1303 @@ pub fn stack_frame_pop() {
     });
 }
 
+/// Pop `frame` and all frames pushed after it
+pub fn stack_frame_pop_to(frame: Pointer) {
+    assert_initialized();
+    while GC_STATE.with_borrow(|state| state.stack_top_frame >= frame && state.stack_top_frame != Pointer::null()) {
+        stack_frame_pop();
+    }
+}
+
 pub struct FastCollectStats {
     pub initial_young_capacity: WordSize,
     pub initial_young_len: WordSize,
     pub final_young_capacity: WordSize,
     pub final_young_len: WordSize,
-    //TODO @mark: use ^
+    /// Large objects are not collected by `collect_fast`, so there is no initial and final
+    pub large_capacity: WordSize,
+    pub large_len: WordSize,
+    /// Objects moved to the other young side, and their size including headers
+    pub objects_copied: Nr,
+    pub words_copied: WordSize,
+    /// Objects moved to the old heap, and their size including headers
+    pub objects_promoted: Nr,
+    pub words_promoted: WordSize,
+    /// Pointer fields in the stack, large objects and dirty cards
+    pub roots_scanned: Nr,
+    /// References to objects that had already been moved during this collection
+    pub forwards_followed: Nr,
 }
 
-struct TaskStack {
-    start: Pointer,
-    top: Pointer,
+pub struct FullCollectStats {
+    pub initial_old_capacity: WordSize,
+    pub initial_old_len: WordSize,
+    pub final_old_capacity: WordSize,
+    pub final_old_len: WordSize,
+    pub initial_large_len: WordSize,
+    pub final_large_len: WordSize,
+    /// Old objects moved during compaction, and their size including headers
+    pub objects_copied: Nr,
+    pub words_copied: WordSize,
+    /// Pointer fields in the stack
+    pub roots_scanned: Nr,
 }
 
-//TODO @mark: not yet needed for young-only
-impl TaskStack {
-    fn new_empty_at(start: Pointer) -> Self {
-        TaskStack { start, top: start }
-    }
+/// Totals since `init`, see `gc_stats`. This is `repr(C)` so the host can read it
+/// from wasm memory, see the `gc_stats` export.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[repr(C)]
+pub struct GcStats {
+    pub fast_collections: u64,
+    pub full_collections: u64,
+    /// Heap objects including headers, not counting the stack
+    pub bytes_allocated: u64,
+    pub objects_copied: u64,
+    pub words_copied: u64,
+    pub objects_promoted: u64,
+    pub words_promoted: u64,
+    pub roots_scanned: u64,
+    pub forwards_followed: u64,
+}
 
-    /// push all pointers in one object before popping anything; this probably leads to higher
-    /// stack size than DFS, but it means we only need to store header pointers, not field ones.
-    fn push_all(young_only: bool) {
-        todo!()
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "allocated:   {} bytes", self.bytes_allocated)?;
+        writeln!(f, "collections: {} fast, {} full", self.fast_collections, self.full_collections)?;
+        writeln!(f, "copied:      {} objects, {} words", self.objects_copied, self.words_copied)?;
+        writeln!(f, "promoted:    {} objects, {} words", self.objects_promoted, self.words_promoted)?;
+        writeln!(f, "roots:       {} scanned", self.roots_scanned)?;
+        write!(f, "forwards:    {} followed", self.forwards_followed)
     }
+}
 
-    //TODO @mark: if mutable objects stay young forever, that means young heap can have objects older than old heap, which in turn means old heap can have references to young heap and we need to scan everything all the time
-    fn push(young_only: bool) {
-        todo!()
-    }
+/// Capacity of every region, see `resize`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionCapacities {
+    pub stack: WordSize,
+    /// Capacity of each of the two young sides
+    pub young_side: WordSize,
+    pub old_mut: WordSize,
+    pub old_immut: WordSize,
+    pub large: WordSize,
+    pub meta: WordSize,
+}
 
-    fn pop() {
-        todo!()
-    }
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region { Stack, Young, OldMutable, OldImmutable, Large, Meta }
+
+/// Sizes that no header can describe, so allocation fails however much memory is free
+#[derive(Debug, PartialEq)]
+pub enum ObjectSizeError {
+    /// The size word of a big header has 23 bits for the size
+    TooLarge { size_32: WordSize },
+    /// A big header has 16 bits for the pointer count, unless all fields are pointers
+    TooManyPointers { pointer_cnt: WordSize },
 }
 
-fn mem_copy(data: &mut Data, from: Pointer, to: Pointer, len: WordSize) {
-    let mut off = ByteSize(0);
-    while off < len.bytes() {
-        data[to + off] = data[from + off];
-        off = off + WORD_SIZE
+impl fmt::Display for ObjectSizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectSizeError::TooLarge { size_32 } => write!(f, "object too large: {}", size_32),
+            ObjectSizeError::TooManyPointers { pointer_cnt } => write!(f, "too many pointers in struct with data: {}", pointer_cnt),
+        }
     }
 }
 
-fn collect_fast_handle_pointer(data: &mut Data, pointer_ix: Pointer, young_from_range: Range<Pointer>, new_young_top: &mut Pointer) {
-    // Stop if stack or old heap, or if already moved to opposite young heap side
-    let mut pointer_data = data[pointer_ix];
-    let mut pointer = Pointer(pointer_data);
-    if !young_from_range.contains(&pointer) {
-        println!("not young heap {}, stop (not in range {:?})", pointer_ix, young_from_range);
-        return;
+/// Whether a header can be encoded for an object with these sizes; if not, allocating it returns `None`
+pub fn check_object_size(pointer_cnt: WordSize, size_32: WordSize) -> Result<(), ObjectSizeError> {
+    if size_32.0 >= 1 << 23 {
+        return Err(ObjectSizeError::TooLarge { size_32 });
     }
+    if pointer_cnt != size_32 && pointer_cnt.0 >= BIG_HEADER_ALL_POINTERS as Nr {
+        return Err(ObjectSizeError::TooManyPointers { pointer_cnt });
+    }
+    Ok(())
+}
 
-    // Update ref and stop if already moved
-    let header_pointer = pointer - WORD_SIZE;
+#[derive(Debug, PartialEq)]
+pub enum ResizeError {
+    /// Live data in a region does not fit in the requested capacity
+    DoesNotFit { region: Region, len: WordSize, capacity: WordSize },
+    InvalidConf(ConfError),
+}
+
+impl fmt::Display for ResizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ResizeError::DoesNotFit { region, len, capacity } =>
+                write!(f, "cannot resize {:?} region to {} words, it contains {} words of live data", region, capacity, len),
+            ResizeError::InvalidConf(err) => write!(f, "invalid capacities: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HeapError {
+    /// Header kind byte is not a struct or array; the rest of the region cannot be walked
+    InvalidKind { header_ix: Pointer, kind: u8 },
+    /// Forward left behind by a collection; the rest of the region cannot be walked
+    Forward { header_ix: Pointer },
+    /// Pointer field that is not null, and not the start of a live object
+    Dangling { field_ix: Pointer, target: Pointer },
+    /// Immutable old objects are older than any young object, so cannot reference them, unless
+    /// `collect_fast` remembered that one was promoted before the object it references
+    OldImmutableToYoung { field_ix: Pointer, target: Pointer },
+    /// Stack objects are freed when their frame is popped, so the heap must not reference them
+    HeapToStack { field_ix: Pointer, target: Pointer },
+}
+
+impl fmt::Display for HeapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HeapError::InvalidKind { header_ix, kind } => write!(f, "header at {} has invalid kind {}", header_ix, kind),
+            HeapError::Forward { header_ix } => write!(f, "header at {} is a forward outside of collection", header_ix),
+            HeapError::Dangling { field_ix, target } => write!(f, "field at {} points to {}, which is not a live object", field_ix, target),
+            HeapError::OldImmutableToYoung { field_ix, target } => write!(f, "immutable old field at {} points to young object {}", field_ix, target),
+            HeapError::HeapToStack { field_ix, target } => write!(f, "heap field at {} points to stack object {}", field_ix, target),
+        }
+    }
+}
+
+/// Something the allocator or collector did, for debugging. These are only produced
+/// with the `gc-events` feature, see `set_event_sink`. Pointers are to object data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GcEvent {
+    AllocationFailed { region: Region, words: WordSize },
+    FrameScanned { frame: Pointer },
+    /// Object moved to the other young side
+    ObjectCopied { from: Pointer, to: Pointer, words: WordSize },
+    /// Object moved from young to old heap
+    ObjectPromoted { from: Pointer, to: Pointer, words: WordSize },
+    ForwardFollowed { field_ix: Pointer, target: Pointer },
+    CollectionFinished { full: bool },
+}
+
+impl fmt::Display for GcEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GcEvent::AllocationFailed { region, words } => write!(f, "no room for {} words in {:?} region", words, region),
+            GcEvent::FrameScanned { frame } => write!(f, "scan stack frame {}", frame),
+            GcEvent::ObjectCopied { from, to, words } => write!(f, "copy {} words from {} to {}", words, from, to),
+            GcEvent::ObjectPromoted { from, to, words } => write!(f, "promote {} words from {} to {}", words, from, to),
+            GcEvent::ForwardFollowed { field_ix, target } => write!(f, "field {} follows forward to {}", field_ix, target),
+            GcEvent::CollectionFinished { full: false } => write!(f, "fast collection finished"),
+            GcEvent::CollectionFinished { full: true } => write!(f, "full collection finished"),
+        }
+    }
+}
+
+/// Receives `GcEvent`s. Events are sent while the GC state is borrowed, so sinks must not call the GC.
+pub trait GcEventSink {
+    fn event(&mut self, event: GcEvent);
+}
+
+/// Default sink, which prints every event
+pub struct LogSink;
+
+impl GcEventSink for LogSink {
+    fn event(&mut self, event: GcEvent) {
+        println!("gc: {}", event)
+    }
+}
+
+/// Keeps all events, e.g. to assert on them in tests. Clones share the same events.
+#[derive(Clone, Default)]
+pub struct RecordingSink {
+    events: Rc<RefCell<Vec<GcEvent>>>,
+}
+
+impl RecordingSink {
+    pub fn events(&self) -> Vec<GcEvent> {
+        self.events.borrow().clone()
+    }
+}
+
+impl GcEventSink for RecordingSink {
+    fn event(&mut self, event: GcEvent) {
+        self.events.borrow_mut().push(event)
+    }
+}
+
+#[cfg(feature = "gc-events")]
+thread_local! {
+    static EVENT_SINK: RefCell<Box<dyn GcEventSink>> = RefCell::new(Box::new(LogSink));
+}
+
+/// Send events to `sink` instead of the previous sink, which is returned
+#[cfg(feature = "gc-events")]
+pub fn set_event_sink(sink: impl GcEventSink + 'static) -> Box<dyn GcEventSink> {
+    EVENT_SINK.with_borrow_mut(|current| ::std::mem::replace(current, Box::new(sink)))
+}
+
+/// Send the event to the sink; without the `gc-events` feature, this does nothing
+fn emit(event: impl FnOnce() -> GcEvent) {
+    #[cfg(feature = "gc-events")]
+    EVENT_SINK.with_borrow_mut(|sink| sink.event(event()));
+}
+
+/// Work queue for the mark phase, stored in the GC metadata region.
+struct TaskStack {
+    start: Pointer,
+    top: Pointer,
+    end: Pointer,
+}
+
+impl TaskStack {
+    fn new_empty_at(start: Pointer, end: Pointer) -> Self {
+        TaskStack { start, top: start, end }
+    }
+
+    /// push all pointers in one object before popping anything; this probably leads to higher
+    /// stack size than DFS, but it means we only need to store object pointers, not field ones.
+    fn push_all(&mut self, data: &mut Data, pointer: Pointer, heap_ranges: &[Range<Pointer>]) {
+        let header = YoungHeapHeader::decode(HeaderEnc::read_before(data, pointer));
+        let mut pointer_ix = pointer;
+        let pointer_end = pointer + header.pointer_cnt.bytes();
+        while pointer_ix < pointer_end {
+            self.push(data, pointer_ix, heap_ranges);
+            pointer_ix = pointer_ix + WORD_SIZE;
+        }
+    }
+
+    /// Mark the object referenced from `pointer_ix` and enqueue it, unless it is outside
+    /// the heap ranges being collected, or already marked.
+    //TODO @mark: if mutable objects stay young forever, that means young heap can have objects older than old heap, which in turn means old heap can have references to young heap and we need to scan everything all the time
+    fn push(&mut self, data: &mut Data, pointer_ix: Pointer, heap_ranges: &[Range<Pointer>]) {
+        let pointer = data.read_pointer(pointer_ix);
+        if !heap_ranges.iter().any(|range| points_into(range, pointer)) {
+            return;
+        }
+        let flags_ix = pointer - WORD_SIZE;
+        if is_reachable(data[flags_ix]) {
+            return;
+        }
+        mark_reachable(&mut data[flags_ix]);
+        assert!(self.top < self.end, "GC metadata region is too small for mark stack");
+        data[self.top] = pointer.0;
+        self.top = self.top + WORD_SIZE;
+    }
+
+    fn pop(&mut self, data: &Data) -> Option<Pointer> {
+        if self.top == self.start {
+            return None;
+        }
+        self.top = self.top - WORD_SIZE;
+        Some(data.read_pointer(self.top))
+    }
+}
+
+/// Table of (old address, new address) pairs for every live old heap object, stored in the
+/// GC metadata region. It is filled by walking the heap, so it is sorted without extra work.
+struct BreakTable {
+    start: Pointer,
+    top: Pointer,
+    end: Pointer,
+}
+
+impl BreakTable {
+    fn new_empty_at(start: Pointer, end: Pointer) -> Self {
+        BreakTable { start, top: start, end }
+    }
+
+    fn push(&mut self, data: &mut Data, old_pointer: Pointer, new_pointer: Pointer) {
+        assert!(self.top + WORD_SIZE * 2 <= self.end, "GC metadata region is too small for break table");
+        data[self.top] = old_pointer.0;
+        data[self.top + WORD_SIZE] = new_pointer.0;
+        self.top = self.top + WORD_SIZE * 2;
+    }
+
+    /// Binary search for the new address of a live object
+    fn lookup(&self, data: &Data, old_pointer: Pointer) -> Pointer {
+        let mut low = 0;
+        let mut high = (self.top - self.start).whole_words().0 / 2;
+        while low < high {
+            let mid = (low + high) / 2;
+            let entry = self.start + WORD_SIZE * (2 * mid);
+            let entry_old = data.read_pointer(entry);
+            if entry_old == old_pointer {
+                return data.read_pointer(entry + WORD_SIZE);
+            } else if entry_old < old_pointer {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        panic!("no break table entry for {old_pointer}, was it marked?")
+    }
+}
+
+fn mark_card_dirty(conf: &GcConf, data: &mut Data, field_ix: Pointer) {
+    data[conf.card_table_entry(conf.card_ix(field_ix))] = CARD_DIRTY;
+}
+
+/// Remember the first object (or large object block) overlapping each card, so that dirty
+/// cards can be scanned without walking the mutable old heap or large object heap from the start.
+fn record_card_object(conf: &GcConf, data: &mut Data, header_ix: Pointer, end: Pointer) {
+    for card_ix in conf.card_ix(header_ix) ..= conf.card_ix(end - WORD_SIZE) {
+        let entry = conf.card_first_object_entry(card_ix);
+        if data.read_pointer(entry) == Pointer::null() {
+            data[entry] = header_ix.0;
+        }
+    }
+}
+
+fn reset_card_table(conf: &GcConf, data: &mut Data) {
+    for card_ix in 0 .. conf.card_cnt() {
+        data[conf.card_table_entry(card_ix)] = CARD_CLEAN;
+        data[conf.card_first_object_entry(card_ix)] = Pointer::null().0;
+    }
+}
+
+fn encode_large_block(len: WordSize, used: bool) -> Nr {
+    (len.0 << 1) | if used { LARGE_BLOCK_USED } else { 0 }
+}
+
+fn decode_large_block(block_word: Nr) -> (WordSize, bool) {
+    (WordSize(block_word >> 1), block_word & LARGE_BLOCK_USED != 0)
+}
+
+/// Pointer to the object in the large object block at `block`, if the block is in use
+fn large_object_in_block(data: &Data, block: Pointer) -> Option<Pointer> {
+    let (_, used) = decode_large_block(data[block]);
+    if !used {
+        return None;
+    }
+    Some(block + WORD_SIZE + HeaderEnc::read_at(data, block + WORD_SIZE).len())
+}
+
+fn next_large_block(data: &Data, block: Pointer) -> Pointer {
+    block + decode_large_block(data[block]).0.bytes()
+}
+
+/// Words in use by large object blocks, not counting free blocks
+fn large_used_len(conf: &GcConf, state: &GcState, data: &Data) -> WordSize {
+    let mut len = WordSize(0);
+    let mut block = conf.large_start();
+    while block < state.large_top {
+        let (block_len, used) = decode_large_block(data[block]);
+        if used {
+            len = len + block_len;
+        }
+        block = block + block_len.bytes();
+    }
+    len
+}
+
+/// Clear the card table, then record all mutable old objects and large object blocks, and mark cards that point to young data
+fn rebuild_card_table(conf: &GcConf, state: &GcState, data: &mut Data) {
+    let young_range = conf.young_side_start(state.young_side) .. state.young_top;
+    reset_card_table(conf, data);
+    let mut header_ix = conf.old_mut_start();
+    while header_ix < state.old_mut_top {
+        let header_enc = HeaderEnc::read_at(data, header_ix);
+        let header = OldHeapHeader::decode(header_enc);
+        let end = next_object(data, header_ix);
+        record_card_object(conf, data, header_ix, end);
+        let mut pointer_ix = header_ix + header_enc.len();
+        while pointer_ix < header_ix + header_enc.len() + header.pointer_cnt.bytes() {
+            if points_into(&young_range, data.read_pointer(pointer_ix)) {
+                mark_card_dirty(conf, data, pointer_ix);
+            }
+            pointer_ix = pointer_ix + WORD_SIZE;
+        }
+        header_ix = end;
+    }
+    let mut block = conf.large_start();
+    while block < state.large_top {
+        let end = next_large_block(data, block);
+        record_card_object(conf, data, block, end);
+        if let Some(pointer) = large_object_in_block(data, block) {
+            let (_, _, pointer_cnt, _) = HeaderEnc::read_before(data, pointer).decode();
+            let mut pointer_ix = pointer;
+            while pointer_ix < pointer + pointer_cnt.bytes() {
+                if points_into(&young_range, data.read_pointer(pointer_ix)) {
+                    mark_card_dirty(conf, data, pointer_ix);
+                }
+                pointer_ix = pointer_ix + WORD_SIZE;
+            }
+        }
+        block = end;
+    }
+}
+
+/// Whether `pointer` is to an object whose header is in `range`. Pointers point after the header,
+/// so can be equal to the end of a range (for empty objects), but not the start.
+fn points_into(range: &Range<Pointer>, pointer: Pointer) -> bool {
+    range.start < pointer && pointer <= range.end
+}
+
+/// Start of the object after the one whose header starts at `header_ix`
+fn next_object(data: &Data, header_ix: Pointer) -> Pointer {
+    let enc = HeaderEnc::read_at(data, header_ix);
+    let (_, _, _, size_32) = enc.decode();
+    header_ix + enc.len() + size_32.bytes()
+}
+
+fn mem_copy(data: &mut Data, from: Pointer, to: Pointer, len: WordSize) {
+    let mut off = ByteSize(0);
+    while off < len.bytes() {
+        data[to + off] = data[from + off];
+        off = off + WORD_SIZE
+    }
+}
+
+/// Call `handle` for the address of every pointer field of every object in every stack frame.
+fn walk_stack_pointers(data: &mut Data, state: &GcState, mut handle: impl FnMut(&mut Data, Pointer)) {
+    let mut frame_start = state.stack_top_frame;
+    let mut frame_after = state.stack_top_data;
+    while frame_start != Pointer::null() {
+        emit(|| GcEvent::FrameScanned { frame: frame_start });
+        let mut header_ix = frame_start + WORD_SIZE;
+        while header_ix < frame_after {
+            let header_enc = HeaderEnc::read_at(data, header_ix);
+            let header = StackHeader::decode(header_enc);
+            let mut pointer_ix = header_ix + header_enc.len();
+            let mut pointer_end = pointer_ix + header.pointer_cnt.bytes();
+            while pointer_ix < pointer_end {
+                handle(data, pointer_ix);
+                pointer_ix = pointer_ix + WORD_SIZE;
+            }
+            header_ix = header_ix + header_enc.len() + header.size_32.bytes();
+        }
+        frame_after = frame_start;
+        frame_start = data.read_pointer(frame_start);
+    }
+}
+
+/// Where surviving young objects are copied to during `collect_fast`
+struct FastCollectTargets {
+    young_from_range: Range<Pointer>,
+    new_young_top: Pointer,
+    old_mut_top: Pointer,
+    old_mut_end: Pointer,
+    old_immut_top: Pointer,
+    old_immut_end: Pointer,
+    objects_copied: Nr,
+    words_copied: WordSize,
+    objects_promoted: Nr,
+    words_promoted: WordSize,
+    roots_scanned: Nr,
+    forwards_followed: Nr,
+}
+
+fn collect_fast_handle_pointer(data: &mut Data, pointer_ix: Pointer, targets: &mut FastCollectTargets) {
+    // Stop if stack or old heap, or if already moved to opposite young heap side
+    let mut pointer_data = data[pointer_ix];
+    let mut pointer = Pointer(pointer_data);
+    if !points_into(&targets.young_from_range, pointer) {
+        return;
+    }
+
+    // Update ref and stop if already moved
+    let header_pointer = pointer - WORD_SIZE;
     let mut header_data = &mut data[header_pointer];
     if let Some(forward) = DataKind::try_as_forward(*header_data) {
-        println!("found a forward: forward to {forward} from {header_data} at {header_pointer} (from {pointer_ix})");
+        emit(|| GcEvent::ForwardFollowed { field_ix: pointer_ix, target: forward });
         data[pointer_ix] = forward.0;
+        targets.forwards_followed += 1;
         return;
-    } else {
-        println!("not a forward: header {header_data} at {header_pointer} from {pointer_ix}");
     }
 
-    // If old enough, move to old heap, and leave a pointer
-    println!("at {} from {} header {:#x}", header_pointer, pointer_ix, header_data);
+    // Mutable and immutable objects are promoted to different old regions
     let gc_age = increment_gc_age(&mut header_data);
-    debug_assert!(gc_age < 7, "too old for young gc");
+    let header_enc = HeaderEnc::read_before(data, pointer);
+    let header = YoungHeapHeader::decode(header_enc);
+    let header_start = pointer - header_enc.len();
+    let len = (header_enc.len() + header.size_32.bytes()).whole_words();
+    let (old_top, old_end) = if header.pointers_mutable {
+        (&mut targets.old_mut_top, targets.old_mut_end)
+    } else {
+        (&mut targets.old_immut_top, targets.old_immut_end)
+    };
+    let new_addr = if gc_age >= TENURE_GC_AGE && *old_top + len.bytes() <= old_end {
+
+        // If old enough, move to old heap, with a header that does not track age (but has the same length)
+        let old_header = OldHeapHeader {
+            data_kind: header.data_kind,
+            pointers_mutable: header.pointers_mutable,
+            pointer_cnt: header.pointer_cnt,
+            size_32: header.size_32,
+        }.encode();
+        old_header.write_to(*old_top, data);
+        let new_addr = *old_top + old_header.len();
+        mem_copy(data, pointer, new_addr, header.size_32);
+        *old_top = new_addr + header.size_32.bytes();
+        targets.objects_promoted += 1;
+        targets.words_promoted = targets.words_promoted + len;
+        emit(|| GcEvent::ObjectPromoted { from: pointer, to: new_addr, words: len });
+        new_addr
+    } else {
 
-    // Otherwise (if not old), move to other side of young heap
-    let header = YoungHeapHeader::decode(*header_data);
-    let len = header.size_32 + WordSize(1);
-    println!("MOVE young side {len} from {header_pointer} to {new_young_top}");  //TODO @mark: TEMPORARY! REMOVE THIS!
-    let new_addr = *new_young_top + WORD_SIZE;
-    mem_copy(data, header_pointer, *new_young_top, len);
-    *new_young_top = *new_young_top + len.bytes();
+        // Otherwise (if not old, or old heap is full), move to other side of young heap
+        let new_addr = targets.new_young_top + header_enc.len();
+        mem_copy(data, header_start, targets.new_young_top, len);
+        targets.new_young_top = targets.new_young_top + len.bytes();
+        targets.objects_copied += 1;
+        targets.words_copied = targets.words_copied + len;
+        emit(|| GcEvent::ObjectCopied { from: pointer, to: new_addr, words: len });
+        new_addr
+    };
 
     // Update incoming pointer and leave a forward
-    println!("create forward at {header_pointer}: {} (was {}) to {new_addr} ", Pointer(new_forward(new_addr)), Pointer(data[header_pointer]));  //TODO @mark:
     data[header_pointer] = new_forward(new_addr);
-    println!("update {pointer_ix} to {new_addr}");
     data[pointer_ix] = new_addr.0;
 
-    // We don't need to recurse or enqueue tasks, since we'll
-    // walk the new young heap to process all pointers.
+    // We don't need to recurse or enqueue tasks, since we'll walk the new
+    // young heap and the old heap to process all pointers.
+}
+
+/// Handle all the pointer fields of the object at `pointer`, returning the start of the next object
+fn collect_fast_scan_object(data: &mut Data, pointer: Pointer, pointer_cnt: WordSize, size_32: WordSize, targets: &mut FastCollectTargets) -> Pointer {
+    let mut pointer_ix = pointer;
+    let pointer_end = pointer + pointer_cnt.bytes();
+    while pointer_ix < pointer_end {
+        collect_fast_handle_pointer(data, pointer_ix, targets);
+        pointer_ix = pointer_ix + WORD_SIZE;
+    }
+    pointer + size_32.bytes()
+}
+
+/// Handle all the pointer fields of the immutable old object at `header_ix`, returning the start of
+/// the next object, and whether any of the fields still points to young data afterwards
+fn collect_fast_scan_old_immut(data: &mut Data, header_ix: Pointer, young_to_range: &Range<Pointer>, targets: &mut FastCollectTargets) -> (Pointer, bool) {
+    let header_enc = HeaderEnc::read_at(data, header_ix);
+    let header = OldHeapHeader::decode(header_enc);
+    let pointer = header_ix + header_enc.len();
+    let next_ix = collect_fast_scan_object(data, pointer, header.pointer_cnt, header.size_32, targets);
+    let mut to_young = false;
+    let mut pointer_ix = pointer;
+    while pointer_ix < pointer + header.pointer_cnt.bytes() {
+        to_young |= points_into(young_to_range, data.read_pointer(pointer_ix));
+        pointer_ix = pointer_ix + WORD_SIZE;
+    }
+    (next_ix, to_young)
+}
+
+/// Handle the pointer fields of the mutable old or large object at `header_ix` that are within `field_range`,
+/// and keep the card dirty for any that still point to young data afterwards. Returns the number of fields handled.
+fn collect_fast_scan_remembered(data: &mut Data, conf: &GcConf, header_ix: Pointer, field_range: Range<Pointer>, young_to_range: &Range<Pointer>, targets: &mut FastCollectTargets) -> Nr {
+    let header_enc = HeaderEnc::read_at(data, header_ix);
+    let (_, _, pointer_cnt, _) = header_enc.decode();
+    let mut pointer_ix = header_ix + header_enc.len();
+    let mut pointer_end = pointer_ix + pointer_cnt.bytes();
+    if pointer_ix < field_range.start {
+        pointer_ix = field_range.start;
+    }
+    if pointer_end > field_range.end {
+        pointer_end = field_range.end;
+    }
+    let mut handled = 0;
+    while pointer_ix < pointer_end {
+        collect_fast_handle_pointer(data, pointer_ix, targets);
+        if points_into(young_to_range, data.read_pointer(pointer_ix)) {
+            mark_card_dirty(conf, data, pointer_ix);
+        }
+        pointer_ix = pointer_ix + WORD_SIZE;
+        handled += 1;
+    }
+    handled
 }
 
 pub fn collect_fast() -> FastCollectStats {
-    GC_CONF.with_borrow(|conf| { GC_STATE.with_borrow_mut(|state| { DATA.with_borrow_mut(|data| {
-        let young_from_range = conf.young_side_start(state.young_side) .. conf.young_side_end(state.young_side);
+    assert_initialized();
+    verify_heap_if_enabled("before collect_fast");
+    let stats = GC_CONF.with_borrow(|conf| { GC_STATE.with_borrow_mut(|state| { DATA.with_borrow_mut(|data| {
         let new_young_start =  conf.young_side_start(state.young_side.opposite());
-        let mut new_young_top = new_young_start;
         let init_young_size = state.young_top - conf.young_side_start(state.young_side);
+        let mut targets = FastCollectTargets {
+            young_from_range: conf.young_side_start(state.young_side) .. conf.young_side_end(state.young_side),
+            new_young_top: new_young_start,
+            old_mut_top: state.old_mut_top,
+            old_mut_end: conf.old_mut_end(),
+            old_immut_top: state.old_immut_top,
+            old_immut_end: conf.old_immut_end(),
+            objects_copied: 0,
+            words_copied: WordSize(0),
+            objects_promoted: 0,
+            words_promoted: WordSize(0),
+            roots_scanned: 0,
+            forwards_followed: 0,
+        };
+
+        let young_to_range = new_young_start .. conf.young_side_end(state.young_side.opposite());
 
         // First walk the stack for roots
-        let mut frame_start = state.stack_top_frame;
-        let mut frame_after = state.stack_top_data;
-        while frame_start != Pointer::null() {
-            println!("stack frame {}", frame_start);  //TODO @mark:
-            let mut header_ix = frame_start + WORD_SIZE;
-            while header_ix < frame_after {
-                let header = StackHeader::decode(data[header_ix]);
-                let mut pointer_ix = header_ix + WORD_SIZE;
-                println!("stack object {header_ix}, header {:?}, scan from {pointer_ix}", header);  //TODO @mark:
-                let mut pointer_end = header.pointer_cnt.bytes() + WORD_SIZE;
-                while pointer_ix < header_ix + pointer_end {
-                    println!("stack pointer {} from obj {}", pointer_ix, header_ix);  //TODO @mark:
-                    collect_fast_handle_pointer(data, pointer_ix, young_from_range.clone(), &mut new_young_top);
-                    pointer_ix = pointer_ix + WORD_SIZE;
+        walk_stack_pointers(data, state, |data, pointer_ix| {
+            targets.roots_scanned += 1;
+            collect_fast_handle_pointer(data, pointer_ix, &mut targets)
+        });
+
+        // Large objects can be young and point to young data, and are not traced, so they are roots,
+        // but like mutable old objects below, only fields on dirty cards can point to young data
+        let mut card_ix = conf.old_mut_card_cnt();
+        while card_ix < conf.card_cnt() && conf.card_start(card_ix) < state.large_top {
+            let entry = conf.card_table_entry(card_ix);
+            if data[entry] == CARD_DIRTY {
+                data[entry] = CARD_CLEAN;
+                let card_range = conf.card_start(card_ix) .. conf.card_end(card_ix);
+                let mut block = data.read_pointer(conf.card_first_object_entry(card_ix));
+                while block < card_range.end && block < state.large_top {
+                    if large_object_in_block(data, block).is_some() {
+                        targets.roots_scanned += collect_fast_scan_remembered(data, conf, block + WORD_SIZE, card_range.clone(), &young_to_range, &mut targets);
+                    }
+                    block = next_large_block(data, block);
                 }
-                header_ix = header_ix + header.size_32.bytes() + WORD_SIZE;
             }
-            frame_after = frame_start;
-            frame_start = data.read_pointer(frame_start);
+            card_ix += 1;
         }
-        println!("stack END {}", frame_start);  //TODO @mark:
-
-        // Having found all stack roots, handle the young heap by scanning flip side
-        // Note that the young heap still grows (new_young_top)
-        let mut header_ix = new_young_start;
-        println!("young {:?} {} -> {} ({:?})", state.young_side.opposite(), header_ix, new_young_top, new_young_top - header_ix);  //TODO @mark:
-        while header_ix < new_young_top {
-            println!("header: {:?} at {}", data[header_ix], header_ix);
-            let header = YoungHeapHeader::decode(data[header_ix]);
-            let mut pointer_ix = header_ix + WORD_SIZE;
-            let mut pointer_end = header.pointer_cnt.bytes() + WORD_SIZE;
-            println!("task header {:?}", header);  //TODO @mark:
-            while pointer_ix < header_ix + pointer_end {
-                println!("task pointer {}", pointer_ix);  //TODO @mark:
-                collect_fast_handle_pointer(data, pointer_ix, young_from_range.clone(), &mut new_young_top);
-                pointer_ix = pointer_ix + WORD_SIZE;
+
+        // Mutable old objects are roots too, but only fields on dirty cards can point to young
+        // data. Cards are cleaned, and marked again if they still point to young data after.
+        let mut card_ix = 0;
+        while card_ix < conf.old_mut_card_cnt() && conf.card_start(card_ix) < state.old_mut_top {
+            let entry = conf.card_table_entry(card_ix);
+            if data[entry] == CARD_DIRTY {
+                data[entry] = CARD_CLEAN;
+                let card_range = conf.card_start(card_ix) .. conf.card_end(card_ix);
+                let mut header_ix = data.read_pointer(conf.card_first_object_entry(card_ix));
+                while header_ix < card_range.end && header_ix < state.old_mut_top {
+                    targets.roots_scanned += collect_fast_scan_remembered(data, conf, header_ix, card_range.clone(), &young_to_range, &mut targets);
+                    header_ix = next_object(data, header_ix);
+                }
+            }
+            card_ix += 1;
+        }
+
+        // Immutable old objects normally only point to old data, but if one was promoted without
+        // the young data it references, all of them are roots until that data is old too
+        let mut old_immut_to_young = false;
+        if state.old_immut_to_young {
+            let mut header_ix = conf.old_immut_start();
+            while header_ix < state.old_immut_top {
+                targets.roots_scanned += OldHeapHeader::decode(HeaderEnc::read_at(data, header_ix)).pointer_cnt.0;
+                let (next_ix, to_young) = collect_fast_scan_old_immut(data, header_ix, &young_to_range, &mut targets);
+                old_immut_to_young |= to_young;
+                header_ix = next_ix;
+            }
+        }
+
+        // Having found all roots, handle the young heap by scanning flip side. Old objects can
+        // only reference young data if they were promoted during this collection, or if they
+        // were on a dirty card, so only those are scanned. A promoted immutable object can still
+        // point to young data afterwards, if the object it references stayed young, which is
+        // remembered for the next collection.
+        // Note that these regions still grow while scanning, including promoted objects.
+        let mut young_header_ix = new_young_start;
+        let mut old_mut_header_ix = state.old_mut_top;
+        let mut old_immut_header_ix = state.old_immut_top;
+        while young_header_ix < targets.new_young_top
+                || old_mut_header_ix < targets.old_mut_top
+                || old_immut_header_ix < targets.old_immut_top {
+            while young_header_ix < targets.new_young_top {
+                let header_enc = HeaderEnc::read_at(data, young_header_ix);
+                let header = YoungHeapHeader::decode(header_enc);
+                young_header_ix = collect_fast_scan_object(data, young_header_ix + header_enc.len(), header.pointer_cnt, header.size_32, &mut targets);
+            }
+            while old_mut_header_ix < targets.old_mut_top {
+                let end = next_object(data, old_mut_header_ix);
+                record_card_object(conf, data, old_mut_header_ix, end);
+                collect_fast_scan_remembered(data, conf, old_mut_header_ix, old_mut_header_ix .. end, &young_to_range, &mut targets);
+                old_mut_header_ix = end;
+            }
+            while old_immut_header_ix < targets.old_immut_top {
+                let (next_ix, to_young) = collect_fast_scan_old_immut(data, old_immut_header_ix, &young_to_range, &mut targets);
+                old_immut_to_young |= to_young;
+                old_immut_header_ix = next_ix;
             }
-            header_ix = header_ix + header.size_32.bytes() + WORD_SIZE;
         }
 
         state.young_side = state.young_side.opposite();
-        state.young_top = new_young_top;
+        state.young_top = targets.new_young_top;
+        state.old_mut_top = targets.old_mut_top;
+        state.old_immut_top = targets.old_immut_top;
+        state.old_immut_to_young = old_immut_to_young;
+        let totals = &mut state.totals;
+        totals.fast_collections += 1;
+        totals.objects_copied += targets.objects_copied as u64;
+        totals.words_copied += targets.words_copied.0 as u64;
+        totals.objects_promoted += targets.objects_promoted as u64;
+        totals.words_promoted += targets.words_promoted.0 as u64;
+        totals.roots_scanned += targets.roots_scanned as u64;
+        totals.forwards_followed += targets.forwards_followed as u64;
         FastCollectStats {
             initial_young_capacity: conf.young_side_capacity,
             initial_young_len: init_young_size.whole_words(),
             final_young_capacity: conf.young_side_capacity,
-            final_young_len: (new_young_top - new_young_start).whole_words(),
+            final_young_len: (targets.new_young_top - new_young_start).whole_words(),
+            large_capacity: conf.large_capacity,
+            large_len: large_used_len(conf, state, data),
+            objects_copied: targets.objects_copied,
+            words_copied: targets.words_copied,
+            objects_promoted: targets.objects_promoted,
+            words_promoted: targets.words_promoted,
+            roots_scanned: targets.roots_scanned,
+            forwards_followed: targets.forwards_followed,
+        }
+    }) }) });
+    verify_heap_if_enabled("after collect_fast");
+    emit(|| GcEvent::CollectionFinished { full: false });
+    stats
+}
+
+/// Update the pointer at `pointer_ix` if it points into the (pre-compaction) old heap.
+fn collect_full_update_pointer(data: &mut Data, pointer_ix: Pointer, old_ranges: &[Range<Pointer>], breaks: &BreakTable) {
+    let pointer = data.read_pointer(pointer_ix);
+    if !old_ranges.iter().any(|range| points_into(range, pointer)) {
+        return;
+    }
+    data[pointer_ix] = breaks.lookup(data, pointer).0;
+}
+
+fn collect_full_update_object(data: &mut Data, pointer: Pointer, old_ranges: &[Range<Pointer>], breaks: &BreakTable) {
+    let header = YoungHeapHeader::decode(HeaderEnc::read_before(data, pointer));
+    let mut pointer_ix = pointer;
+    let pointer_end = pointer + header.pointer_cnt.bytes();
+    while pointer_ix < pointer_end {
+        collect_full_update_pointer(data, pointer_ix, old_ranges, breaks);
+        pointer_ix = pointer_ix + WORD_SIZE;
+    }
+}
+
+fn collect_full_clear_object(data: &mut Data, pointer: Pointer) {
+    let header = YoungHeapHeader::decode(HeaderEnc::read_before(data, pointer));
+    let mut pointer_ix = pointer;
+    while pointer_ix < pointer + header.pointer_cnt.bytes() {
+        data[pointer_ix] = Pointer::null().0;
+        pointer_ix = pointer_ix + WORD_SIZE;
+    }
+}
+
+/// Mark-compact collection of the old heaps. Young objects are traced, so that young memory
+/// can keep old memory alive, but they are not moved; that is left to `collect_fast`.
+///
+/// This uses a break table in the metadata region instead of forwarding addresses in the
+/// headers, so old objects do not need an extra header word. Since the table is built
+/// by walking the old heaps in address order, it does not need to be sorted.
+pub fn collect_full() -> FullCollectStats {
+    assert_initialized();
+    verify_heap_if_enabled("before collect_full");
+    let stats = GC_CONF.with_borrow(|conf| { GC_STATE.with_borrow_mut(|state| { DATA.with_borrow_mut(|data| {
+        let young_range = conf.young_side_start(state.young_side) .. state.young_top;
+        // mutable before immutable, so that the break table is sorted
+        let old_ranges = [
+            conf.old_mut_start() .. state.old_mut_top,
+            conf.old_immut_start() .. state.old_immut_top,
+        ];
+        let init_old_len = state.old_len(conf);
+        let init_large_len = large_used_len(conf, state, data);
+
+        // Mark everything reachable from the stack, in both young and old heaps
+        let large_range = conf.large_start() .. state.large_top;
+        let heap_ranges = [young_range.clone(), old_ranges[0].clone(), old_ranges[1].clone(), large_range];
+        let mut tasks = TaskStack::new_empty_at(conf.scratch_start(), conf.meta_end());
+        let mut roots_scanned = 0;
+        walk_stack_pointers(data, state, |data, pointer_ix| {
+            roots_scanned += 1;
+            tasks.push(data, pointer_ix, &heap_ranges)
+        });
+        while let Some(pointer) = tasks.pop(data) {
+            tasks.push_all(data, pointer, &heap_ranges);
+        }
+
+        // Compute new addresses for live old objects (mark stack is empty, so reuse the space)
+        let mut breaks = BreakTable::new_empty_at(conf.scratch_start(), conf.meta_end());
+        let mut new_old_tops = [old_ranges[0].start, old_ranges[1].start];
+        for (old_range, new_old_top) in old_ranges.iter().zip(new_old_tops.iter_mut()) {
+            let mut header_ix = old_range.start;
+            while header_ix < old_range.end {
+                let header_len = HeaderEnc::read_at(data, header_ix).len();
+                let next_ix = next_object(data, header_ix);
+                let pointer = header_ix + header_len;
+                if is_reachable(data[pointer - WORD_SIZE]) {
+                    breaks.push(data, pointer, *new_old_top + header_len);
+                    *new_old_top = *new_old_top + (next_ix - header_ix);
+                }
+                header_ix = next_ix;
+            }
+        }
+
+        // Update all pointers into the old heaps, from stack, live young, live old and live large objects.
+        // Dead young objects stay until the next `collect_fast`, so clear their pointers, which may be to
+        // old objects that are freed or moved.
+        walk_stack_pointers(data, state, |data, pointer_ix|
+            collect_full_update_pointer(data, pointer_ix, &old_ranges, &breaks));
+        let mut header_ix = young_range.start;
+        while header_ix < young_range.end {
+            let next_ix = next_object(data, header_ix);
+            let pointer = header_ix + HeaderEnc::read_at(data, header_ix).len();
+            if is_reachable(data[pointer - WORD_SIZE]) {
+                clear_reachable(&mut data[pointer - WORD_SIZE]);
+                collect_full_update_object(data, pointer, &old_ranges, &breaks);
+            } else {
+                collect_full_clear_object(data, pointer);
+            }
+            header_ix = next_ix;
+        }
+        for old_range in &old_ranges {
+            let mut header_ix = old_range.start;
+            while header_ix < old_range.end {
+                let next_ix = next_object(data, header_ix);
+                let pointer = header_ix + HeaderEnc::read_at(data, header_ix).len();
+                if is_reachable(data[pointer - WORD_SIZE]) {
+                    collect_full_update_object(data, pointer, &old_ranges, &breaks);
+                }
+                header_ix = next_ix;
+            }
+        }
+        let mut block = conf.large_start();
+        while block < state.large_top {
+            if let Some(pointer) = large_object_in_block(data, block) {
+                if is_reachable(data[pointer - WORD_SIZE]) {
+                    collect_full_update_object(data, pointer, &old_ranges, &breaks);
+                }
+            }
+            block = next_large_block(data, block);
+        }
+
+        // Large objects are not moved, instead free unreachable ones, merging adjacent free blocks
+        let mut block = conf.large_start();
+        let mut free_start = None;
+        while block < state.large_top {
+            let next_block = next_large_block(data, block);
+            match large_object_in_block(data, block) {
+                Some(pointer) if is_reachable(data[pointer - WORD_SIZE]) => {
+                    clear_reachable(&mut data[pointer - WORD_SIZE]);
+                    free_start = None;
+                }
+                _ => {
+                    let start = *free_start.get_or_insert(block);
+                    data[start] = encode_large_block((next_block - start).whole_words(), false);
+                }
+            }
+            block = next_block;
+        }
+        if let Some(start) = free_start {
+            state.large_top = start;
+        }
+
+        // Slide live old objects down; objects only move to lower addresses, so the
+        // header of the next object is never overwritten before it is read
+        let mut objects_copied = 0;
+        let mut words_copied = WordSize(0);
+        for (old_range, new_old_top) in old_ranges.iter().zip(new_old_tops) {
+            let mut header_ix = old_range.start;
+            let mut new_header_ix = old_range.start;
+            while header_ix < old_range.end {
+                let len = next_object(data, header_ix) - header_ix;
+                let flags_ix = header_ix + HeaderEnc::read_at(data, header_ix).len() - WORD_SIZE;
+                if is_reachable(data[flags_ix]) {
+                    clear_reachable(&mut data[flags_ix]);
+                    if new_header_ix != header_ix {
+                        mem_copy(data, header_ix, new_header_ix, len.whole_words());
+                        objects_copied += 1;
+                        words_copied = words_copied + len.whole_words();
+                    }
+                    new_header_ix = new_header_ix + len;
+                }
+                header_ix = header_ix + len;
+            }
+            debug_assert!(new_header_ix == new_old_top);
+        }
+
+        state.old_mut_top = new_old_tops[0];
+        state.old_immut_top = new_old_tops[1];
+
+        // Objects moved to different cards, so rebuild the card table
+        rebuild_card_table(conf, state, data);
+        let old_capacity = conf.old_mut_capacity + conf.old_immut_capacity;
+        state.totals.full_collections += 1;
+        state.totals.objects_copied += objects_copied as u64;
+        state.totals.words_copied += words_copied.0 as u64;
+        state.totals.roots_scanned += roots_scanned as u64;
+        FullCollectStats {
+            initial_old_capacity: old_capacity,
+            initial_old_len: init_old_len,
+            final_old_capacity: old_capacity,
+            final_old_len: state.old_len(conf),
+            initial_large_len: init_large_len,
+            final_large_len: large_used_len(conf, state, data),
+            objects_copied,
+            words_copied,
+            roots_scanned,
+        }
+    }) }) });
+    verify_heap_if_enabled("after collect_full");
+    emit(|| GcEvent::CollectionFinished { full: true });
+    stats
+}
+
+/// Store a pointer in a heap object field. Pointers in mutable old objects and large objects must be
+/// written through this, so that the card is marked, otherwise `collect_fast` may not see the reference.
+pub fn write_pointer(field_ix: Pointer, value: Pointer) {
+    assert_initialized();
+    GC_CONF.with_borrow(|conf| {
+        DATA.with_borrow_mut(|data| {
+            data[field_ix] = value.0;
+            if (conf.old_mut_start() .. conf.old_mut_end()).contains(&field_ix)
+                    || (conf.large_start() .. conf.large_end()).contains(&field_ix) {
+                mark_card_dirty(conf, data, field_ix);
+            }
+        })
+    })
+}
+
+/// Read any word of GC memory, e.g. a data field of an object
+pub fn read_word(ix: Pointer) -> Nr {
+    assert_initialized();
+    DATA.with_borrow(|data| data[ix])
+}
+
+/// Store a non-pointer word in a data field; pointers must use `write_pointer`
+pub fn write_word(ix: Pointer, value: Nr) {
+    assert_initialized();
+    DATA.with_borrow_mut(|data| data[ix] = value)
+}
+
+/// Check the invariants that the collectors rely on, returning every violation found. This walks
+/// all stack frames and heap regions, so it is slow; it is meant for debugging and tests.
+pub fn verify_heap() -> Result<(), Vec<HeapError>> {
+    assert_initialized();
+    GC_CONF.with_borrow(|conf| { GC_STATE.with_borrow(|state| { DATA.with_borrow(|data| {
+        let mut errors = Vec::new();
+
+        // First find all objects, as (region, pointer to data, pointer count)
+        let mut objects = Vec::new();
+        let mut frame_start = state.stack_top_frame;
+        let mut frame_after = state.stack_top_data;
+        while frame_start != Pointer::null() {
+            verify_walk_region(data, Region::Stack, frame_start + WORD_SIZE, frame_after, &mut objects, &mut errors);
+            frame_after = frame_start;
+            frame_start = data.read_pointer(frame_start);
+        }
+        let young_start = conf.young_side_start(state.young_side);
+        verify_walk_region(data, Region::Young, young_start, state.young_top, &mut objects, &mut errors);
+        verify_walk_region(data, Region::OldMutable, conf.old_mut_start(), state.old_mut_top, &mut objects, &mut errors);
+        verify_walk_region(data, Region::OldImmutable, conf.old_immut_start(), state.old_immut_top, &mut objects, &mut errors);
+        let mut block = conf.large_start();
+        while block < state.large_top {
+            if decode_large_block(data[block]).1 {
+                verify_walk_region(data, Region::Large, block + WORD_SIZE, next_large_block(data, block), &mut objects, &mut errors);
+            }
+            block = next_large_block(data, block);
+        }
+
+        // Then check that every pointer field targets one of them
+        let live = objects.iter().map(|(_, pointer, _)| pointer.0).collect::<HashSet<Nr>>();
+        let young_range = young_start .. state.young_top;
+        let stack_range = conf.stack_start() .. conf.stack_end();
+        for &(region, pointer, pointer_cnt) in &objects {
+            let mut field_ix = pointer;
+            while field_ix < pointer + pointer_cnt.bytes() {
+                let target = data.read_pointer(field_ix);
+                if target == Pointer::null() {
+                } else if !live.contains(&target.0) {
+                    errors.push(HeapError::Dangling { field_ix, target });
+                } else if region != Region::Stack && stack_range.contains(&target) {
+                    errors.push(HeapError::HeapToStack { field_ix, target });
+                } else if region == Region::OldImmutable && points_into(&young_range, target) && !state.old_immut_to_young {
+                    errors.push(HeapError::OldImmutableToYoung { field_ix, target });
+                }
+                field_ix = field_ix + WORD_SIZE;
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }) }) })
+}
+
+/// Add all objects in `start .. end` to `objects`, stopping at the first header that cannot be decoded
+fn verify_walk_region(data: &Data, region: Region, start: Pointer, end: Pointer, objects: &mut Vec<(Region, Pointer, WordSize)>, errors: &mut Vec<HeapError>) {
+    let mut header_ix = start;
+    while header_ix < end {
+        let enc = HeaderEnc::read_at(data, header_ix);
+        let last = match enc {
+            HeaderEnc::Small(word) => word,
+            HeaderEnc::Big(_, word) => word,
+        };
+        if DataKind::try_as_forward(last).is_some() {
+            errors.push(HeapError::Forward { header_ix });
+            return;
+        }
+        let kind = last.to_le_bytes()[0] & !BIG_HEADER_MARK;
+        if !matches!(DataKind::try_from_u8(kind), Some(DataKind::Struct | DataKind::Array)) {
+            errors.push(HeapError::InvalidKind { header_ix, kind });
+            return;
+        }
+        let (_, _, pointer_cnt, _) = enc.decode();
+        objects.push((region, header_ix + enc.len(), pointer_cnt));
+        header_ix = next_object(data, header_ix);
+    }
+}
+
+/// With the `verify-heap` feature, panic if `verify_heap` finds any problem
+fn verify_heap_if_enabled(moment: &str) {
+    #[cfg(feature = "verify-heap")]
+    if let Err(errors) = verify_heap() {
+        let errors = errors.iter().map(|err| err.to_string()).collect::<Vec<_>>();
+        panic!("heap is invalid {moment}:\n{}", errors.join("\n"));
+    }
+}
+
+/// Write all objects reachable from the stack frames to `path`, as a Graphviz graph
+pub fn dump_dot(path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, heap_dot())
+}
+
+/// Graphviz graph with a node per object reachable from the stack frames, and an edge per pointer
+/// field, clustered by frame and region. Pointers to invalid objects get a node but are not followed.
+pub fn heap_dot() -> String {
+    assert_initialized();
+    GC_CONF.with_borrow(|conf| { GC_STATE.with_borrow(|state| { DATA.with_borrow(|data| {
+        let mut frames = Vec::new();
+        let mut frame_start = state.stack_top_frame;
+        let mut frame_after = state.stack_top_data;
+        while frame_start != Pointer::null() {
+            frames.push((frame_start, frame_after));
+            frame_after = frame_start;
+            frame_start = data.read_pointer(frame_start);
+        }
+
+        let mut out = String::new();
+        let mut edges = String::new();
+        let mut seen = HashSet::new();
+        let mut queue = Vec::new();
+        writeln!(out, "digraph heap {{").unwrap();
+        writeln!(out, "    node [ shape=box ];").unwrap();
+        for (frame_ix, &(frame_start, frame_after)) in frames.iter().rev().enumerate() {
+            writeln!(out, "    subgraph cluster_frame_{} {{", frame_ix).unwrap();
+            writeln!(out, "        label=\"Stack frame {}\";", frame_start).unwrap();
+            let mut header_ix = frame_start + WORD_SIZE;
+            while header_ix < frame_after {
+                let header_enc = HeaderEnc::read_at(data, header_ix);
+                let pointer = header_ix + header_enc.len();
+                seen.insert(pointer.0);
+                dot_object(&mut out, &mut edges, &mut queue, data, pointer, Region::Stack, None);
+                header_ix = next_object(data, header_ix);
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+
+        // Follow pointers from the stack, collecting nodes per region so they can be clustered
+        let regions = [Region::Young, Region::OldMutable, Region::OldImmutable, Region::Large];
+        let mut region_nodes = regions.map(|_| String::new());
+        let mut invalid_nodes = String::new();
+        while let Some(pointer) = queue.pop() {
+            if !seen.insert(pointer.0) {
+                continue;
+            }
+            let region = dot_region_of(conf, state, pointer);
+            let flags_word = match region {
+                Some(region) if region != Region::Stack => data[pointer - WORD_SIZE],
+                _ => {
+                    writeln!(invalid_nodes, "    n{} [ label=\"{}\\noutside live regions\", color=red ];", pointer.0, pointer).unwrap();
+                    continue;
+                }
+            };
+            if let Some(forward) = DataKind::try_as_forward(flags_word) {
+                writeln!(invalid_nodes, "    n{} [ label=\"{}\\nforward to {}\", color=red ];", pointer.0, pointer, forward).unwrap();
+                continue;
+            }
+            let kind = flags_word.to_le_bytes()[0] & !BIG_HEADER_MARK;
+            if !matches!(DataKind::try_from_u8(kind), Some(DataKind::Struct | DataKind::Array)) {
+                writeln!(invalid_nodes, "    n{} [ label=\"{}\\ninvalid kind {}\", color=red ];", pointer.0, pointer, kind).unwrap();
+                continue;
+            }
+            let region = region.unwrap();
+            let age = if region == Region::Young { Some(get_gc_age(flags_word)) } else { None };
+            let region_ix = regions.iter().position(|&r| r == region).unwrap();
+            dot_object(&mut region_nodes[region_ix], &mut edges, &mut queue, data, pointer, region, age);
+        }
+        for (region_ix, region) in regions.iter().enumerate() {
+            if region_nodes[region_ix].is_empty() {
+                continue;
+            }
+            writeln!(out, "    subgraph cluster_{:?} {{", region).unwrap();
+            if *region == Region::Young {
+                writeln!(out, "        label=\"Young side {:?}\";", state.young_side).unwrap();
+            } else {
+                writeln!(out, "        label=\"{:?}\";", region).unwrap();
+            }
+            out.push_str(&region_nodes[region_ix]);
+            writeln!(out, "    }}").unwrap();
+        }
+        out.push_str(&invalid_nodes);
+        out.push_str(&edges);
+        writeln!(out, "}}").unwrap();
+        out
+    }) }) })
+}
+
+/// Live region that contains `pointer`, if any
+fn dot_region_of(conf: &GcConf, state: &GcState, pointer: Pointer) -> Option<Region> {
+    [
+        (Region::Stack, conf.stack_start() .. state.stack_top_data),
+        (Region::Young, conf.young_side_start(state.young_side) .. state.young_top),
+        (Region::OldMutable, conf.old_mut_start() .. state.old_mut_top),
+        (Region::OldImmutable, conf.old_immut_start() .. state.old_immut_top),
+        (Region::Large, conf.large_start() .. state.large_top),
+    ].into_iter()
+        .find(|(_, range)| points_into(range, pointer))
+        .map(|(region, _)| region)
+}
+
+/// Write the node for the object at `pointer`, and an edge and queue entry for each non-null pointer field
+fn dot_object(nodes: &mut String, edges: &mut String, queue: &mut Vec<Pointer>, data: &Data, pointer: Pointer, region: Region, age: Option<Nr>) {
+    let (kind, flags, pointer_cnt, size_32) = HeaderEnc::read_before(data, pointer).decode();
+    let mut label = format!("{}\\n{:?} {:?}", pointer, region, kind);
+    if let Some(age) = age {
+        write!(label, ", age {}", age).unwrap();
+    }
+    if flags & (1 << POINTER_MUTABLE_FLAG_BIT) != 0 {
+        label.push_str(", mutable");
+    }
+    write!(label, "\\n{} pointers, {} data", pointer_cnt, size_32.0 - pointer_cnt.0).unwrap();
+    writeln!(nodes, "        n{} [ label=\"{}\" ];", pointer.0, label).unwrap();
+    for field in 0 .. pointer_cnt.0 {
+        let target = data.read_pointer(pointer + WORD_SIZE * field);
+        if target != Pointer::null() {
+            writeln!(edges, "    n{} -> n{} [ label=\"{}\" ];", pointer.0, target.0, field).unwrap();
+            queue.push(target);
+        }
+    }
+}
+
+pub fn region_capacities() -> RegionCapacities {
+    assert_initialized();
+    GC_CONF.with_borrow(|conf| RegionCapacities {
+        stack: conf.stack_capacity,
+        young_side: conf.young_side_capacity,
+        old_mut: conf.old_mut_capacity,
+        old_immut: conf.old_immut_capacity,
+        large: conf.large_capacity,
+        meta: conf.meta_capacity,
+    })
+}
+
+/// Grow or shrink regions at runtime. Garbage is collected first (unless disabled with
+/// `alloc_gc_disable`), then every region is copied to its new position in a new memory,
+/// and all pointers are updated. If live data does not fit, nothing is changed.
+pub fn resize(capacities: RegionCapacities) -> Result<(), ResizeError> {
+    assert_initialized();
+    let mut new_conf = GC_CONF.with_borrow(|conf| GcConf {
+        stack_capacity: capacities.stack,
+        young_side_capacity: capacities.young_side,
+        old_mut_capacity: capacities.old_mut,
+        old_immut_capacity: capacities.old_immut,
+        large_capacity: capacities.large,
+        large_object_threshold: conf.large_object_threshold,
+        meta_capacity: capacities.meta,
+        memory_start: conf.memory_start,
+    });
+    new_conf.validate().map_err(ResizeError::InvalidConf)?;
+    if GC_STATE.with_borrow(|state| state.alloc_gc_disabled == 0) {
+        collect_fast();
+        collect_full();
+    }
+    GC_CONF.with_borrow_mut(|conf| { GC_STATE.with_borrow_mut(|state| { DATA.with_borrow_mut(|data| {
+        // Large objects are not moved within their region, so free blocks before the top must fit too
+        let checks = [
+            (Region::Stack, state.stack_len(conf), new_conf.stack_capacity),
+            (Region::Young, state.young_len(conf), new_conf.young_side_capacity),
+            (Region::OldMutable, state.old_mut_len(conf), new_conf.old_mut_capacity),
+            (Region::OldImmutable, state.old_immut_len(conf), new_conf.old_immut_capacity),
+            (Region::Large, (state.large_top - conf.large_start()).whole_words(), new_conf.large_capacity),
+        ];
+        for (region, len, capacity) in checks {
+            if len > capacity {
+                return Err(ResizeError::DoesNotFit { region, len, capacity });
+            }
         }
+
+        let mut new_data = Data::allocate(&mut new_conf, 0);
+        move_regions(conf, &new_conf, state, data, &mut new_data);
+        *conf = new_conf;
+        *data = new_data;
+        Ok(())
     }) }) })
 }
 
-pub fn collect_full() {
-    todo!();
+/// Copy the used part of every region from the `conf` layout in `data` to the `new_conf` layout in
+/// `new_data`, and update all pointers (including frame links) and the region tops in `state`
+fn move_regions(conf: &GcConf, new_conf: &GcConf, state: &mut GcState, data: &Data, new_data: &mut Data) {
+    // The stack moves too if memory starts elsewhere, which is the case in wasm
+    let moves = [
+        (conf.stack_start() .. state.stack_top_data, new_conf.stack_start()),
+        (conf.young_side_start(state.young_side) .. state.young_top, new_conf.young_side_start(state.young_side)),
+        (conf.old_mut_start() .. state.old_mut_top, new_conf.old_mut_start()),
+        (conf.old_immut_start() .. state.old_immut_top, new_conf.old_immut_start()),
+        (conf.large_start() .. state.large_top, new_conf.large_start()),
+    ];
+    for (range, new_start) in &moves {
+        let mut ix = range.start;
+        while ix < range.end {
+            new_data[*new_start + (ix - range.start)] = data[ix];
+            ix = ix + WORD_SIZE;
+        }
+    }
+
+    // Frame links point to the start of frames, which for the first frame is the start of the stack
+    let relocate_frame = |frame: Pointer| if frame == Pointer::null() {
+        frame
+    } else {
+        new_conf.stack_start() + (frame - conf.stack_start())
+    };
+    state.stack_top_frame = relocate_frame(state.stack_top_frame);
+    state.stack_top_data = new_conf.stack_start() + (state.stack_top_data - conf.stack_start());
+    let mut frame = state.stack_top_frame;
+    while frame != Pointer::null() {
+        let prev_frame = relocate_frame(new_data.read_pointer(frame));
+        new_data[frame] = prev_frame.0;
+        frame = prev_frame;
+    }
+
+    // Pointers point after the header, so can be equal to the end of a region (for empty objects), but not the start
+    let relocate = |data: &mut Data, pointer_ix: Pointer| {
+        let pointer = data.read_pointer(pointer_ix);
+        if let Some((range, new_start)) = moves.iter().find(|(range, _)| range.start < pointer && pointer <= range.end) {
+            data[pointer_ix] = (*new_start + (pointer - range.start)).0;
+        }
+    };
+    let relocate_object = |data: &mut Data, header_ix: Pointer| {
+        let header_enc = HeaderEnc::read_at(data, header_ix);
+        let (_, _, pointer_cnt, _) = header_enc.decode();
+        let mut pointer_ix = header_ix + header_enc.len();
+        while pointer_ix < header_ix + header_enc.len() + pointer_cnt.bytes() {
+            relocate(data, pointer_ix);
+            pointer_ix = pointer_ix + WORD_SIZE;
+        }
+    };
+    walk_stack_pointers(new_data, state, relocate);
+    for (range, new_start) in &moves[1..4] {
+        let mut header_ix = *new_start;
+        while header_ix < *new_start + (range.end - range.start) {
+            relocate_object(new_data, header_ix);
+            header_ix = next_object(new_data, header_ix);
+        }
+    }
+    let mut block = new_conf.large_start();
+    while block < new_conf.large_start() + (state.large_top - conf.large_start()) {
+        if large_object_in_block(new_data, block).is_some() {
+            relocate_object(new_data, block + WORD_SIZE);
+        }
+        block = next_large_block(new_data, block);
+    }
+
+    state.young_top = new_conf.young_side_start(state.young_side) + (state.young_top - conf.young_side_start(state.young_side));
+    state.old_mut_top = new_conf.old_mut_start() + (state.old_mut_top - conf.old_mut_start());
+    state.old_immut_top = new_conf.old_immut_start() + (state.old_immut_top - conf.old_immut_start());
+    state.large_top = new_conf.large_start() + (state.large_top - conf.large_start());
+    rebuild_card_table(new_conf, state, new_data);
+}
+
+/// Totals since `init`, e.g. to print an allocation report at exit
+pub fn gc_stats() -> GcStats {
+    assert_initialized();
+    GC_STATE.with_borrow(|state| state.totals)
 }
 
 pub fn young_heap_size() -> WordSize {
+    assert_initialized();
     GC_CONF.with_borrow(|conf| {
         GC_STATE.with_borrow(|state| {
             state.young_top - conf.young_side_start(state.young_side)
@@ -687,7 +2490,46 @@ pub fn young_heap_size() -> WordSize {
     }).whole_words()
 }
 
+/// Words of pointers and data of the objects in the active young half, so without headers
+pub fn young_heap_field_size() -> WordSize {
+    assert_initialized();
+    GC_CONF.with_borrow(|conf| {
+        GC_STATE.with_borrow(|state| {
+            DATA.with_borrow(|data| {
+                let mut size = WordSize::new(0);
+                let mut header_ix = conf.young_side_start(state.young_side);
+                while header_ix < state.young_top {
+                    let (_, _, _, size_32) = HeaderEnc::read_at(data, header_ix).decode();
+                    size = size + size_32;
+                    header_ix = next_object(data, header_ix);
+                }
+                size
+            })
+        })
+    })
+}
+
+pub fn old_heap_size() -> WordSize {
+    assert_initialized();
+    GC_CONF.with_borrow(|conf| {
+        GC_STATE.with_borrow(|state| {
+            state.old_len(conf)
+        })
+    })
+}
+
+/// Words used by large objects, including their block words
+pub fn large_heap_size() -> WordSize {
+    assert_initialized();
+    GC_CONF.with_borrow(|conf| {
+        GC_STATE.with_borrow(|state| {
+            DATA.with_borrow(|data| large_used_len(conf, state, data))
+        })
+    })
+}
+
 pub fn stack_size() -> WordSize {
+    assert_initialized();
     GC_CONF.with_borrow(|conf| {
         GC_STATE.with_borrow(|state| {
             state.stack_top_data - conf.stack_start()
@@ -695,6 +2537,16 @@ pub fn stack_size() -> WordSize {
     }).whole_words()
 }
 
+/// From the start of the stack to after its last object, e.g. to check frames before popping them
+pub fn stack_range() -> Range<Pointer> {
+    assert_initialized();
+    GC_CONF.with_borrow(|conf| {
+        GC_STATE.with_borrow(|state| {
+            conf.stack_start() .. state.stack_top_data
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -705,28 +2557,16 @@ mod tests {
     const THREE_WORDS: WordSize = WordSize(3);
 
     fn reset() {
-        GC_CONF.with_borrow_mut(|conf| *conf = GcConf {
-            stack_capacity: WordSize(1024),
-            young_side_capacity: WordSize(16384),
-            old_capacity: WordSize(16384),
-        });
-        GC_CONF.with_borrow(|conf| {
-            GC_STATE.with_borrow_mut(|state| *state = GcState {
-                stack_top_frame: Pointer::null(),
-                stack_top_data: conf.stack_start(),
-                young_side: Side::Left,
-                young_top: conf.young_side_start(Side::Left),
-                old_top: conf.old_start(),
-            });
-            DATA.with_borrow_mut(|data| {
-                if (Pointer::null() + data.len().bytes()) < conf.end_of_memory() {
-                    // in debug mode 0x0F0F0F0F, usually 0
-                    *data = Data { mem: vec![0x0F0F0F0F; conf.end_of_memory().0 as usize] };
-                } else {
-                    data.mem.fill(0x0F0F0F0F);
-                }
-            });
-        });
+        init(GcConf::builder()
+            .stack_capacity(WordSize(1024))
+            .young_side_capacity(WordSize(16384))
+            .old_mut_capacity(WordSize(8192))
+            .old_immut_capacity(WordSize(16384))
+            .large_capacity(WordSize(16384))
+            .large_object_threshold(WordSize(2048))
+            .meta_capacity(WordSize(57344))
+            .build()
+            .unwrap());
     }
 
     fn print_memory() {
@@ -761,9 +2601,7 @@ mod tests {
 
     fn fill_zeros(obj_addr: Pointer) -> Pointer {
         DATA.with_borrow_mut(|data| {
-            let hdr = data[obj_addr - WORD_SIZE];
-            let pointer_cnt: WordSize = read_pointer_cnt(hdr);
-            let size_32: WordSize = read_data_size(hdr);
+            let (_, _, _, size_32) = HeaderEnc::read_before(data, obj_addr).decode();
             let mut i = obj_addr;
             let end = obj_addr + size_32.bytes();
             while i < end {
@@ -774,12 +2612,26 @@ mod tests {
         obj_addr
     }
 
-    fn read_pointer_cnt(header: Nr) -> WordSize {
-        WordSize(header.to_le_bytes()[2] as Nr)
-    }
-
-    fn read_data_size(header: Nr) -> WordSize {
-        WordSize(header.to_le_bytes()[3] as Nr)
+    /// Place an object directly in the old heap, as if it had been promoted
+    fn alloc_old(pointer_cnt: WordSize, size_32: WordSize, pointers_mutable: bool) -> Pointer {
+        GC_CONF.with_borrow(|conf| GC_STATE.with_borrow_mut(|state| {
+            DATA.with_borrow_mut(|data| {
+                let header_enc = OldHeapHeader {
+                    data_kind: DataKind::Struct,
+                    pointers_mutable,
+                    pointer_cnt,
+                    size_32,
+                }.encode();
+                let old_top = if pointers_mutable { &mut state.old_mut_top } else { &mut state.old_immut_top };
+                let p_init = *old_top;
+                header_enc.write_to(p_init, data);
+                *old_top = p_init + header_enc.len() + size_32.bytes();
+                if pointers_mutable {
+                    record_card_object(conf, data, p_init, *old_top);
+                }
+                p_init + header_enc.len()
+            })
+        }))
     }
 
     #[test]
@@ -815,70 +2667,572 @@ mod tests {
     }
 
     #[test]
-    fn alloc_heap_out_of_space() {
+    #[should_panic(expected = "used before initialization")]
+    fn alloc_before_init() {
+        alloc_heap(NO_WORDS, ONE_WORD, false);
+    }
+
+    #[test]
+    fn conf_builder_validates() {
+        assert!(GcConf::builder().build().is_ok());
+        assert_eq!(GcConf::builder().young_side_capacity(WordSize(8)).build().unwrap_err(),
+            ConfError::TooSmall { region: Region::Young, capacity: WordSize(8), minimum: WordSize(16) });
+        assert_eq!(GcConf::builder().old_immut_capacity(WordSize(-1)).build().unwrap_err(),
+            ConfError::TooSmall { region: Region::OldImmutable, capacity: WordSize(-1), minimum: NO_WORDS });
+        assert_eq!(GcConf::builder().old_mut_capacity(WordSize(100)).build().unwrap_err(),
+            ConfError::NotCardAligned { capacity: WordSize(100), card_size: WordSize(32) });
+        assert_eq!(GcConf::builder().large_object_threshold(NO_WORDS).build().unwrap_err(),
+            ConfError::InvalidLargeObjectThreshold { threshold: NO_WORDS });
+        assert!(matches!(GcConf::builder().young_side_capacity(WordSize(300_000_000)).build(),
+            Err(ConfError::TooLarge { .. })));
+        assert_eq!(GcConf::builder().meta_capacity(WordSize(4096)).build().unwrap_err(),
+            ConfError::TooSmall { region: Region::Meta, capacity: WordSize(4096), minimum: WordSize(57344) });
+        assert_eq!(GcConf::builder().young_side_capacity(WordSize(16)).large_capacity(NO_WORDS).meta_capacity(WordSize(49151)).build().unwrap_err(),
+            ConfError::TooSmall { region: Region::Meta, capacity: WordSize(49151), minimum: WordSize(49152) });
+    }
+
+    #[test]
+    fn init_discards_previous_objects() {
+        reset();
+        stack_frame_push();
+        alloc_stack(NO_WORDS, ONE_WORD);
+        alloc_heap(NO_WORDS, ONE_WORD, false);
+        init(GcConf::builder().build().unwrap());
+        assert_eq!(stack_size(), NO_WORDS);
+        assert_eq!(young_heap_size(), NO_WORDS);
+    }
+
+    #[test]
+    fn alloc_heap_out_of_space() {
+        reset();
+        without_alloc_gc(|| {
+            for _ in 0 .. 64 {
+                let addr1 = alloc0_heap(WordSize(0), WordSize(255), false);
+                assert!(addr1.is_some());
+            }
+            let addr2 = alloc0_heap(WordSize(0), WordSize(255), false);
+            assert!(addr2.is_none());
+        });
+    }
+
+    #[test]
+    fn alloc_heap_collects_when_full() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let kept = fill_zeros(alloc_heap(NO_WORDS, TWO_WORDS, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = kept.0;
+            data[kept] = 123;
+        });
+        for _ in 0 .. 64 {
+            alloc_heap(WordSize(0), WordSize(255), false);
+        }
+        let addr = alloc0_heap(WordSize(0), WordSize(255), false);
+        assert!(addr.is_some());
+        // the last allocation in the loop did not fit, and triggered collection
+        assert_eq!(young_heap_size(), WordSize(3 + 2 * 256), "garbage should have been collected");
+        DATA.with_borrow(|data| assert_eq!(data[data.read_pointer(stack_ref)], 123));
+    }
+
+    #[test]
+    fn alloc_heap_escalates_to_full_gc() {
+        reset();
+        stack_frame_push();
+        let stack_refs = fill_zeros(alloc_stack(WordSize(8), WordSize(8)));
+        for i in 0 .. 8 {
+            let obj = alloc_heap(NO_WORDS, WordSize(2000), false);
+            DATA.with_borrow_mut(|data| data[stack_refs + WORD_SIZE * i] = obj.0);
+        }
+        for _ in 1 .. TENURE_GC_AGE {
+            collect_fast();
+        }
+        // Old heap is full of garbage, so young data cannot be promoted without a full collection
+        alloc_old(NO_WORDS, WordSize(16384 - 2), false);
+        assert_eq!(young_heap_size(), WordSize(8 * 2002));
+        let addr = alloc0_heap(NO_WORDS, WordSize(2000), false);
+        assert!(addr.is_some());
+        assert_eq!(young_heap_size(), WordSize(2002));
+        assert_eq!(old_heap_size(), WordSize(8 * 2002));
+    }
+
+    #[test]
+    fn alloc_gc_disable_nests() {
+        reset();
+        alloc_gc_disable();
+        without_alloc_gc(|| assert!(alloc0_heap(NO_WORDS, WordSize(16384), false).is_none()));
+        assert!(alloc0_heap(NO_WORDS, WordSize(16384), false).is_none());
+        alloc_gc_enable();
+        GC_STATE.with_borrow(|state| assert_eq!(state.alloc_gc_disabled, 0));
+    }
+
+    #[test]
+    fn without_alloc_gc_enables_after_panic() {
+        reset();
+        let res = ::std::panic::catch_unwind(|| without_alloc_gc(|| panic!("inside without_alloc_gc")));
+        assert!(res.is_err());
+        GC_STATE.with_borrow(|state| assert_eq!(state.alloc_gc_disabled, 0));
+    }
+
+    #[test]
+    fn alloc_larger_than_region_does_not_collect() {
+        init(GcConf::builder()
+            .young_side_capacity(WordSize(1024))
+            .large_capacity(WordSize(4096))
+            .build().unwrap());
+        stack_frame_push();
+        // below the large object threshold, but larger than a young side
+        assert_eq!(alloc0_heap(NO_WORDS, WordSize(1500), false), None);
+        // above the threshold, but larger than the large object region
+        assert_eq!(alloc0_heap(NO_WORDS, WordSize(5000), false), None);
+        assert_eq!(gc_stats().fast_collections, 0, "collecting cannot make room for these");
+        assert_eq!(gc_stats().full_collections, 0);
+        assert!(alloc0_heap(NO_WORDS, WordSize(1000), false).is_some());
+        assert!(alloc0_heap(NO_WORDS, WordSize(4000), false).is_some());
+    }
+
+    #[test]
+    fn header_manipulation() {
+        let mut nr: Nr = 0x00000004;
+        mark_reachable(&mut nr);
+        assert_eq!(nr, 0x00008004);
+        increment_gc_age(&mut nr);
+        assert_eq!(nr, 0x00008104);
+        increment_gc_age(&mut nr);
+        assert_eq!(nr, 0x00008204);
+        assert_eq!(get_gc_age(nr), 2);
+        for _ in 0 .. 12 {
+            increment_gc_age(&mut nr);
+        }
+        assert_eq!(nr, 0x00008704);
+        assert_eq!(get_gc_age(nr), 7);
+    }
+
+    #[test]
+    fn alloc_stack_out_of_space() {
+        reset();
+        for _ in 0 .. 4 {
+            let addr1 = alloc0_stack(WordSize(0), WordSize(255));
+            assert!(addr1.is_some());
+        }
+        let addr2 = alloc0_stack(WordSize(0), WordSize(255));
+        assert!(addr2.is_none());
+    }
+
+    #[test]
+    fn alloc_data_on_heap() {
+        reset();
+        let orig = alloc_heap(ONE_WORD, THREE_WORDS, false);
+        let subsequent = alloc_heap(TWO_WORDS, THREE_WORDS, false);
+        DATA.with_borrow_mut(|data| assert_eq!(data[orig - WORD_SIZE], 0x03010004));
+        assert_eq!(subsequent - orig, ByteSize(16));
+        assert_eq!(young_heap_size(), WordSize(8));
+        assert_eq!(stack_size(), NO_WORDS);
+    }
+
+    #[test]
+    fn alloc_data_on_stack() {
+        reset();
+        stack_frame_push();
+        let orig = alloc_stack(ONE_WORD, THREE_WORDS);
+        stack_frame_push();
+        let subsequent = alloc_stack(TWO_WORDS, THREE_WORDS);
+        DATA.with_borrow_mut(|data| assert_eq!(data[orig - WORD_SIZE], 0x03010004));
+        assert_eq!(subsequent - orig, WORD_SIZE * 5);
+        assert_eq!(stack_size(), WordSize(1 + 1 + 3 + 1 + 1 + 3));
+        stack_frame_pop();
+        assert_eq!(stack_size(), WordSize(1 + 1 + 3));
+        stack_frame_pop();
+        assert_eq!(stack_size(), NO_WORDS);
+        assert_eq!(young_heap_size(), NO_WORDS);
+    }
+
+    #[test]
+    fn stack_range_contains_pushed_frames() {
+        reset();
+        let empty = stack_range();
+        assert_eq!(empty.start, empty.end);
+        let frame = stack_frame_push();
+        alloc_stack(ONE_WORD, TWO_WORDS);
+        let range = stack_range();
+        assert_eq!(range.start, frame);
+        assert!(range.contains(&frame));
+        assert_eq!(range.end - range.start, WORD_SIZE * 4);
+        stack_frame_pop();
+        assert_eq!(stack_range(), empty);
+    }
+
+    #[test]
+    fn alloc_arrays_on_heap() {
+        reset();
+        let pointers = alloc_array(THREE_WORDS, true, true);
+        let values = alloc_array(WordSize(4), false, false);
+        let empty = alloc_array(NO_WORDS, false, false);
+        assert_eq!(array_len(pointers), THREE_WORDS);
+        assert_eq!(array_len(values), WordSize(4));
+        assert_eq!(array_len(empty), NO_WORDS);
+        DATA.with_borrow(|data| {
+            assert_eq!(data[pointers - WORD_SIZE], 0x03034008);
+            assert_eq!(data[values - WORD_SIZE], 0x04000008);
+        });
+        assert_eq!(young_heap_size(), WordSize(4 + 5 + 1));
+    }
+
+    #[test]
+    fn big_header_encode_decode() {
+        for (pointer_cnt, size_32) in [(WordSize(3), WordSize(300)), (WordSize(300), WordSize(300)), (WordSize(256), WordSize(70000))] {
+            let header = YoungHeapHeader {
+                data_kind: DataKind::Struct,
+                pointers_mutable: true,
+                pointer_cnt,
+                size_32,
+            };
+            let enc = header.encode();
+            assert_eq!(enc.len(), WORD_SIZE * 2);
+            let mut data = Data { mem: vec![0; 4] };
+            enc.write_to(Pointer(4), &mut data);
+            assert_eq!(HeaderEnc::read_at(&data, Pointer(4)), enc);
+            assert_eq!(HeaderEnc::read_before(&data, Pointer(12)), enc);
+            let decoded = YoungHeapHeader::decode(enc);
+            assert_eq!(decoded.pointer_cnt, pointer_cnt);
+            assert_eq!(decoded.size_32, size_32);
+            assert!(decoded.pointers_mutable);
+        }
+        assert!(matches!(StackHeader { data_kind: DataKind::Array, pointer_cnt: WordSize(255), size_32: WordSize(255) }.encode(), HeaderEnc::Small(_)));
+    }
+
+    #[test]
+    fn alloc_big_objects() {
+        reset();
+        stack_frame_push();
+        let stack_big = alloc_stack(WordSize(2), WordSize(300));
+        let heap_small = alloc_heap(ONE_WORD, ONE_WORD, false);
+        let heap_big = alloc_array(WordSize(1000), true, true);
+        let heap_after = alloc_heap(ONE_WORD, ONE_WORD, false);
+        assert_eq!(heap_big - heap_small, WORD_SIZE * 3);
+        assert_eq!(heap_after - heap_big, WORD_SIZE * 1001);
+        assert_eq!(array_len(heap_big), WordSize(1000));
+        DATA.with_borrow(|data| {
+            assert_eq!(data[stack_big - WORD_SIZE * 2], (300 << 8) | 0x10);
+            assert_eq!(data[stack_big - WORD_SIZE], 0x00020014);
+            assert_eq!(data[heap_big - WORD_SIZE * 2], (1000 << 8) | 0x10);
+            assert_eq!(data[heap_big - WORD_SIZE], 0xFFFF4018u32 as Nr);
+        });
+        assert_eq!(stack_size(), WordSize(1 + 2 + 300));
+        assert_eq!(young_heap_size(), WordSize(2 + 1002 + 2));
+    }
+
+    #[test]
+    fn alloc_fails_if_header_cannot_describe_object() {
+        reset();
+        assert_eq!(check_object_size(WordSize(70000), WordSize(70001)),
+            Err(ObjectSizeError::TooManyPointers { pointer_cnt: WordSize(70000) }));
+        assert_eq!(check_object_size(NO_WORDS, WordSize(1 << 23)),
+            Err(ObjectSizeError::TooLarge { size_32: WordSize(1 << 23) }));
+        assert_eq!(check_object_size(WordSize(70000), WordSize(70000)), Ok(()));
+        assert_eq!(alloc0_heap(WordSize(70000), WordSize(70001), true), None);
+        assert_eq!(alloc0_heap(NO_WORDS, WordSize(1 << 23), false), None);
+        assert_eq!(alloc0_array(WordSize(1 << 23), true, false), None);
+        stack_frame_push();
+        assert_eq!(alloc0_stack(WordSize(70000), WordSize(70001)), None);
+        assert_eq!(gc_stats().fast_collections, 0, "collecting cannot make room for these");
+        assert!(alloc0_heap(NO_WORDS, ONE_WORD, false).is_some());
+    }
+
+    #[test]
+    fn gc_big_objects_through_promotion() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(TWO_WORDS, WordSize(260)));
+        let _not_referenced = fill_zeros(alloc_heap(NO_WORDS, WordSize(400), false));
+        let big_pointers = fill_zeros(alloc_array(WordSize(300), true, true));
+        let big_values = fill_zeros(alloc_heap(ONE_WORD, WordSize(500), false));
+        let small = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = big_pointers.0;
+            data[stack_ref + WORD_SIZE] = big_values.0;
+            data[stack_ref + WORD_SIZE * 259] = 555;
+            data[big_pointers + WORD_SIZE * 299] = small.0;
+            data[big_values + WORD_SIZE * 499] = 666;
+            data[small] = 777;
+        });
+        collect_fast();
+        assert_eq!(young_heap_size(), WordSize(302 + 502 + 2), "big objects should be copied including both header words");
+        for _ in 1 .. TENURE_GC_AGE {
+            collect_fast();
+        }
+        assert_eq!(young_heap_size(), NO_WORDS);
+        assert_eq!(old_heap_size(), WordSize(302 + 502 + 2));
+        collect_full();
+        assert_eq!(old_heap_size(), WordSize(302 + 502 + 2));
+        DATA.with_borrow(|data| {
+            assert_eq!(data[stack_ref + WORD_SIZE * 259], 555);
+            let big_pointers_new = data.read_pointer(stack_ref);
+            let big_values_new = data.read_pointer(stack_ref + WORD_SIZE);
+            let (_, _, pointer_cnt, size_32) = HeaderEnc::read_before(data, big_pointers_new).decode();
+            assert_eq!((pointer_cnt, size_32), (WordSize(300), WordSize(300)));
+            assert_eq!(data[big_values_new + WORD_SIZE * 499], 666);
+            assert_eq!(data[data.read_pointer(big_pointers_new + WORD_SIZE * 299)], 777);
+        });
+    }
+
+    #[test]
+    fn fast_gc_scans_only_pointer_array_elements() {
+        reset();
+        stack_frame_push();
+        let stack_arr = fill_zeros(alloc_stack_array(TWO_WORDS, true));
+        let pointers = fill_zeros(alloc_array(TWO_WORDS, true, false));
+        let values = fill_zeros(alloc_array(TWO_WORDS, false, false));
+        let not_referenced = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        let referenced = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_arr] = pointers.0;
+            data[pointers] = values.0;
+            data[pointers + WORD_SIZE] = referenced.0;
+            data[values] = not_referenced.0;
+            data[values + WORD_SIZE] = 888;
+            data[referenced] = 999;
+        });
+        assert_eq!(stack_size(), WordSize(4));
+        collect_fast();
+        assert_eq!(young_heap_size(), WordSize(3 + 3 + 2), "data array elements should not be followed");
+        DATA.with_borrow(|data| {
+            let pointers_new = data.read_pointer(stack_arr);
+            assert_eq!(array_len(pointers_new), TWO_WORDS);
+            let values_new = data.read_pointer(pointers_new);
+            assert_eq!(data.read_pointer(values_new), not_referenced, "data array element should not be updated");
+            assert_eq!(data[values_new + WORD_SIZE], 888);
+            assert_eq!(data[data.read_pointer(pointers_new + WORD_SIZE)], 999);
+        });
+    }
+
+    #[test]
+    fn full_gc_keeps_promoted_arrays() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let pointers = fill_zeros(alloc_array(ONE_WORD, true, true));
+        let values = fill_zeros(alloc_array(THREE_WORDS, false, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = pointers.0;
+            data[pointers] = values.0;
+            data[values + WORD_SIZE * 2] = 777;
+        });
+        for _ in 0 .. TENURE_GC_AGE {
+            collect_fast();
+        }
+        assert_eq!(young_heap_size(), NO_WORDS);
+        collect_full();
+        assert_eq!(old_heap_size(), WordSize(2 + 4));
+        DATA.with_borrow(|data| {
+            let pointers_new = data.read_pointer(stack_ref);
+            let values_new = data.read_pointer(pointers_new);
+            assert_eq!(array_len(pointers_new), ONE_WORD);
+            assert_eq!(array_len(values_new), THREE_WORDS);
+            assert_eq!(data[values_new + WORD_SIZE * 2], 777);
+        });
+    }
+
+    #[test]
+    fn alloc_large_objects_outside_young() {
         reset();
-        for _ in 0 .. 64 {
-            let addr1 = alloc0_heap(WordSize(0), WordSize(255), false);
-            assert!(addr1.is_some());
-        }
-        let addr2 = alloc0_heap(WordSize(0), WordSize(255), false);
-        assert!(addr2.is_none());
+        let _below_threshold = alloc_array(WordSize(2047), false, false);
+        let large = alloc_array(WordSize(2048), true, false);
+        assert_eq!(young_heap_size(), WordSize(2 + 2047));
+        assert_eq!(large_heap_size(), WordSize(1 + 2 + 2048));
+        assert_eq!(array_len(large), WordSize(2048));
     }
 
     #[test]
-    fn header_manipulation() {
-        let mut nr: Nr = 0x00000004;
-        mark_reachable(&mut nr);
-        assert_eq!(nr, 0x00008004);
-        increment_gc_age(&mut nr);
-        assert_eq!(nr, 0x00008104);
-        increment_gc_age(&mut nr);
-        assert_eq!(nr, 0x00008204);
-        assert_eq!(get_gc_age(nr), 2);
-        for _ in 0 .. 12 {
-            increment_gc_age(&mut nr);
+    fn fast_gc_does_not_move_large_objects() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let large = fill_zeros(alloc_array(WordSize(3000), true, true));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = large.0;
+            data[young] = 444;
+        });
+        write_pointer(large + WORD_SIZE * 2999, young);
+        for _ in 0 .. TENURE_GC_AGE + 1 {
+            let stats = collect_fast();
+            assert_eq!(stats.large_capacity, WordSize(16384));
+            assert_eq!(stats.large_len, WordSize(3003));
         }
-        assert_eq!(nr, 0x00008704);
-        assert_eq!(get_gc_age(nr), 7);
+        assert_eq!(young_heap_size(), NO_WORDS);
+        assert_eq!(old_heap_size(), TWO_WORDS, "young object referenced only from large object should be promoted");
+        DATA.with_borrow(|data| {
+            assert_eq!(data.read_pointer(stack_ref), large);
+            assert_eq!(data[data.read_pointer(large + WORD_SIZE * 2999)], 444);
+        });
     }
 
     #[test]
-    fn alloc_stack_out_of_space() {
+    fn full_gc_sweeps_and_reuses_large_blocks() {
         reset();
-        for _ in 0 .. 4 {
-            let addr1 = alloc0_stack(WordSize(0), WordSize(255));
-            assert!(addr1.is_some());
-        }
-        let addr2 = alloc0_stack(WordSize(0), WordSize(255));
-        assert!(addr2.is_none());
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let garbage = alloc_heap(NO_WORDS, WordSize(3000), false);
+        let kept = fill_zeros(alloc_heap(ONE_WORD, WordSize(2500), false));
+        let _old_garbage = alloc_old(NO_WORDS, TWO_WORDS, false);
+        let old = alloc_old(NO_WORDS, ONE_WORD, false);
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = kept.0;
+            data[kept] = old.0;
+            data[old] = 321;
+        });
+        let stats = collect_full();
+        assert_eq!(stats.initial_large_len, WordSize(3003 + 2503));
+        assert_eq!(stats.final_large_len, WordSize(2503));
+        assert_eq!(old_heap_size(), TWO_WORDS);
+        DATA.with_borrow(|data| {
+            assert_eq!(data.read_pointer(stack_ref), kept, "large objects should not move");
+            assert_ne!(data.read_pointer(kept), old, "pointers from large objects should be updated");
+            assert_eq!(data[data.read_pointer(kept)], 321);
+        });
+
+        // Freed block is reused, and split if too big
+        let reused = alloc_heap(NO_WORDS, WordSize(2048), false);
+        assert_eq!(reused, garbage);
+        assert_eq!(large_heap_size(), WordSize(2503 + 2051));
+
+        // Once all is unreachable, adjacent free blocks merge and the whole region can be used
+        DATA.with_borrow_mut(|data| data[stack_ref] = 0);
+        collect_full();
+        assert_eq!(large_heap_size(), NO_WORDS);
+        assert!(alloc0_heap(NO_WORDS, WordSize(16384 - 3), false).is_some());
+        without_alloc_gc(|| assert!(alloc0_heap(NO_WORDS, WordSize(2048), false).is_none()));
     }
 
     #[test]
-    fn alloc_data_on_heap() {
+    fn resize_grow_keeps_objects_in_all_regions() {
         reset();
-        let orig = alloc_heap(ONE_WORD, THREE_WORDS, false);
-        let subsequent = alloc_heap(TWO_WORDS, THREE_WORDS, false);
-        DATA.with_borrow_mut(|data| assert_eq!(data[orig - WORD_SIZE], 0x03010004));
-        assert_eq!(subsequent - orig, ByteSize(16));
-        assert_eq!(young_heap_size(), WordSize(8));
-        assert_eq!(stack_size(), NO_WORDS);
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(THREE_WORDS, THREE_WORDS));
+        let young = fill_zeros(alloc_heap(ONE_WORD, TWO_WORDS, true));
+        let old = fill_zeros(alloc_old(ONE_WORD, TWO_WORDS, true));
+        let old_immut = fill_zeros(alloc_old(NO_WORDS, ONE_WORD, false));
+        let large = fill_zeros(alloc_array(WordSize(3000), false, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = young.0;
+            data[stack_ref + WORD_SIZE] = old.0;
+            data[stack_ref + WORD_SIZE * 2] = large.0;
+            data[young] = old_immut.0;
+            data[young + WORD_SIZE] = 111;
+            data[old + WORD_SIZE] = 222;
+            data[old_immut] = 333;
+            data[large + WORD_SIZE * 2999] = 444;
+        });
+        write_pointer(old, young);
+        let mut capacities = region_capacities();
+        capacities.stack = WordSize(2048);
+        capacities.young_side = WordSize(20000);
+        capacities.old_mut = WordSize(10240);
+        capacities.large = WordSize(30000);
+        capacities.meta = WordSize(80000);
+        resize(capacities).unwrap();
+        assert_eq!(region_capacities(), capacities);
+        assert_eq!(stack_size(), WordSize(1 + 1 + 3));
+        assert_eq!(young_heap_size(), THREE_WORDS);
+        assert_eq!(old_heap_size(), THREE_WORDS + TWO_WORDS);
+        assert_eq!(large_heap_size(), WordSize(3003));
+        DATA.with_borrow(|data| {
+            let young_new = data.read_pointer(stack_ref);
+            let old_new = data.read_pointer(stack_ref + WORD_SIZE);
+            let large_new = data.read_pointer(stack_ref + WORD_SIZE * 2);
+            assert_ne!(young_new, young);
+            assert_eq!(data[young_new + WORD_SIZE], 111);
+            assert_eq!(data[data.read_pointer(young_new)], 333);
+            assert_eq!(data.read_pointer(old_new), young_new);
+            assert_eq!(data[old_new + WORD_SIZE], 222);
+            assert_eq!(data[large_new + WORD_SIZE * 2999], 444);
+        });
+        // Old mutable object still points to young, so its card is dirty, and collections still work
+        collect_fast();
+        collect_full();
+        DATA.with_borrow(|data| {
+            let young_new = data.read_pointer(stack_ref);
+            assert_eq!(data.read_pointer(data.read_pointer(stack_ref + WORD_SIZE)), young_new);
+            assert_eq!(data[young_new + WORD_SIZE], 111);
+        });
+        assert!(alloc0_heap(NO_WORDS, WordSize(2000), false).is_some());
     }
 
     #[test]
-    fn alloc_data_on_stack() {
+    fn resize_shrink_only_if_live_data_fits() {
         reset();
         stack_frame_push();
-        let orig = alloc_stack(ONE_WORD, THREE_WORDS);
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let kept = fill_zeros(alloc_heap(NO_WORDS, WordSize(200), false));
+        let _garbage = fill_zeros(alloc_heap(NO_WORDS, WordSize(5000), false));
+        DATA.with_borrow_mut(|data| data[stack_ref] = kept.0);
+        let mut capacities = region_capacities();
+        capacities.young_side = WordSize(100);
+        assert_eq!(resize(capacities), Err(ResizeError::DoesNotFit { region: Region::Young, len: WordSize(201), capacity: WordSize(100) }));
+        assert_eq!(region_capacities().young_side, WordSize(16384));
+        capacities.young_side = WordSize(300);
+        resize(capacities).unwrap();
+        assert_eq!(young_heap_size(), WordSize(201), "garbage should be collected before resizing");
+        DATA.with_borrow(|data| assert_eq!(data.len(), (GC_CONF.with_borrow(|conf| conf.end_of_memory()) - Pointer::null()).whole_words()));
+    }
+
+    #[test]
+    fn move_regions_relocates_stack_when_memory_starts_elsewhere() {
+        reset();
+        let outer_frame = stack_frame_push();
+        let outer = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
         stack_frame_push();
-        let subsequent = alloc_stack(TWO_WORDS, THREE_WORDS);
-        DATA.with_borrow_mut(|data| assert_eq!(data[orig - WORD_SIZE], 0x03010004));
-        assert_eq!(subsequent - orig, WORD_SIZE * 5);
-        assert_eq!(stack_size(), WordSize(1 + 1 + 3 + 1 + 1 + 3));
+        let inner = fill_zeros(alloc_stack(TWO_WORDS, TWO_WORDS));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[outer] = young.0;
+            data[inner] = outer.0;
+            data[inner + WORD_SIZE] = young.0;
+            data[young] = 555;
+        });
+
+        // Like `resize` in wasm, where new memory is taken after the old memory
+        let shift = ByteSize(4096);
+        GC_CONF.with_borrow_mut(|conf| GC_STATE.with_borrow_mut(|state| DATA.with_borrow_mut(|data| {
+            let mut new_conf = conf.clone();
+            new_conf.memory_start = conf.memory_start + shift;
+            let mut new_data = Data { mem: vec![0; new_conf.end_of_memory().0 as usize] };
+            move_regions(conf, &new_conf, state, data, &mut new_data);
+            *conf = new_conf;
+            *data = new_data;
+        })));
+        assert_eq!(stack_size(), WordSize(1 + 2 + 1 + 3));
+        assert_eq!(verify_heap(), Ok(()));
+        let (outer_new, inner_new) = (outer + shift, inner + shift);
+        DATA.with_borrow(|data| {
+            assert_eq!(data.read_pointer(inner_new), outer_new, "pointer between stack objects");
+            assert_eq!(data.read_pointer(outer_new), young + shift);
+            assert_eq!(data.read_pointer(inner_new - WORD_SIZE * 2), outer_frame + shift, "frame link");
+        });
+
+        // Collections find the roots through the moved frames, and popping follows the moved links
+        collect_fast();
+        DATA.with_borrow(|data| {
+            assert_eq!(data.read_pointer(inner_new + WORD_SIZE), data.read_pointer(outer_new));
+            assert_eq!(data[data.read_pointer(outer_new)], 555);
+        });
         stack_frame_pop();
-        assert_eq!(stack_size(), WordSize(1 + 1 + 3));
+        assert_eq!(stack_size(), WordSize(1 + 2));
         stack_frame_pop();
         assert_eq!(stack_size(), NO_WORDS);
-        assert_eq!(young_heap_size(), NO_WORDS);
+    }
+
+    #[test]
+    fn resize_without_gc_keeps_garbage() {
+        reset();
+        let garbage = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        without_alloc_gc(|| {
+            let mut capacities = region_capacities();
+            capacities.stack = WordSize(4096);
+            resize(capacities).unwrap();
+        });
+        assert_eq!(young_heap_size(), TWO_WORDS);
     }
 
     #[test]
@@ -957,33 +3311,549 @@ mod tests {
 
         // allocate mutable data, and immutable heap data referencing it
         stack_frame_push();
-        let heap_mut = fill_zeros(alloc_heap(ONE_WORD, ONE_WORD, true));
+        let heap_mut = fill_zeros(alloc_heap(ONE_WORD, TWO_WORDS, true));
         let heap_immut = fill_zeros(alloc_heap(ONE_WORD, ONE_WORD, false));
         let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
         DATA.with_borrow_mut(|data| {
             data[stack_ref] = heap_immut.0;
             data[heap_immut] = heap_mut.0;
+            data[heap_mut + WORD_SIZE] = 123;
         });
 
-        // do a few GC rounds to move immutable data to old heap
+        // do a few GC rounds to move both to their old heaps
         for _ in 0 .. 20 {
             collect_fast();
         }
+        GC_CONF.with_borrow(|conf| DATA.with_borrow(|data| {
+            let immut_addr = data.read_pointer(stack_ref);
+            assert!((conf.old_immut_start() .. conf.old_immut_end()).contains(&immut_addr),
+                "immutable not moved from young to immutable old");
+            let mut_addr = data.read_pointer(immut_addr);
+            assert!((conf.old_mut_start() .. conf.old_mut_end()).contains(&mut_addr),
+                "mutable not moved from young to mutable old");
+            assert_eq!(data[mut_addr + WORD_SIZE], 123);
+        }));
+
+        // Both were promoted in the same collection, so the pointer got updated, and nothing stayed young.
+        assert_eq!(young_heap_size(), NO_WORDS, "young size incorrect");
+        assert_eq!(old_heap_size(), WordSize(5), "old size incorrect");
+    }
+
+    #[test]
+    fn fast_gc_promotes_after_tenure_age() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let heap_orig = fill_zeros(alloc_heap(NO_WORDS, TWO_WORDS, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = heap_orig.0;
+            data[heap_orig] = 111;
+            data[heap_orig + WORD_SIZE] = 222;
+        });
+        for _ in 1 .. TENURE_GC_AGE {
+            collect_fast();
+        }
+        assert_eq!(young_heap_size(), THREE_WORDS, "promoted too early");
+        assert_eq!(old_heap_size(), NO_WORDS, "promoted too early");
+        collect_fast();
+        assert_eq!(young_heap_size(), NO_WORDS);
+        assert_eq!(old_heap_size(), THREE_WORDS);
+        GC_CONF.with_borrow(|conf| DATA.with_borrow(|data| {
+            let heap_new = data.read_pointer(stack_ref);
+            assert_eq!(heap_new, conf.old_immut_start() + WORD_SIZE);
+            assert_eq!(data[heap_new], 111);
+            assert_eq!(data[heap_new + WORD_SIZE], 222);
+            assert_eq!(get_gc_age(data[heap_new - WORD_SIZE]), 0, "old header should not have age");
+            let header = OldHeapHeader::decode(HeaderEnc::read_before(data, heap_new));
+            assert_eq!(header.size_32, TWO_WORDS);
+            assert!(!header.pointers_mutable);
+        }));
+        collect_fast();
+        DATA.with_borrow(|data| assert_eq!(data[data.read_pointer(stack_ref)], 111));
+    }
+
+    #[test]
+    fn fast_gc_remembers_immutable_promoted_before_referent() {
+        init(GcConf::builder().old_mut_capacity(WordSize(32)).build().unwrap());
+        let filler = fill_zeros(alloc_old(ONE_WORD, WordSize(31), true));
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let mutable = fill_zeros(alloc_heap(ONE_WORD, TWO_WORDS, true));
+        let immutable = fill_zeros(alloc_heap(ONE_WORD, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = immutable.0;
+            data[immutable] = mutable.0;
+            data[mutable + WORD_SIZE] = 333;
+        });
+
+        // The mutable old heap is full, so only the immutable object can be promoted
+        for _ in 0 ..= TENURE_GC_AGE {
+            collect_fast();
+        }
+        assert_eq!(old_heap_size(), WordSize(32 + 2));
+        assert_eq!(young_heap_size(), THREE_WORDS);
+        assert_eq!(verify_heap(), Ok(()));
+        GC_CONF.with_borrow(|conf| DATA.with_borrow(|data| {
+            let immutable_new = data.read_pointer(stack_ref);
+            assert!((conf.old_immut_start() .. conf.old_immut_end()).contains(&immutable_new));
+            assert_eq!(data[data.read_pointer(immutable_new) + WORD_SIZE], 333);
+        }));
+
+        // Once the filler is collected, the young object is promoted too, and nothing is remembered
+        assert!(filler > Pointer::null());
+        collect_full();
+        collect_fast();
+        assert_eq!(old_heap_size(), WordSize(5));
+        assert_eq!(young_heap_size(), NO_WORDS);
+        assert!(!GC_STATE.with_borrow(|state| state.old_immut_to_young));
+        DATA.with_borrow(|data| assert_eq!(data[data.read_pointer(data.read_pointer(stack_ref)) + WORD_SIZE], 333));
+        assert_eq!(verify_heap(), Ok(()));
+    }
+
+    #[cfg_attr(feature = "verify-heap", ignore = "breaks heap invariants on purpose")]
+    #[test]
+    fn full_gc_compacts_old_heap() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        fill_zeros(alloc_old(ONE_WORD, TWO_WORDS, false));
+        let deep_orig = fill_zeros(alloc_old(NO_WORDS, ONE_WORD, false));
+        fill_zeros(alloc_old(NO_WORDS, TWO_WORDS, false));
+        let shallow_orig = fill_zeros(alloc_old(TWO_WORDS, THREE_WORDS, false));
+        let young = fill_zeros(alloc_heap(ONE_WORD, TWO_WORDS, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = shallow_orig.0;
+            data[deep_orig] = 777;
+            data[shallow_orig] = deep_orig.0;
+            data[shallow_orig + WORD_SIZE] = young.0;
+            data[shallow_orig + WORD_SIZE * 2] = 888;
+            data[young] = deep_orig.0;
+            data[young + WORD_SIZE] = 999;
+        });
+        assert_eq!(old_heap_size(), WordSize(12));
+        let stats = collect_full();
+        assert_eq!(stats.initial_old_len, WordSize(12));
+        assert_eq!(stats.final_old_len, WordSize(6));
+        assert_eq!(old_heap_size(), WordSize(6));
+        assert_eq!(young_heap_size(), THREE_WORDS);
+        DATA.with_borrow(|data| {
+            let shallow_new = data.read_pointer(stack_ref);
+            let deep_new = data.read_pointer(shallow_new);
+            assert!(shallow_new < shallow_orig, "old heap not compacted");
+            assert!(deep_new < deep_orig, "old heap not compacted");
+            assert_eq!(data[deep_new], 777);
+            assert_eq!(data.read_pointer(shallow_new + WORD_SIZE), young);
+            assert_eq!(data[shallow_new + WORD_SIZE * 2], 888);
+            assert_eq!(data.read_pointer(young), deep_new);
+            assert_eq!(data[young + WORD_SIZE], 999);
+            assert!(!is_reachable(data[shallow_new - WORD_SIZE]));
+            assert!(!is_reachable(data[young - WORD_SIZE]));
+        });
+    }
+
+    #[test]
+    fn full_gc_old_cycle_reachable_through_young() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let dead = fill_zeros(alloc_old(ONE_WORD, ONE_WORD, false));
+        let cycle_a = fill_zeros(alloc_old(ONE_WORD, TWO_WORDS, false));
+        let cycle_b = fill_zeros(alloc_old(ONE_WORD, TWO_WORDS, false));
+        let young = fill_zeros(alloc_heap(ONE_WORD, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = young.0;
+            data[young] = cycle_a.0;
+            data[dead] = dead.0;
+            data[cycle_a] = cycle_b.0;
+            data[cycle_a + WORD_SIZE] = 111;
+            data[cycle_b] = cycle_a.0;
+            data[cycle_b + WORD_SIZE] = 222;
+        });
+        collect_full();
+        assert_eq!(old_heap_size(), WordSize(6));
+        DATA.with_borrow(|data| {
+            let a_new = data.read_pointer(young);
+            let b_new = data.read_pointer(a_new);
+            assert_eq!(data.read_pointer(b_new), a_new);
+            assert_eq!(data[a_new + WORD_SIZE], 111);
+            assert_eq!(data[b_new + WORD_SIZE], 222);
+        });
+    }
+
+    /// With the `verify-heap` feature, `collect_full` also checks the heap itself afterwards
+    #[test]
+    fn full_gc_clears_dead_young_pointers_to_old() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let freed = fill_zeros(alloc_old(NO_WORDS, ONE_WORD, false));
+        let moved = fill_zeros(alloc_old(NO_WORDS, ONE_WORD, false));
+        let dead = fill_zeros(alloc_heap(TWO_WORDS, TWO_WORDS, false));
         DATA.with_borrow_mut(|data| {
-            assert_ne!(heap_immut.0, data[stack_ref], "immutable not moved from young to old");
-            let mut_addr = data.read_pointer(stack_ref);
-            assert_eq!(heap_mut.0, data[mut_addr], "mutable data moved, it should stay young");
+            data[stack_ref] = moved.0;
+            data[moved] = 777;
+            data[dead] = freed.0;
+            data[dead + WORD_SIZE] = moved.0;
+        });
+        collect_full();
+        assert_eq!(verify_heap(), Ok(()));
+        assert_eq!(young_heap_size(), THREE_WORDS, "dead young objects stay until collect_fast");
+        DATA.with_borrow(|data| {
+            let moved_new = data.read_pointer(stack_ref);
+            assert!(moved_new < moved, "old heap not compacted");
+            assert_eq!(data[moved_new], 777);
+            assert_eq!(data.read_pointer(dead), Pointer::null());
+            assert_eq!(data.read_pointer(dead + WORD_SIZE), Pointer::null());
+        });
+    }
+
+    #[test]
+    fn full_gc_cleans_old_if_unreferenced() {
+        reset();
+        let self_ref = fill_zeros(alloc_old(ONE_WORD, TWO_WORDS, false));
+        DATA.with_borrow_mut(|data| data[self_ref] = self_ref.0);
+        let stats = collect_full();
+        assert_eq!(stats.initial_old_len, THREE_WORDS);
+        assert_eq!(old_heap_size(), NO_WORDS);
+    }
+
+    /// The break table needs two words per live old object, which for these small objects
+    /// is more than fits in the metadata region that used to be the default (4096 words)
+    #[test]
+    fn full_gc_break_table_fits_many_small_objects() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(TWO_WORDS, TWO_WORDS));
+        let mut live_cnt = 0;
+        for (list_ix, (pointers_mutable, object_cnt)) in [(true, 4096), (false, 8192)].into_iter().enumerate() {
+            let list_ref = stack_ref + WORD_SIZE * list_ix as Nr;
+            for object_ix in 0 .. object_cnt {
+                let object = fill_zeros(alloc_old(ONE_WORD, ONE_WORD, pointers_mutable));
+                if object_ix % 3 != 0 {
+                    DATA.with_borrow_mut(|data| {
+                        data[object] = data[list_ref];
+                        data[list_ref] = object.0;
+                    });
+                    live_cnt += 1;
+                }
+            }
+        }
+        assert!(live_cnt > 4096 / 2);
+        collect_full();
+        assert_eq!(old_heap_size(), WordSize(2 * live_cnt));
+        assert_eq!(verify_heap(), Ok(()));
+        DATA.with_borrow(|data| {
+            let mut found_cnt = 0;
+            for list_ix in 0 .. 2 {
+                let mut object = data.read_pointer(stack_ref + WORD_SIZE * list_ix);
+                while object != Pointer::null() {
+                    found_cnt += 1;
+                    object = data.read_pointer(object);
+                }
+            }
+            assert_eq!(found_cnt, live_cnt);
         });
+    }
 
-        // The problem to test for is this: if collect_fast does not scan old heap,
-        // then it does not see the pointer to heap_mut, and that gets collected.
-        assert_ne!(young_heap_size(), NO_WORDS, "mutable data got collected but was reachable");
-        assert_eq!(young_heap_size(), TWO_WORDS, "young size incorrect");
+    /// An empty object at the top of an old region has a pointer equal to the top
+    #[test]
+    fn full_gc_keeps_empty_object_at_old_top() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let empty = alloc_array(NO_WORDS, false, false);
+        DATA.with_borrow_mut(|data| data[stack_ref] = empty.0);
+        for _ in 0 .. 4 {
+            collect_fast();
+        }
+        assert_eq!(young_heap_size(), NO_WORDS);
+        let old_len = old_heap_size();
+        assert_ne!(old_len, NO_WORDS);
+        collect_full();
+        assert_eq!(old_heap_size(), old_len);
+        assert_eq!(verify_heap(), Ok(()));
+        let empty_new = DATA.with_borrow(|data| data.read_pointer(stack_ref));
+        assert_eq!(array_len(empty_new), NO_WORDS);
     }
 
     #[test]
     fn fast_gc_young_data_ref_from_old_mutable() {
-        todo!()  //TODO @mark:
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let old_mut = fill_zeros(alloc_old(ONE_WORD, ONE_WORD, true));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = old_mut.0;
+            data[young] = 555;
+        });
+        write_pointer(old_mut, young);
+        let mut young_prev = young;
+        for _ in 0 .. 2 {
+            collect_fast();
+            assert_eq!(young_heap_size(), TWO_WORDS, "young data only referenced from mutable old got collected");
+            DATA.with_borrow(|data| {
+                let young_new = data.read_pointer(old_mut);
+                assert_ne!(young_new, young_prev, "pointer from mutable old not updated");
+                assert_eq!(data[young_new], 555);
+                young_prev = young_new;
+            });
+        }
+    }
+
+    #[cfg_attr(feature = "verify-heap", ignore = "breaks heap invariants on purpose")]
+    #[test]
+    fn fast_gc_skips_clean_cards() {
+        reset();
+        let old_mut = fill_zeros(alloc_old(ONE_WORD, ONE_WORD, true));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        // write without barrier, so the card stays clean
+        DATA.with_borrow_mut(|data| data[old_mut] = young.0);
+        collect_fast();
+        assert_eq!(young_heap_size(), NO_WORDS, "clean card should not be scanned");
+    }
+
+    #[cfg_attr(feature = "verify-heap", ignore = "breaks heap invariants on purpose")]
+    #[test]
+    fn fast_gc_skips_clean_large_cards() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let large = fill_zeros(alloc_array(WordSize(3000), true, true));
+        let kept = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        let skipped = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = large.0;
+            // write without barrier, so the card stays clean
+            data[large] = skipped.0;
+        });
+        write_pointer(large + WORD_SIZE * 2999, kept);
+        GC_CONF.with_borrow(|conf| DATA.with_borrow(|data| {
+            assert_eq!(data[conf.card_table_entry(conf.card_ix(large))], CARD_CLEAN);
+            assert_eq!(data[conf.card_table_entry(conf.card_ix(large + WORD_SIZE * 2999))], CARD_DIRTY);
+        }));
+        let stats = collect_fast();
+        assert_eq!(young_heap_size(), TWO_WORDS, "only the object on the dirty card should be kept");
+        assert!(stats.roots_scanned <= 1 + CARD_SIZE.whole_words().0, "only the stack root and the fields on the dirty card should be scanned");
+    }
+
+    #[test]
+    fn fast_gc_cleans_card_after_promotion() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let old_mut = fill_zeros(alloc_old(ONE_WORD, ONE_WORD, true));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| data[stack_ref] = old_mut.0);
+        write_pointer(old_mut, young);
+        let card_state = || GC_CONF.with_borrow(|conf| DATA.with_borrow(|data|
+            data[conf.card_table_entry(conf.card_ix(old_mut))]));
+        assert_eq!(card_state(), CARD_DIRTY);
+        for _ in 1 .. TENURE_GC_AGE {
+            collect_fast();
+            assert_eq!(card_state(), CARD_DIRTY, "card should stay dirty while pointing to young data");
+        }
+        collect_fast();
+        assert_eq!(young_heap_size(), NO_WORDS);
+        assert_eq!(card_state(), CARD_CLEAN, "card should be clean after young data is promoted");
+    }
+
+    #[test]
+    fn fast_gc_dirty_card_inside_large_object() {
+        reset();
+        let field_cnt = WordSize(3 * CARD_SIZE.whole_words().0);
+        let filler = fill_zeros(alloc_old(NO_WORDS, ONE_WORD, true));
+        let old_mut = fill_zeros(alloc_old(field_cnt, field_cnt, true));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        let field_ix = old_mut + WORD_SIZE * (2 * CARD_SIZE.whole_words().0);
+        DATA.with_borrow_mut(|data| data[young] = 666);
+        write_pointer(field_ix, young);
+        GC_CONF.with_borrow(|conf| {
+            assert!(conf.card_ix(field_ix) > conf.card_ix(old_mut), "field should be on a later card than header");
+        });
+        collect_fast();
+        assert_eq!(young_heap_size(), TWO_WORDS);
+        DATA.with_borrow(|data| {
+            let young_new = data.read_pointer(field_ix);
+            assert_ne!(young_new, young);
+            assert_eq!(data[young_new], 666);
+        });
+    }
+
+    #[test]
+    fn full_gc_rebuilds_card_table() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        fill_zeros(alloc_old(NO_WORDS, WordSize(100), true));
+        let old_mut = fill_zeros(alloc_old(ONE_WORD, ONE_WORD, true));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[stack_ref] = old_mut.0;
+            data[young] = 777;
+        });
+        write_pointer(old_mut, young);
+        collect_full();
+        collect_fast();
+        DATA.with_borrow(|data| {
+            let old_mut_new = data.read_pointer(stack_ref);
+            assert_ne!(old_mut_new, old_mut, "old heap not compacted");
+            let young_new = data.read_pointer(old_mut_new);
+            assert_ne!(young_new, young, "moved mutable old object not scanned");
+            assert_eq!(data[young_new], 777);
+        });
+    }
+
+    #[cfg_attr(feature = "verify-heap", ignore = "breaks heap invariants on purpose")]
+    #[test]
+    fn fast_gc_skips_immutable_old() {
+        reset();
+        let old_immut = fill_zeros(alloc_old(ONE_WORD, ONE_WORD, false));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| data[old_immut] = young.0);
+        collect_fast();
+        assert_eq!(young_heap_size(), NO_WORDS, "immutable old heap should not be a root");
+    }
+
+    #[test]
+    fn verify_accepts_heap_after_collections() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(TWO_WORDS, TWO_WORDS));
+        let mutable = fill_zeros(alloc_heap(ONE_WORD, TWO_WORDS, true));
+        let immutable = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        let large = fill_zeros(alloc_heap(ONE_WORD, WordSize(2048), false));
+        write_pointer(stack_ref, mutable);
+        write_pointer(stack_ref + WORD_SIZE, large);
+        write_pointer(mutable, immutable);
+        write_pointer(large, immutable);
+        assert_eq!(verify_heap(), Ok(()));
+        for _ in 0 .. TENURE_GC_AGE {
+            collect_fast();
+            assert_eq!(verify_heap(), Ok(()));
+        }
+        collect_full();
+        assert_eq!(verify_heap(), Ok(()));
+    }
+
+    #[test]
+    fn verify_finds_invalid_pointers() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let old_immut = fill_zeros(alloc_old(TWO_WORDS, TWO_WORDS, false));
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| {
+            data[old_immut] = young.0;
+            data[old_immut + WORD_SIZE] = (young + WORD_SIZE).0;
+        });
+        write_pointer(stack_ref, old_immut);
+        assert_eq!(verify_heap(), Err(vec![
+            HeapError::OldImmutableToYoung { field_ix: old_immut, target: young },
+            HeapError::Dangling { field_ix: old_immut + WORD_SIZE, target: young + WORD_SIZE },
+        ]));
+        DATA.with_borrow_mut(|data| {
+            data[old_immut] = stack_ref.0;
+            data[old_immut + WORD_SIZE] = 0;
+        });
+        assert_eq!(verify_heap(), Err(vec![HeapError::HeapToStack { field_ix: old_immut, target: stack_ref }]));
+    }
+
+    #[test]
+    fn verify_finds_invalid_headers() {
+        reset();
+        let young = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        DATA.with_borrow_mut(|data| data[young - WORD_SIZE] = new_forward(young));
+        assert_eq!(verify_heap(), Err(vec![HeapError::Forward { header_ix: young - WORD_SIZE }]));
+        DATA.with_borrow_mut(|data| data[young - WORD_SIZE] = 0x0102_0306);
+        assert_eq!(verify_heap(), Err(vec![HeapError::InvalidKind { header_ix: young - WORD_SIZE, kind: 6 }]));
+    }
+
+    #[test]
+    fn heap_dot_shows_reachable_objects() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(ONE_WORD, ONE_WORD));
+        let old = fill_zeros(alloc_old(ONE_WORD, TWO_WORDS, false));
+        let young = fill_zeros(alloc_heap(ONE_WORD, TWO_WORDS, true));
+        let garbage = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        write_pointer(stack_ref, young);
+        write_pointer(young, old);
+        let dot = heap_dot();
+        assert!(dot.starts_with("digraph heap {"));
+        assert!(dot.contains("subgraph cluster_frame_0 {"));
+        assert!(dot.contains("subgraph cluster_Young {"));
+        assert!(dot.contains("subgraph cluster_OldImmutable {"));
+        assert!(!dot.contains("cluster_OldMutable"));
+        assert!(dot.contains(&format!("n{} [ label=\"{}\\nYoung Struct, age 0, mutable\\n1 pointers, 1 data\" ];", young.0, young)));
+        assert!(dot.contains(&format!("n{} -> n{} [ label=\"0\" ];", stack_ref.0, young.0)));
+        assert!(dot.contains(&format!("n{} -> n{} [ label=\"0\" ];", young.0, old.0)));
+        assert!(!dot.contains(&format!("n{} ", garbage.0)), "unreachable objects should not be shown");
+    }
+
+    #[test]
+    fn collection_stats_count_copies_and_promotions() {
+        reset();
+        stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(TWO_WORDS, TWO_WORDS));
+        let parent = fill_zeros(alloc_heap(ONE_WORD, TWO_WORDS, false));
+        let child = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        write_pointer(stack_ref, parent);
+        write_pointer(stack_ref + WORD_SIZE, child);
+        write_pointer(parent, child);
+        let stats = collect_fast();
+        assert_eq!(stats.objects_copied, 2);
+        assert_eq!(stats.words_copied, WordSize(5));
+        assert_eq!(stats.objects_promoted, 0);
+        assert_eq!(stats.roots_scanned, 2);
+        assert_eq!(stats.forwards_followed, 1);
+        for _ in 2 .. TENURE_GC_AGE {
+            collect_fast();
+        }
+        let stats = collect_fast();
+        assert_eq!(stats.objects_copied, 0);
+        assert_eq!(stats.objects_promoted, 2);
+        assert_eq!(stats.words_promoted, WordSize(5));
+        let stats = collect_full();
+        assert_eq!(stats.roots_scanned, 2);
+        assert_eq!(stats.objects_copied, 0, "nothing to compact");
+        assert_eq!(gc_stats(), GcStats {
+            fast_collections: TENURE_GC_AGE as u64,
+            full_collections: 1,
+            bytes_allocated: 28,
+            objects_copied: 2 * (TENURE_GC_AGE as u64 - 1),
+            words_copied: 5 * (TENURE_GC_AGE as u64 - 1),
+            objects_promoted: 2,
+            words_promoted: 5,
+            roots_scanned: 2 * TENURE_GC_AGE as u64 + 2,
+            forwards_followed: TENURE_GC_AGE as u64,
+        });
+    }
+
+    #[cfg(feature = "gc-events")]
+    #[test]
+    fn events_show_copy_order() {
+        reset();
+        let sink = RecordingSink::default();
+        set_event_sink(sink.clone());
+        let frame = stack_frame_push();
+        let stack_ref = fill_zeros(alloc_stack(TWO_WORDS, TWO_WORDS));
+        let first = fill_zeros(alloc_heap(ONE_WORD, TWO_WORDS, false));
+        let second = fill_zeros(alloc_heap(NO_WORDS, ONE_WORD, false));
+        write_pointer(stack_ref, second);
+        write_pointer(stack_ref + WORD_SIZE, first);
+        write_pointer(first, second);
+        collect_fast();
+        set_event_sink(LogSink);
+        let to_start = GC_CONF.with_borrow(|conf| conf.young_side_start(Side::Right));
+        assert_eq!(sink.events(), vec![
+            GcEvent::FrameScanned { frame },
+            GcEvent::ObjectCopied { from: second, to: to_start + WORD_SIZE, words: TWO_WORDS },
+            GcEvent::ObjectCopied { from: first, to: to_start + WORD_SIZE * 3, words: THREE_WORDS },
+            GcEvent::ForwardFollowed { field_ix: to_start + WORD_SIZE * 3, target: to_start + WORD_SIZE },
+            GcEvent::CollectionFinished { full: false },
+        ]);
     }
 
     #[ignore]
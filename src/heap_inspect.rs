@@ -0,0 +1,504 @@
+//! Decode the stack and heaps of a gc.wat instance from a copy of its linear memory, to show
+//! what the collector sees. The metadata is decoded into the gc.rs header types, since the
+//! fields mean the same, but gc.wat packs them differently (see the layout comment in gc.wat).
+
+use ::std::fmt;
+use ::std::fmt::Write;
+use ::std::ops::Range;
+
+use crate::gc::DataKind;
+use crate::gc::WordSize;
+use crate::gc::YoungHeapHeader;
+
+// Addresses of the gc.wat metadata, which must match its `$addr_*` functions (see `constants_match_gc_wat`)
+const ADDR_STACK_LENGTH: u32 = 4;
+const ADDR_YOUNG_SIDE: u32 = 8;
+const ADDR_YOUNG_LENGTH: u32 = 12;
+const ADDR_OLD_LENGTH: u32 = 16;
+const ADDR_STACK_MAX_SIZE: u32 = 24;
+const ADDR_YOUNG_SIDE_MAX_SIZE: u32 = 28;
+const ADDR_STACK_TOP_FRAME: u32 = 32;
+const ADDR_OLD_MAX_SIZE: u32 = 40;
+const STACK_START: u32 = 64;
+
+// Metadata encoding, which must match gc.wat's `$type_*`, `$flag_*` and similar functions
+const TYPE_ARRAY: u8 = 2;
+const BIG_HEADER_FIRST_BYTE: u8 = 0x80;
+const FLAG_MARKED: u8 = 1;
+const FLAG_POINTERS_MUTABLE: u8 = 2;
+const FLAG_BIG: u8 = 4;
+const AGE_OFFSET_BITS: u8 = 3;
+
+/// Region ids used by the `assert_ptr_in_region` host import, which must match gc.wat's `$region_*` functions
+pub const REGION_NAMES: [&str; 3] = ["stack", "young", "old"];
+
+/// Region boundaries (byte addresses) read from the gc.wat metadata
+#[derive(Debug)]
+struct Regions {
+    stack: Range<u32>,
+    stack_len: u32,
+    young_side: u32,
+    young: Range<u32>,
+    young_len: u32,
+    young_capacity: u32,
+    old: Range<u32>,
+    old_len: u32,
+    old_capacity: u32,
+    stack_capacity: u32,
+    top_frame: u32,
+}
+
+impl Regions {
+    /// Corrupt sizes can put regions past the end of 32-bit memory, which is reported like other bad reads
+    fn read(mem: &Memory) -> Result<Self, OutOfRange> {
+        let stack_capacity = mem.word(ADDR_STACK_MAX_SIZE)?;
+        let young_capacity = mem.word(ADDR_YOUNG_SIDE_MAX_SIZE)?;
+        let young_side = mem.word(ADDR_YOUNG_SIDE)?;
+        let stack_len = mem.word(ADDR_STACK_LENGTH)?;
+        let young_len = mem.word(ADDR_YOUNG_LENGTH)?;
+        let old_len = mem.word(ADDR_OLD_LENGTH)?;
+        let young_left_start = mem.offset(STACK_START, 4 * stack_capacity as u64)?;
+        let young_start = mem.offset(young_left_start, if young_side != 0 { 4 * young_capacity as u64 } else { 0 })?;
+        let old_start = mem.offset(young_left_start, 8 * young_capacity as u64)?;
+        Ok(Regions {
+            stack: STACK_START .. mem.offset(STACK_START, 4 * stack_len as u64)?,
+            stack_len,
+            young_side,
+            young: young_start .. mem.offset(young_start, 4 * young_len as u64)?,
+            young_len,
+            young_capacity,
+            old: old_start .. mem.offset(old_start, 4 * old_len as u64)?,
+            old_len,
+            old_capacity: mem.word(ADDR_OLD_MAX_SIZE)?,
+            stack_capacity,
+            top_frame: mem.word(ADDR_STACK_TOP_FRAME)?,
+        })
+    }
+
+    /// Which region a pointer points into; pointers point after the metadata,
+    /// so they can equal the end of a region but not the start
+    fn name_of(&self, pointer: u32) -> &'static str {
+        let contains = |range: &Range<u32>| pointer > range.start && pointer <= range.end;
+        if pointer == 0 {
+            "null"
+        } else if contains(&self.young) {
+            "young"
+        } else if contains(&self.old) {
+            "old"
+        } else if contains(&self.stack) {
+            "stack"
+        } else {
+            "outside heap"
+        }
+    }
+}
+
+/// A read past the end of memory, which happens when metadata or a pointer is corrupt;
+/// this is reported in the description instead of panicking inside the host import
+#[derive(Debug, PartialEq)]
+struct OutOfRange {
+    addr: u64,
+    memory_len: usize,
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "address {} is outside wasm memory of {} bytes", self.addr, self.memory_len)
+    }
+}
+
+struct Memory<'a>(&'a [u8]);
+
+impl Memory<'_> {
+    fn byte(&self, addr: u32) -> Result<u8, OutOfRange> {
+        self.0.get(addr as usize).copied()
+            .ok_or(OutOfRange { addr: addr.into(), memory_len: self.0.len() })
+    }
+
+    fn word(&self, addr: u32) -> Result<u32, OutOfRange> {
+        let mut bytes = [0; 4];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.byte(self.offset(addr, offset as u64)?)?;
+        }
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// The address `bytes` after `addr`, unless that is beyond 32-bit memory
+    fn offset(&self, addr: u32, bytes: u64) -> Result<u32, OutOfRange> {
+        let end = addr as u64 + bytes;
+        u32::try_from(end).map_err(|_| OutOfRange { addr: end, memory_len: self.0.len() })
+    }
+}
+
+/// Metadata that cannot be decoded, so the rest of the region is skipped
+#[derive(Debug, PartialEq)]
+enum BadObject {
+    OutOfRange(OutOfRange),
+    /// The flag for big metadata disagrees with the first byte, so the metadata size is unknown
+    BigFlagMismatch { header_addr: u32, flags: u8 },
+}
+
+impl From<OutOfRange> for BadObject {
+    fn from(err: OutOfRange) -> Self {
+        BadObject::OutOfRange(err)
+    }
+}
+
+impl fmt::Display for BadObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BadObject::OutOfRange(err) => write!(f, "{err}"),
+            BadObject::BigFlagMismatch { header_addr, flags } =>
+                write!(f, "metadata at {header_addr} has flags {flags:#04x}, which do not match its size word"),
+        }
+    }
+}
+
+/// One object, decoded from its metadata
+#[derive(Debug)]
+struct Object {
+    /// Address after the metadata, which is what pointers point to
+    pointer: u32,
+    header: YoungHeapHeader,
+    gc_age: u8,
+    marked: bool,
+    /// Address of the next object
+    end: u32,
+}
+
+impl Object {
+    /// Decode the object whose metadata starts at `header_addr`; returns None for a GC forward,
+    /// since then the size is gone (these only exist during collection)
+    fn read_at(mem: &Memory, header_addr: u32) -> Result<Option<Self>, BadObject> {
+        let is_big = mem.byte(header_addr)? == BIG_HEADER_FIRST_BYTE;
+        let meta_addr = if is_big { mem.offset(header_addr, 4)? } else { header_addr };
+        let meta = mem.word(meta_addr)?;
+        if meta & 3 == 3 {
+            return Ok(None)
+        }
+        let [typ, flags, pointer_cnt_u8, data_cnt_u8] = meta.to_le_bytes();
+        if is_big != (flags & FLAG_BIG != 0) {
+            return Err(BadObject::BigFlagMismatch { header_addr, flags })
+        }
+        let (pointer_cnt, data_cnt) = if is_big {
+            (mem.word(header_addr)? >> 8, (meta >> 16) & 0xFFFF)
+        } else {
+            (pointer_cnt_u8 as u32, data_cnt_u8 as u32)
+        };
+        // stack objects have no type, so read as structs
+        let data_kind = match typ {
+            TYPE_ARRAY => DataKind::Array,
+            _ => DataKind::Struct,
+        };
+        let pointer = mem.offset(meta_addr, 4)?;
+        Ok(Some(Object {
+            pointer,
+            header: YoungHeapHeader {
+                data_kind,
+                pointers_mutable: flags & FLAG_POINTERS_MUTABLE != 0,
+                pointer_cnt: WordSize::new(pointer_cnt as i32),
+                size_32: WordSize::new((pointer_cnt + data_cnt) as i32),
+            },
+            gc_age: (flags >> AGE_OFFSET_BITS) & 7,
+            marked: flags & FLAG_MARKED != 0,
+            end: mem.offset(pointer, 4 * (pointer_cnt as u64 + data_cnt as u64))?,
+        }))
+    }
+
+    fn pointer_cnt(&self) -> u32 {
+        self.header.pointer_cnt.words() as u32
+    }
+
+    fn data_cnt(&self) -> u32 {
+        (self.header.size_32.words() - self.header.pointer_cnt.words()) as u32
+    }
+}
+
+/// Name of the region that `pointer` points into, one of `REGION_NAMES`, or "null" or "outside heap"
+/// (also if memory is too small to hold the metadata)
+pub fn region_of(mem: &[u8], pointer: u32) -> &'static str {
+    let mem = Memory(mem);
+    Regions::read(&mem).map_or("outside heap", |regions| regions.name_of(pointer))
+}
+
+/// Words of pointers and data of the objects in the active young half, so without the metadata,
+/// which gc.rs encodes differently; None if the metadata cannot be decoded
+pub fn young_field_size(mem: &[u8]) -> Option<u32> {
+    let mem = Memory(mem);
+    let regions = Regions::read(&mem).ok()?;
+    let mut size = 0;
+    let mut header_addr = regions.young.start;
+    while header_addr < regions.young.end {
+        let obj = Object::read_at(&mem, header_addr).ok()??;
+        size += obj.pointer_cnt() + obj.data_cnt();
+        header_addr = obj.end;
+    }
+    Some(size)
+}
+
+/// Describe every object on the stack (per frame) and in the active young half and old gen,
+/// with their kind, age, flags and pointer targets. `mem` is the whole linear memory.
+/// Reads outside memory are described too, and skip the rest of that region.
+pub fn describe(mem: &[u8]) -> String {
+    let mem = Memory(mem);
+    let mut out = String::new();
+    let regions = match Regions::read(&mem) {
+        Ok(regions) => regions,
+        Err(err) => {
+            writeln!(out, "metadata unreadable: {err}").unwrap();
+            return out
+        }
+    };
+
+    // frames are linked from the top, but are printed bottom-up like the other regions;
+    // each link must go down, otherwise a corrupt link could make this loop forever
+    let mut frames = vec![];
+    let mut frame = regions.top_frame;
+    let mut frame_err = None;
+    while frame != 0 {
+        match mem.word(frame) {
+            Ok(prev_frame) => {
+                frames.push(frame);
+                if prev_frame != 0 && prev_frame >= frame {
+                    frame_err = Some(format!("frame link at {frame} points to {prev_frame}, which is not below it"));
+                    break
+                }
+                frame = prev_frame;
+            }
+            Err(err) => {
+                frame_err = Some(format!("frame link unreadable: {err}"));
+                break
+            }
+        }
+    }
+    writeln!(out, "stack ({}/{} words)", regions.stack_len, regions.stack_capacity).unwrap();
+    if let Some(err) = frame_err {
+        writeln!(out, "  {err} (older frames skipped)").unwrap();
+    }
+    let mut start = regions.stack.start;
+    for &frame in frames.iter().rev() {
+        describe_objects(&mut out, &mem, &regions, start .. frame, false);
+        writeln!(out, "  frame @{frame}").unwrap();
+        start = frame + 4;
+    }
+    describe_objects(&mut out, &mem, &regions, start .. regions.stack.end, false);
+
+    writeln!(out, "young side {} ({}/{} words)", regions.young_side, regions.young_len, regions.young_capacity).unwrap();
+    describe_objects(&mut out, &mem, &regions, regions.young.clone(), true);
+    writeln!(out, "old ({}/{} words)", regions.old_len, regions.old_capacity).unwrap();
+    describe_objects(&mut out, &mem, &regions, regions.old.clone(), true);
+    out
+}
+
+fn describe_objects(out: &mut String, mem: &Memory, regions: &Regions, range: Range<u32>, is_heap: bool) {
+    if let Err(err) = describe_objects_until_err(out, mem, regions, range, is_heap) {
+        writeln!(out, "    {err} (rest of region skipped)").unwrap();
+    }
+}
+
+fn describe_objects_until_err(out: &mut String, mem: &Memory, regions: &Regions, range: Range<u32>, is_heap: bool) -> Result<(), BadObject> {
+    let mut header_addr = range.start;
+    while header_addr < range.end {
+        let Some(obj) = Object::read_at(mem, header_addr)? else {
+            writeln!(out, "    @{} forwarded to @{} (rest of region skipped)", mem.offset(header_addr, 4)?, mem.word(header_addr)? & !3).unwrap();
+            return Ok(())
+        };
+        // read the pointers before writing anything, so a bad read does not leave half a line
+        let targets = (0 .. obj.pointer_cnt())
+            .map(|ix| mem.word(mem.offset(obj.pointer, 4 * ix as u64)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        write!(out, "    @{} ", obj.pointer).unwrap();
+        if is_heap {
+            let kind = match obj.header.data_kind {
+                DataKind::Array => "array",
+                _ => "struct",
+            };
+            write!(out, "{kind} age {}", obj.gc_age).unwrap();
+            if obj.header.pointers_mutable {
+                write!(out, " mutable").unwrap();
+            }
+            if obj.marked {
+                write!(out, " marked").unwrap();
+            }
+            write!(out, ", ").unwrap();
+        }
+        write!(out, "{} pointers, {} data", obj.pointer_cnt(), obj.data_cnt()).unwrap();
+        let targets = targets.into_iter()
+            .map(|target| match regions.name_of(target) {
+                "null" => "null".to_owned(),
+                region => format!("@{target} ({region})"),
+            })
+            .collect::<Vec<_>>();
+        if !targets.is_empty() {
+            write!(out, " -> {}", targets.join(", ")).unwrap();
+        }
+        writeln!(out).unwrap();
+        header_addr = obj.end;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(mem: &mut [u8], addr: u32, word: u32) {
+        mem[addr as usize .. addr as usize + 4].copy_from_slice(&word.to_le_bytes());
+    }
+
+    /// The value of a constant function in gc.wat, like `(func $addr_young_side (result i32) i32.const 8)`
+    fn gc_wat_const(name: &str) -> u32 {
+        let wat = include_str!("../gc.wat");
+        let marker = format!("(func ${name} (result i32) i32.const ");
+        let ix = wat.find(&marker).unwrap_or_else(|| panic!("gc.wat has no constant function ${name}"));
+        let rest = &wat[ix + marker.len() ..];
+        let value = &rest[.. rest.find(')').unwrap()];
+        match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => value.parse(),
+        }.unwrap_or_else(|_| panic!("gc.wat constant ${name} is not a number: {value}"))
+    }
+
+    #[test]
+    fn constants_match_gc_wat() {
+        assert_eq!(gc_wat_const("addr_stack_length"), ADDR_STACK_LENGTH);
+        assert_eq!(gc_wat_const("addr_young_side"), ADDR_YOUNG_SIDE);
+        assert_eq!(gc_wat_const("addr_young_length"), ADDR_YOUNG_LENGTH);
+        assert_eq!(gc_wat_const("addr_old_length"), ADDR_OLD_LENGTH);
+        assert_eq!(gc_wat_const("addr_stack_max_size"), ADDR_STACK_MAX_SIZE);
+        assert_eq!(gc_wat_const("addr_young_side_max_size"), ADDR_YOUNG_SIDE_MAX_SIZE);
+        assert_eq!(gc_wat_const("addr_stack_top_frame"), ADDR_STACK_TOP_FRAME);
+        assert_eq!(gc_wat_const("addr_old_max_size"), ADDR_OLD_MAX_SIZE);
+        assert_eq!(gc_wat_const("glob_stack_start_addr"), STACK_START);
+        assert_eq!(gc_wat_const("type_array"), TYPE_ARRAY as u32);
+        assert_eq!(gc_wat_const("big_metadata_first_byte"), BIG_HEADER_FIRST_BYTE as u32);
+        assert_eq!(gc_wat_const("flag_gc_mark"), FLAG_MARKED as u32);
+        assert_eq!(gc_wat_const("flag_pointers_mutable"), FLAG_POINTERS_MUTABLE as u32);
+        assert_eq!(gc_wat_const("flag_big_metadata"), FLAG_BIG as u32);
+        assert_eq!(gc_wat_const("gc_age_offset_bits"), AGE_OFFSET_BITS as u32);
+        for (id, name) in REGION_NAMES.iter().enumerate() {
+            assert_eq!(gc_wat_const(&format!("region_{name}")), id as u32);
+        }
+    }
+
+    #[test]
+    fn describe_frames_and_heaps() {
+        // stack of 16 words, young sides of 32 words, second side active
+        let mut mem = vec![0u8; 64 + 4 * (16 + 2 * 32 + 202)];
+        store(&mut mem, ADDR_STACK_MAX_SIZE, 16);
+        store(&mut mem, ADDR_YOUNG_SIDE_MAX_SIZE, 32);
+        store(&mut mem, ADDR_YOUNG_SIDE, 1);
+        store(&mut mem, ADDR_OLD_MAX_SIZE, 256);
+        let young = 64 + 4 * 16 + 4 * 32;
+        let old = 64 + 4 * 16 + 8 * 32;
+
+        // an object before the first frame, then a frame with an object pointing to young and old
+        store(&mut mem, 64, 0x00_00_00_00);
+        store(&mut mem, 68, 0);
+        store(&mut mem, 72, 0x01_02_00_00);
+        store(&mut mem, 76, young + 4);
+        store(&mut mem, 80, old + 8);
+        store(&mut mem, 84, 7);
+        store(&mut mem, ADDR_STACK_LENGTH, 6);
+        store(&mut mem, ADDR_STACK_TOP_FRAME, 68);
+
+        // young mutable struct of age 2 pointing to itself, old array with a big header
+        store(&mut mem, young, 0x01_01_12_01);
+        store(&mut mem, young + 4, young + 4);
+        store(&mut mem, young + 8, 42);
+        store(&mut mem, ADDR_YOUNG_LENGTH, 3);
+        store(&mut mem, old, 0x80);
+        store(&mut mem, old + 4, 0x00_C8_04_02);
+        store(&mut mem, ADDR_OLD_LENGTH, 202);
+
+        let text = describe(&mem);
+        assert_eq!(text, format!("stack (6/16 words)
+    @68 0 pointers, 0 data
+  frame @68
+    @76 2 pointers, 1 data -> @{} (young), @{} (old)
+young side 1 (3/32 words)
+    @{} struct age 2 mutable, 1 pointers, 1 data -> @{} (young)
+old (202/256 words)
+    @{} array age 0, 0 pointers, 200 data
+", young + 4, old + 8, young + 4, young + 4, old + 8));
+        assert_eq!(young_field_size(&mem), Some(2));
+    }
+
+    #[test]
+    fn region_of_pointers() {
+        let mut mem = vec![0u8; 64 + 4 * (16 + 2 * 16)];
+        store(&mut mem, ADDR_STACK_MAX_SIZE, 16);
+        store(&mut mem, ADDR_YOUNG_SIDE_MAX_SIZE, 16);
+        store(&mut mem, ADDR_STACK_LENGTH, 2);
+        store(&mut mem, ADDR_YOUNG_LENGTH, 3);
+        let young = 64 + 4 * 16;
+        assert_eq!(region_of(&mem, 68), "stack");
+        assert_eq!(region_of(&mem, young), "outside heap");
+        assert_eq!(region_of(&mem, young + 12), "young");
+        assert_eq!(region_of(&mem, young + 16), "outside heap");
+        assert_eq!(region_of(&mem, 0), "null");
+    }
+
+    #[test]
+    fn describe_stops_at_forward() {
+        let mut mem = vec![0u8; 64 + 4 * (16 + 2 * 16)];
+        store(&mut mem, ADDR_STACK_MAX_SIZE, 16);
+        store(&mut mem, ADDR_YOUNG_SIDE_MAX_SIZE, 16);
+        let young = 64 + 4 * 16;
+        store(&mut mem, young, (young + 100) | 3);
+        store(&mut mem, ADDR_YOUNG_LENGTH, 4);
+        assert!(describe(&mem).contains(&format!("@{} forwarded to @{} (rest of region skipped)", young + 4, young + 100)));
+    }
+
+    #[test]
+    fn describe_reports_reads_outside_memory() {
+        // a young object with more pointers than fit in memory, then another object that is skipped
+        let mut mem = vec![0u8; 64 + 4 * (16 + 2 * 16)];
+        store(&mut mem, ADDR_STACK_MAX_SIZE, 16);
+        store(&mut mem, ADDR_YOUNG_SIDE_MAX_SIZE, 16);
+        store(&mut mem, ADDR_STACK_TOP_FRAME, 1 << 20);
+        let young = 64 + 4 * 16;
+        store(&mut mem, young, 0x00_FF_00_01);
+        store(&mut mem, ADDR_YOUNG_LENGTH, 4);
+        let text = describe(&mem);
+        assert!(text.contains(&format!("frame link unreadable: address {} is outside wasm memory of {} bytes (older frames skipped)", 1 << 20, mem.len())), "{text}");
+        assert!(text.contains(&format!("    address {} is outside wasm memory of {} bytes (rest of region skipped)", mem.len(), mem.len())), "{text}");
+        assert!(!text.contains(&format!("@{}", young + 4)), "{text}");
+
+        assert_eq!(describe(&mem[.. 16]), "metadata unreadable: address 24 is outside wasm memory of 16 bytes\n");
+        assert_eq!(region_of(&mem[.. 16], 68), "outside heap");
+    }
+
+    #[test]
+    fn describe_reports_regions_beyond_32_bit_memory() {
+        let mut mem = vec![0u8; 64 + 4 * (16 + 2 * 16)];
+        store(&mut mem, ADDR_STACK_MAX_SIZE, 0x4000_0000);
+        assert_eq!(describe(&mem), format!("metadata unreadable: address {} is outside wasm memory of {} bytes\n", 64 + (1u64 << 32), mem.len()));
+        assert_eq!(region_of(&mem, 68), "outside heap");
+    }
+
+    #[test]
+    fn describe_reports_big_flag_mismatch() {
+        let mut mem = vec![0u8; 64 + 4 * (16 + 2 * 16)];
+        store(&mut mem, ADDR_STACK_MAX_SIZE, 16);
+        store(&mut mem, ADDR_YOUNG_SIDE_MAX_SIZE, 16);
+        let young = 64 + 4 * 16;
+        store(&mut mem, young, 0x00_00_04_01);
+        store(&mut mem, ADDR_YOUNG_LENGTH, 2);
+        assert!(describe(&mem).contains(&format!("    metadata at {young} has flags 0x04, which do not match its size word (rest of region skipped)")));
+    }
+
+    #[test]
+    fn describe_stops_at_frame_link_that_does_not_go_down() {
+        let mut mem = vec![0u8; 64 + 4 * (16 + 2 * 16)];
+        store(&mut mem, ADDR_STACK_MAX_SIZE, 16);
+        store(&mut mem, ADDR_YOUNG_SIDE_MAX_SIZE, 16);
+        store(&mut mem, ADDR_STACK_LENGTH, 3);
+        store(&mut mem, ADDR_STACK_TOP_FRAME, 68);
+        store(&mut mem, 68, 68);
+        assert!(describe(&mem).contains("  frame link at 68 points to 68, which is not below it (older frames skipped)"));
+        store(&mut mem, 64, 0);
+        store(&mut mem, 68, 72);
+        assert!(describe(&mem).contains("  frame link at 68 points to 72, which is not below it (older frames skipped)"));
+    }
+}
@@ -0,0 +1,14 @@
+pub mod err_code;
+pub mod gc;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod wasm_prog;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod diff;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod wat_tests;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod heap_inspect;
+
+#[cfg(target_arch = "wasm32")]
+mod wasm;
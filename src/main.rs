@@ -1,85 +1,136 @@
-mod gc;
-
 use ::std::env;
 use ::std::fs;
+use ::std::process::exit;
 
-use ::wat;
-
-use ::wasmer::Cranelift;
-use ::wasmer::Function as HostFunction;
-use ::wasmer::Imports;
-use ::wasmer::Instance;
-use ::wasmer::Module;
-use ::wasmer::Store;
-use ::wasmer::sys::EngineBuilder;
-use ::wasmer::sys::Features;
+use ::wasmer::Type;
 use ::wasmer::Value;
 
+use ::wasm_gc_test::diff;
+use ::wasm_gc_test::diff::CompareError;
+use ::wasm_gc_test::diff::NativeGc;
+use ::wasm_gc_test::diff::Script;
+use ::wasm_gc_test::diff::WatGc;
+use ::wasm_gc_test::wasm_prog::WasmProg;
+use ::wasm_gc_test::wat_tests;
+
+const USAGE: &str = "usage: [--gc <gc file>] <command>
+    run <file> <export> [args]   call an export, with arguments parsed by its parameter types
+    run --heap <file> <export> [args]
+                                 same, then print the stack and heaps of a gc.wat instance
+    exports <file>               list exported functions and their signatures
+    test <file>                  run each test_* export in a fresh instance
+    diff <script> [file]         compare gc.rs and a wat file (gc.wat by default) on a script
+with --gc, the file of run, exports and test is a program that imports alloc, stack_push,
+gc_fast, memory, etc. from module \"gc\", which are linked to the exports of the gc file";
+
+/// Exit code when the wasm code trapped, or a diff or test failed
+const EXIT_FAILED: i32 = 1;
+/// Exit code when the arguments or files are invalid
+const EXIT_USAGE: i32 = 2;
+
 fn main() {
-    run(env::args().skip(1).next())
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let (gc_file, args) = match args.as_slice() {
+        ["--gc", gc_file, args @ ..] => (Some(*gc_file), args),
+        args => (None, args),
+    };
+    let res = match args {
+        ["run", "--heap", file, export, export_args @ ..] => run(gc_file, file, export, export_args, true),
+        ["run", file, export, export_args @ ..] => run(gc_file, file, export, export_args, false),
+        ["exports", file] => exports(gc_file, file),
+        ["test", file] => test(gc_file, file),
+        ["diff", script] if gc_file.is_none() => run_diff(script, "gc.wat"),
+        ["diff", script, file] if gc_file.is_none() => run_diff(script, file),
+        _ => Err((EXIT_USAGE, USAGE.to_owned())),
+    };
+    if let Err((code, msg)) = res {
+        eprintln!("{msg}");
+        exit(code)
+    }
 }
 
-fn run(wat_file: Option<String>) {
-    let mut prog = WasmProg::load(&wat_file.unwrap_or_else(|| "main.wat".to_owned()));
-    prog.run("tests", &[]);
-    println!("done")
-}
+type CliResult = Result<(), (i32, String)>;
 
-fn log_i32(nr: i32) {
-    println!("log_i32: {nr}")
+/// Load the file on its own, or linked against a separate GC module
+fn load(gc_file: Option<&str>, file: &str) -> Result<WasmProg, (i32, String)> {
+    match gc_file {
+        Some(gc_file) => WasmProg::try_link(gc_file, file),
+        None => WasmProg::try_load(file),
+    }.map_err(|err| (EXIT_USAGE, err))
 }
 
-fn log_i32x7(a: i32, b: i32, c: i32, d: i32, e: i32, f: i32, g: i32) {
-    println!("\t{a}:\t{b}\t{c}\t{d}\t{e}\t@{g}\t={f}")
+fn run(gc_file: Option<&str>, file: &str, export: &str, export_args: &[&str], show_heap: bool) -> CliResult {
+    let mut prog = load(gc_file, file)?;
+    let ty = prog.export_type(export)
+        .ok_or_else(|| (EXIT_USAGE, format!("no function {export} in {file}, see the exports command")))?;
+    if ty.params().len() != export_args.len() {
+        return Err((EXIT_USAGE, format!("{export} expects {} arguments ({ty}), got {}", ty.params().len(), export_args.len())));
+    }
+    let args = ty.params().iter().zip(export_args)
+        .map(|(ty, arg)| parse_value(*ty, arg))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| (EXIT_USAGE, err))?;
+    let results = prog.call(export, &args)
+        .map_err(|err| (EXIT_FAILED, format!("{export} trapped: {err}")))?;
+    for result in results.iter() {
+        println!("{}", format_value(result));
+    }
+    if show_heap {
+        print!("{}", prog.describe_heap());
+    }
+    Ok(())
 }
 
-fn log_nl() {
-    println!("")
+fn exports(gc_file: Option<&str>, file: &str) -> CliResult {
+    for (name, ty) in load(gc_file, file)?.exports() {
+        println!("{name}: {ty}");
+    }
+    Ok(())
 }
 
-fn log_err_code(nr: i32) {
-    println!("errcode: {nr}")
+fn test(gc_file: Option<&str>, file: &str) -> CliResult {
+    let results = wat_tests::run_tests(&mut load(gc_file, file)?);
+    print!("{}", wat_tests::report(&results));
+    if results.iter().any(|result| result.outcome.is_err()) {
+        return Err((EXIT_FAILED, "some tests failed".to_owned()));
+    }
+    Ok(())
 }
 
-struct WasmProg {
-    name: String,
-    store: Store,
-    instance: Instance,
+/// Run an operation script (see `diff::Script`) on gc.rs and on the wat file, and compare them
+fn run_diff(script_pth: &str, wat_pth: &str) -> CliResult {
+    let text = fs::read_to_string(script_pth)
+        .map_err(|err| (EXIT_USAGE, format!("could not read script file '{script_pth}', error: {err}")))?;
+    let script = Script::parse(&text)
+        .map_err(|err| (EXIT_USAGE, format!("could not parse script file '{script_pth}', error: {err}")))?;
+    let mut wat_gc = WatGc::try_load(wat_pth).map_err(|err| (EXIT_USAGE, err))?;
+    diff::compare(&script, &mut NativeGc, &mut wat_gc)
+        .map_err(|err| match err {
+            CompareError::InvalidScript { .. } => (EXIT_USAGE, format!("invalid script file '{script_pth}': {err}")),
+            CompareError::Diverged(_) => (EXIT_FAILED, err.to_string()),
+        })?;
+    println!("same");
+    Ok(())
 }
 
-impl WasmProg {
-    fn load(wat_pth: &str) -> Self {
-        // based on try-wasm-gen repo
-
-        let wat_data = fs::read_to_string(&wat_pth)
-            .unwrap_or_else(|err| panic!("could not read wat file '{}' (cli arg), error: {err}", &wat_pth));
-        let wasm_code = wat::parse_str(wat_data).unwrap();
-
-        let mut features = Features::new();
-        features.multi_memory(true).tail_call(true);
-        let engine = EngineBuilder::new(Cranelift::new())
-            .set_features(Some(features));
-        let mut store = Store::new(engine);
-        let module = Module::from_binary(&store, &wasm_code).unwrap();
-
-        let mut imports = Imports::new();
-        imports.define("host", "log_i32", HostFunction::new_typed(&mut store, log_i32));
-        imports.define("host", "log_i32x7", HostFunction::new_typed(&mut store, log_i32x7));
-        imports.define("host", "log_nl", HostFunction::new_typed(&mut store, log_nl));
-        imports.define("host", "log_err_code", HostFunction::new_typed(&mut store, log_err_code));
-        let instance = Instance::new(&mut store, &module, &imports).unwrap();
-        
-        WasmProg {
-            name: wat_pth.to_owned(),
-            store,
-            instance,
-        }
-    }
+fn parse_value(ty: Type, arg: &str) -> Result<Value, String> {
+    let invalid = |err: &dyn ToString| format!("argument '{arg}' is not a valid {ty:?}: {}", err.to_string());
+    Ok(match ty {
+        Type::I32 => Value::I32(arg.parse().map_err(|err| invalid(&err))?),
+        Type::I64 => Value::I64(arg.parse().map_err(|err| invalid(&err))?),
+        Type::F32 => Value::F32(arg.parse().map_err(|err| invalid(&err))?),
+        Type::F64 => Value::F64(arg.parse().map_err(|err| invalid(&err))?),
+        ty => return Err(format!("parameters of type {ty:?} are not supported")),
+    })
+}
 
-    fn run(&mut self, func: &str, args: &[Value]) -> Box<[Value]> {
-        self.instance.exports.get_function(func)
-            .unwrap_or_else(|err| panic!("could not find {func} in wasm module {}, err: {}", &self.name, &err))
-            .call(&mut self.store, args)
-            .unwrap_or_else(|err| panic!("could not execute {func} in wasm module {}, err: {}", &self.name, &err))
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::I32(nr) => nr.to_string(),
+        Value::I64(nr) => nr.to_string(),
+        Value::F32(nr) => nr.to_string(),
+        Value::F64(nr) => nr.to_string(),
+        other => format!("{other:?}"),
     }
 }
@@ -0,0 +1,128 @@
+//! Exports with the same signatures as gc.wat, so that `WasmProg::load` can run either.
+
+use ::std::cell::Cell;
+
+use crate::err_code::ErrCode;
+use crate::gc;
+use crate::gc::GcConf;
+use crate::gc::GcStats;
+use crate::gc::ObjectSizeError;
+use crate::gc::Pointer;
+use crate::gc::WordSize;
+
+#[link(wasm_import_module = "host")]
+extern "C" {
+    fn log_err_code(code: i32);
+}
+
+fn fail(err: ErrCode) -> ! {
+    unsafe { log_err_code(err.code()) };
+    ::core::arch::wasm32::unreachable()
+}
+
+/// gc.wat needs no initialization, so use default config on first call. A bottom frame is pushed
+/// so that stack objects allocated before any `stack_push` are still roots, like in gc.wat.
+fn ensure_initialized() {
+    if !gc::is_initialized() {
+        gc::init(GcConf::builder().build().expect("default conf must be valid"));
+        gc::stack_frame_push();
+    }
+}
+
+#[export_name = "alloc"]
+pub extern "C" fn alloc(pointer_cnt: i32, data_size_32: i32, pointers_mutable: i32) -> i32 {
+    let res = alloc0(pointer_cnt, data_size_32, pointers_mutable);
+    if res == 0 {
+        fail(ErrCode::OutOfMemory)
+    }
+    res
+}
+
+#[export_name = "alloc0"]
+pub extern "C" fn alloc0(pointer_cnt: i32, data_size_32: i32, pointers_mutable: i32) -> i32 {
+    ensure_initialized();
+    if pointers_mutable != 0 && pointer_cnt == 0 {
+        fail(ErrCode::MutableWithoutPointers)
+    }
+    let size_32 = WordSize::new(pointer_cnt + data_size_32);
+    match gc::check_object_size(WordSize::new(pointer_cnt), size_32) {
+        Err(ObjectSizeError::TooLarge { .. }) => fail(ErrCode::TooMuchData),
+        Err(ObjectSizeError::TooManyPointers { .. }) => fail(ErrCode::TooManyPointers),
+        Ok(()) => {}
+    }
+    gc::alloc0_heap(WordSize::new(pointer_cnt), size_32, pointers_mutable != 0)
+        .map(Pointer::as_data)
+        .unwrap_or(0)
+}
+
+#[export_name = "stack_push"]
+pub extern "C" fn stack_push() -> i32 {
+    ensure_initialized();
+    gc::stack_frame_push().as_data()
+}
+
+#[export_name = "stack_pop"]
+pub extern "C" fn stack_pop(frame_ix: i32) {
+    ensure_initialized();
+    if frame_ix < 0 {
+        fail(ErrCode::NegativeFrame)
+    }
+    let frame = Pointer::from_data(frame_ix);
+    let stack = gc::stack_range();
+    if frame >= stack.end {
+        fail(ErrCode::StackPopGrows)
+    }
+    // the bottom frame from `ensure_initialized` is at the start of the stack, and is not the caller's to pop
+    if frame <= stack.start {
+        fail(ErrCode::NegativeFrame)
+    }
+    gc::stack_frame_pop_to(frame)
+}
+
+#[export_name = "stack_alloc0"]
+pub extern "C" fn stack_alloc0(pointer_cnt: i32, data_size_32: i32) -> i32 {
+    ensure_initialized();
+    gc::alloc0_stack(WordSize::new(pointer_cnt), WordSize::new(pointer_cnt + data_size_32))
+        .map(Pointer::as_data)
+        .unwrap_or(0)
+}
+
+#[export_name = "write_pointer"]
+pub extern "C" fn write_pointer(field_addr: i32, pointer: i32) {
+    ensure_initialized();
+    gc::write_pointer(Pointer::from_data(field_addr), Pointer::from_data(pointer))
+}
+
+#[export_name = "gc_fast"]
+pub extern "C" fn gc_fast() {
+    ensure_initialized();
+    gc::collect_fast();
+}
+
+#[export_name = "gc_full"]
+pub extern "C" fn gc_full() {
+    ensure_initialized();
+    gc::collect_full();
+}
+
+/// Returns 1 if all heap invariants hold, 0 otherwise
+#[export_name = "gc_verify"]
+pub extern "C" fn gc_verify() -> i32 {
+    ensure_initialized();
+    gc::verify_heap().is_ok() as i32
+}
+
+thread_local! {
+    static STATS: Cell<GcStats> = Cell::new(GcStats::default());
+}
+
+/// Address of a snapshot of the totals since initialization, which is a `GcStats`
+/// (little-endian u64 fields), see `WasmProg::gc_stats`
+#[export_name = "gc_stats"]
+pub extern "C" fn gc_stats() -> i32 {
+    ensure_initialized();
+    STATS.with(|stats| {
+        stats.set(gc::gc_stats());
+        stats.as_ptr() as i32
+    })
+}
@@ -0,0 +1,432 @@
+use ::std::cell::Cell;
+use ::std::cell::RefCell;
+use ::std::env;
+use ::std::fs;
+use ::std::io;
+use ::std::mem::size_of;
+#[cfg(unix)]
+use ::std::os::unix::fs::DirBuilderExt;
+#[cfg(unix)]
+use ::std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
+use ::std::os::unix::fs::PermissionsExt;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::process;
+
+use ::sha2::Digest;
+use ::sha2::Sha256;
+use ::wat;
+
+use ::wasmer::Cranelift;
+use ::wasmer::Function as HostFunction;
+use ::wasmer::FunctionEnv;
+use ::wasmer::FunctionEnvMut;
+use ::wasmer::FunctionType;
+use ::wasmer::Imports;
+use ::wasmer::Instance;
+use ::wasmer::Memory;
+use ::wasmer::Module;
+use ::wasmer::RuntimeError;
+use ::wasmer::Store;
+use ::wasmer::Target;
+use ::wasmer::sys::EngineBuilder;
+use ::wasmer::sys::Features;
+use ::wasmer::Value;
+
+use crate::err_code::ErrCode;
+use crate::gc::GcStats;
+use crate::heap_inspect;
+
+/// Wasm binaries start with `\0asm`, anything else is parsed as WAT text
+const WASM_MAGIC: &[u8; 4] = b"\0asm";
+
+/// Directory for compiled modules, defaults to a subdirectory of the user's cache dir
+const CACHE_DIR_ENV: &str = "WASM_GC_CACHE_DIR";
+
+/// Import module name under which a linked program sees the exports of the GC module
+const GC_NAMESPACE: &str = "gc";
+
+thread_local! {
+    /// Code passed to `log_err_code` during the current call, which is usually followed by a trap
+    static LAST_ERR_CODE: Cell<Option<i32>> = const { Cell::new(None) };
+    /// Description of an assertion import that failed during the current call, which then traps
+    static LAST_ASSERT_FAILURE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn log_i32(nr: i32) {
+    println!("log_i32: {nr}")
+}
+
+fn log_nl() {
+    println!()
+}
+
+fn log_err_code(nr: i32) {
+    println!("error: {}", ErrCode::from_code(nr));
+    LAST_ERR_CODE.set(Some(nr));
+}
+
+/// Record the failed check with the values involved, and trap
+fn fail_assert(code: i32, check: &str, details: String) -> Result<(), RuntimeError> {
+    LAST_ASSERT_FAILURE.set(Some(format!("{} in {check}\n{details}", ErrCode::from_code(code))));
+    Err(RuntimeError::new(format!("{check} failed")))
+}
+
+fn assert_eq_i32(code: i32, expected: i32, actual: i32) -> Result<(), RuntimeError> {
+    if expected == actual {
+        return Ok(())
+    }
+    fail_assert(code, "assert_eq_i32", format!("  expected: {expected} ({expected:#x})\n    actual: {actual} ({actual:#x})"))
+}
+
+fn assert_ne_i32(code: i32, unexpected: i32, actual: i32) -> Result<(), RuntimeError> {
+    if unexpected != actual {
+        return Ok(())
+    }
+    fail_assert(code, "assert_ne_i32", format!("  expected anything but: {unexpected} ({unexpected:#x})"))
+}
+
+/// Region ids are indices in `heap_inspect::REGION_NAMES`
+fn assert_ptr_in_region(mut env: FunctionEnvMut<HostEnv>, code: i32, pointer: i32, region: i32) -> Result<(), RuntimeError> {
+    let (env, store) = env.data_and_store_mut();
+    let memory = env.memory.as_ref().expect("assert_ptr_in_region needs the module to export memory");
+    let bytes = memory.view(&store).copy_to_vec()
+        .unwrap_or_else(|err| panic!("could not read wasm memory, err: {err}"));
+    let Some(&expected) = heap_inspect::REGION_NAMES.get(region as usize) else {
+        return fail_assert(code, "assert_ptr_in_region", format!("  unknown region id {region}"))
+    };
+    let actual = heap_inspect::region_of(&bytes, pointer as u32);
+    if actual == expected {
+        return Ok(())
+    }
+    fail_assert(code, "assert_ptr_in_region", format!("  pointer @{pointer} is {}, expected {expected}",
+        if actual == "null" || actual == "outside heap" { actual.to_owned() } else { format!("in {actual}") }))
+}
+
+/// State for host functions that need to see the instance, which only exists after instantiation
+#[derive(Default)]
+struct HostEnv {
+    memory: Option<Memory>,
+}
+
+fn log_heap(mut env: FunctionEnvMut<HostEnv>) {
+    let (env, store) = env.data_and_store_mut();
+    let memory = env.memory.as_ref().expect("log_heap needs the module to export memory");
+    let bytes = memory.view(&store).copy_to_vec()
+        .unwrap_or_else(|err| panic!("could not read wasm memory, err: {err}"));
+    print!("{}", heap_inspect::describe(&bytes));
+}
+
+pub struct WasmProg {
+    name: String,
+    store: Store,
+    /// GC module that `module` was linked against, if any
+    gc: Option<GcLink>,
+    module: Module,
+    instance: Instance,
+}
+
+/// Separate GC module instance, whose exports (including memory) a program imports as "gc"
+struct GcLink {
+    module: Module,
+    instance: Instance,
+}
+
+impl WasmProg {
+    pub fn load(pth: &str) -> Self {
+        Self::try_load(pth).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Load a `.wasm` binary or `.wat` text file (detected by magic bytes), compiling it
+    /// only if there is no compiled version in the cache yet.
+    pub fn try_load(pth: &str) -> Result<Self, String> {
+        // based on try-wasm-gen repo
+        let mut store = new_store();
+        let module = Self::read_module(&store, pth)?;
+        let instance = Self::instantiate(&mut store, &module, None)
+            .map_err(|err| format!("could not instantiate wasm module '{}', error: {err}", &pth))?;
+
+        Ok(WasmProg {
+            name: pth.to_owned(),
+            store,
+            gc: None,
+            module,
+            instance,
+        })
+    }
+
+    /// Load a program whose `"gc"` imports (like `alloc`, `stack_push`, `gc_fast` and `memory`)
+    /// resolve to the exports of a separately instantiated GC module, such as gc.wat.
+    /// The program should import the GC's memory rather than define its own, so both share it.
+    /// Exports and calls are those of the program; memory accessors see the shared memory.
+    pub fn try_link(gc_pth: &str, pth: &str) -> Result<Self, String> {
+        let mut store = new_store();
+        let gc_module = Self::read_module(&store, gc_pth)?;
+        let module = Self::read_module(&store, pth)?;
+        let (gc_instance, instance) = Self::instantiate_linked(&mut store, &gc_module, &module)
+            .map_err(|err| format!("could not link wasm module '{pth}' against '{gc_pth}', error: {err}"))?;
+
+        Ok(WasmProg {
+            name: pth.to_owned(),
+            store,
+            gc: Some(GcLink { module: gc_module, instance: gc_instance }),
+            module,
+            instance,
+        })
+    }
+
+    /// Read a `.wasm` or `.wat` file and compile it (or take it from the cache)
+    fn read_module(store: &Store, pth: &str) -> Result<Module, String> {
+        let data = fs::read(pth)
+            .map_err(|err| format!("could not read wasm file '{}', error: {err}", &pth))?;
+        let wasm_code = if data.starts_with(WASM_MAGIC) {
+            data
+        } else {
+            wat::parse_bytes(&data)
+                .map_err(|err| format!("could not parse wat file '{}', error: {err}", &pth))?
+                .into_owned()
+        };
+        Self::compile_cached(store, &wasm_code)
+            .map_err(|err| format!("could not compile wasm module '{}', error: {err}", &pth))
+    }
+
+    /// Compile the module, or deserialize it from the cache if the same binary was compiled
+    /// before with the same wasmer version and features. Cache problems are not errors,
+    /// they just mean compiling again. Deserializing runs the cached native code, so only
+    /// files in a directory that no other user can write to are used.
+    fn compile_cached(store: &Store, wasm_code: &[u8]) -> Result<Module, String> {
+        let Some(dir) = private_cache_dir() else {
+            return Module::from_binary(store, wasm_code).map_err(|err| err.to_string())
+        };
+        let cache_pth = dir.join(format!("{}.wasmu", cache_key(wasm_code)));
+        if is_private(&cache_pth) {
+            // safety: only this user can write to the directory and file, and files are
+            // written by `serialize_to_file` below, for the wasmer version in the key
+            if let Ok(module) = unsafe { Module::deserialize_from_file(store, &cache_pth) } {
+                return Ok(module)
+            }
+        }
+        let module = Module::from_binary(store, wasm_code)
+            .map_err(|err| err.to_string())?;
+        // write and rename, so that parallel runs never see half a file
+        let tmp_pth = cache_pth.with_extension(format!("{}.tmp", process::id()));
+        if module.serialize_to_file(&tmp_pth).is_ok() && make_private(&tmp_pth).is_ok() {
+            let _ = fs::rename(&tmp_pth, &cache_pth);
+        }
+        let _ = fs::remove_file(&tmp_pth);
+        Ok(module)
+    }
+
+    /// Instantiate the GC module first, since the program's imports are its exports
+    fn instantiate_linked(store: &mut Store, gc_module: &Module, module: &Module) -> Result<(Instance, Instance), String> {
+        let gc_instance = Self::instantiate(store, gc_module, None)
+            .map_err(|err| format!("GC module: {err}"))?;
+        let instance = Self::instantiate(store, module, Some(&gc_instance))?;
+        Ok((gc_instance, instance))
+    }
+
+    fn instantiate(store: &mut Store, module: &Module, gc: Option<&Instance>) -> Result<Instance, String> {
+        let env = FunctionEnv::new(store, HostEnv::default());
+        let mut imports = Imports::new();
+        imports.define("host", "log_i32", HostFunction::new_typed(store, log_i32));
+        imports.define("host", "log_nl", HostFunction::new_typed(store, log_nl));
+        imports.define("host", "log_err_code", HostFunction::new_typed(store, log_err_code));
+        imports.define("host", "log_heap", HostFunction::new_typed_with_env(store, &env, log_heap));
+        imports.define("host", "assert_eq_i32", HostFunction::new_typed(store, assert_eq_i32));
+        imports.define("host", "assert_ne_i32", HostFunction::new_typed(store, assert_ne_i32));
+        imports.define("host", "assert_ptr_in_region", HostFunction::new_typed_with_env(store, &env, assert_ptr_in_region));
+        if let Some(gc) = gc {
+            imports.register_namespace(GC_NAMESPACE, gc.exports.iter()
+                .map(|(name, export)| (name.clone(), export.clone())));
+        }
+        let instance = Instance::new(store, module, &imports).map_err(|err| err.to_string())?;
+        env.as_mut(store).memory = shared_memory(&instance, gc).cloned();
+        Ok(instance)
+    }
+
+    /// Replace the instance by a fresh one, so that memory and globals are back to their initial state
+    /// (both the program and the GC module it is linked against).
+    pub fn reset(&mut self) {
+        match &mut self.gc {
+            Some(gc) => {
+                (gc.instance, self.instance) = Self::instantiate_linked(&mut self.store, &gc.module, &self.module)
+                    .unwrap_or_else(|err| panic!("could not instantiate wasm module {}, err: {err}", &self.name));
+            }
+            None => {
+                self.instance = Self::instantiate(&mut self.store, &self.module, None)
+                    .unwrap_or_else(|err| panic!("could not instantiate wasm module {}, err: {err}", &self.name));
+            }
+        }
+    }
+
+    /// Exported functions and their signatures, in the order of the module
+    pub fn exports(&self) -> Vec<(String, FunctionType)> {
+        self.module.exports().functions()
+            .map(|export| (export.name().to_owned(), export.ty().clone()))
+            .collect()
+    }
+
+    pub fn export_type(&self, func: &str) -> Option<FunctionType> {
+        self.instance.exports.get_function(func).ok()
+            .map(|function| function.ty(&self.store))
+    }
+
+    pub fn run(&mut self, func: &str, args: &[Value]) -> Box<[Value]> {
+        self.call(func, args)
+            .unwrap_or_else(|err| panic!("could not execute {func} in wasm module {}, err: {}", &self.name, &err))
+    }
+
+    /// Like `run`, but returns traps as error instead of panicking. If the module logged
+    /// an error code or failed an assertion import before trapping, the message says what it means.
+    pub fn call(&mut self, func: &str, args: &[Value]) -> Result<Box<[Value]>, String> {
+        LAST_ERR_CODE.set(None);
+        LAST_ASSERT_FAILURE.set(None);
+        self.instance.exports.get_function(func)
+            .unwrap_or_else(|err| panic!("could not find {func} in wasm module {}, err: {}", &self.name, &err))
+            .call(&mut self.store, args)
+            .map_err(|err| match (LAST_ASSERT_FAILURE.take(), LAST_ERR_CODE.take()) {
+                (Some(failure), _) => failure,
+                (None, Some(code)) => format!("{} ({})", ErrCode::from_code(code), err),
+                (None, None) => err.to_string(),
+            })
+    }
+
+    pub fn read_i32(&self, addr: i32) -> i32 {
+        let mut buf = [0u8; 4];
+        self.memory().view(&self.store).read(addr as u64, &mut buf)
+            .unwrap_or_else(|err| panic!("could not read address {addr} in wasm module {}, err: {}", &self.name, &err));
+        i32::from_le_bytes(buf)
+    }
+
+    pub fn write_i32(&mut self, addr: i32, value: i32) {
+        self.memory().view(&self.store).write(addr as u64, &value.to_le_bytes())
+            .unwrap_or_else(|err| panic!("could not write address {addr} in wasm module {}, err: {}", &self.name, &err));
+    }
+
+    /// Read the totals from a module that exports `gc_stats`, like gc.rs compiled to wasm
+    pub fn gc_stats(&mut self) -> GcStats {
+        let addr = self.run("gc_stats", &[])[0].unwrap_i32();
+        let mut buf = [0u8; size_of::<GcStats>()];
+        self.memory().view(&self.store).read(addr as u64, &mut buf)
+            .unwrap_or_else(|err| panic!("could not read stats in wasm module {}, err: {}", &self.name, &err));
+        let field = |ix: usize| u64::from_le_bytes(buf[ix * 8 .. ix * 8 + 8].try_into().unwrap());
+        GcStats {
+            fast_collections: field(0),
+            full_collections: field(1),
+            bytes_allocated: field(2),
+            objects_copied: field(3),
+            words_copied: field(4),
+            objects_promoted: field(5),
+            words_promoted: field(6),
+            roots_scanned: field(7),
+            forwards_followed: field(8),
+        }
+    }
+
+    /// Decode the stack and heaps of a gc.wat instance, see `heap_inspect::describe`
+    pub fn describe_heap(&self) -> String {
+        let bytes = self.memory().view(&self.store).copy_to_vec()
+            .unwrap_or_else(|err| panic!("could not read memory of wasm module {}, err: {}", &self.name, &err));
+        heap_inspect::describe(&bytes)
+    }
+
+    /// Words of pointers and data in the active young half of a gc.wat instance, see `heap_inspect::young_field_size`
+    pub fn young_field_size(&self) -> Option<u32> {
+        let bytes = self.memory().view(&self.store).copy_to_vec()
+            .unwrap_or_else(|err| panic!("could not read memory of wasm module {}, err: {}", &self.name, &err));
+        heap_inspect::young_field_size(&bytes)
+    }
+
+    fn memory(&self) -> &Memory {
+        shared_memory(&self.instance, self.gc.as_ref().map(|gc| &gc.instance))
+            .unwrap_or_else(|| panic!("neither wasm module {} nor its GC module export memory", &self.name))
+    }
+}
+
+fn new_store() -> Store {
+    let engine = EngineBuilder::new(Cranelift::new())
+        .set_features(Some(engine_features()));
+    Store::new(engine)
+}
+
+/// Engine features, which are part of the cache key since they change the compiled code
+fn engine_features() -> Features {
+    let mut features = Features::new();
+    features.multi_memory(true).tail_call(true);
+    features
+}
+
+/// The program's own exported memory, or else that of the GC module, which it then imports
+fn shared_memory<'a>(instance: &'a Instance, gc: Option<&'a Instance>) -> Option<&'a Memory> {
+    instance.exports.get_memory("memory").ok()
+        .or_else(|| gc.and_then(|gc| gc.exports.get_memory("memory").ok()))
+}
+
+/// `WASM_GC_CACHE_DIR`, or else a subdirectory of `$XDG_CACHE_HOME` or `~/.cache` (`%LOCALAPPDATA%` on Windows)
+fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(CACHE_DIR_ENV) {
+        return Some(PathBuf::from(dir))
+    }
+    let user_cache = env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(user_cache.join("wasm-gc-test"))
+}
+
+/// The cache dir, created if needed with access for this user only, or `None` if it is not private
+fn private_cache_dir() -> Option<PathBuf> {
+    let dir = cache_dir()?;
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(&dir).ok()?;
+    is_private(&dir).then_some(dir)
+}
+
+/// Whether `pth` exists, is not a symlink, is owned by this user and is not writable by anyone else
+#[cfg(unix)]
+fn is_private(pth: &Path) -> bool {
+    // safety: geteuid has no preconditions and always succeeds
+    let uid = unsafe { ::libc::geteuid() };
+    fs::symlink_metadata(pth).is_ok_and(|meta|
+        !meta.file_type().is_symlink() && meta.uid() == uid && meta.mode() & 0o022 == 0)
+}
+
+/// Other platforms have per-user cache dirs, and no ownership in `std`, so only symlinks are refused
+#[cfg(not(unix))]
+fn is_private(pth: &Path) -> bool {
+    fs::symlink_metadata(pth).is_ok_and(|meta| !meta.file_type().is_symlink())
+}
+
+/// Only allow this user to read and write `pth`, since otherwise the umask (e.g. 002) may make
+/// the cache file group-writable, and then `is_private` would refuse it on the next run
+#[cfg(unix)]
+fn make_private(pth: &Path) -> io::Result<()> {
+    fs::set_permissions(pth, fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn make_private(_pth: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// SHA-256 (in hex) of the binary, wasmer version, features and target. Cached modules are native code that
+/// is run without checks, so the key must be collision resistant, and unlike `DefaultHasher`,
+/// it is the same across Rust versions, so the cache survives toolchain updates.
+fn cache_key(wasm_code: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    // `Features` has no `Hash`, but its `Debug` lists every flag
+    let features = format!("{:?}", engine_features());
+    // the engine compiles for the host target, whose `Debug` has the triple and CPU features,
+    // so that a cache dir shared between machines (e.g. a network home) never runs foreign code
+    let target = format!("{:?}", Target::default());
+    let parts: [&[u8]; 4] = [wasm_code, ::wasmer::VERSION.as_bytes(), features.as_bytes(), target.as_bytes()];
+    for part in parts {
+        // length first, so that different splits of the same bytes give different keys
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,60 @@
+//! Find the exported `test_*` functions of a wasm module and run each in a fresh instance,
+//! reporting the results like `cargo test` does.
+
+use ::std::fmt::Write;
+use ::std::time::Duration;
+use ::std::time::Instant;
+
+use crate::wasm_prog::WasmProg;
+
+const TEST_PREFIX: &str = "test_";
+
+pub struct TestResult {
+    pub name: String,
+    /// Trap message if the test failed
+    pub outcome: Result<(), String>,
+    pub duration: Duration,
+}
+
+/// Run every export starting with `test_` that takes no arguments, each in its own
+/// instance, so a trap in one test does not affect the others.
+pub fn run_tests(prog: &mut WasmProg) -> Vec<TestResult> {
+    let names = prog.exports().into_iter()
+        .filter(|(name, ty)| name.starts_with(TEST_PREFIX) && ty.params().is_empty())
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    names.into_iter().map(|name| {
+        prog.reset();
+        let start = Instant::now();
+        let outcome = prog.call(&name, &[]).map(|_| ());
+        TestResult { name, outcome, duration: start.elapsed() }
+    }).collect()
+}
+
+/// Summary in the format of `cargo test`
+pub fn report(results: &[TestResult]) -> String {
+    let mut out = String::new();
+    writeln!(out, "running {} tests", results.len()).unwrap();
+    for result in results {
+        let status = if result.outcome.is_ok() { "ok" } else { "FAILED" };
+        writeln!(out, "test {} ... {} ({:.2?})", result.name, status, result.duration).unwrap();
+    }
+    let failures = results.iter()
+        .filter_map(|result| result.outcome.as_ref().err().map(|err| (&result.name, err)))
+        .collect::<Vec<_>>();
+    if !failures.is_empty() {
+        writeln!(out, "\nfailures:\n").unwrap();
+        for (name, err) in &failures {
+            writeln!(out, "---- {} ----\n{}\n", name, err).unwrap();
+        }
+        writeln!(out, "failures:").unwrap();
+        for (name, _) in &failures {
+            writeln!(out, "    {}", name).unwrap();
+        }
+    }
+    let total = results.iter().map(|result| result.duration).sum::<Duration>();
+    writeln!(out, "\ntest result: {}. {} passed; {} failed; finished in {:.2?}",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        results.len() - failures.len(), failures.len(), total).unwrap();
+    out
+}
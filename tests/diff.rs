@@ -0,0 +1,36 @@
+use ::wasm_gc_test::diff::compare;
+use ::wasm_gc_test::diff::NativeGc;
+use ::wasm_gc_test::diff::Script;
+use ::wasm_gc_test::diff::WatGc;
+
+const GC_WAT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/gc.wat");
+
+#[test]
+fn same_without_collections() {
+    let script = Script::parse("alloc 2 1 mut\nalloc 0 1 imm\nwrite 0 1 1\npush\nalloc 1 2 imm\nalloc 0 100 imm\nwrite 2 0 0\npop\nalloc 1 1 imm\nwrite 2 0 1\n").unwrap();
+    compare(&script, &mut NativeGc, &mut WatGc::load(GC_WAT)).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn same_after_gc_fast() {
+    let script = Script::parse("alloc 2 1 imm\nalloc 0 1 imm\nwrite 0 1 1\npush\nalloc 1 2 mut\nalloc 0 4 imm\nwrite 2 0 3\npop\ngc_fast\nalloc 1 1 imm\nwrite 2 0 1\ngc_fast\ngc_fast\ngc_fast\ngc_fast\n").unwrap();
+    compare(&script, &mut NativeGc, &mut WatGc::load(GC_WAT)).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn same_after_promoting_mutable_objects() {
+    let script = Script::parse("alloc 1 1 mut\ngc_fast\ngc_fast\ngc_fast\nalloc 0 1 imm\nwrite 0 0 1\ngc_fast\ngc_fast\n").unwrap();
+    compare(&script, &mut NativeGc, &mut WatGc::load(GC_WAT)).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn same_after_gc_full() {
+    let script = Script::parse("alloc 1 1 mut\ngc_fast\ngc_fast\ngc_full\nalloc 0 1 imm\nalloc 0 3 imm\nwrite 0 0 1\npush\nalloc 2 1 imm\npop\ngc_full\ngc_fast\ngc_full\n").unwrap();
+    compare(&script, &mut NativeGc, &mut WatGc::load(GC_WAT)).unwrap_or_else(|err| panic!("{err}"));
+}
+
+#[test]
+fn same_big_header_threshold() {
+    let script = Script::parse("alloc 0 200 imm\nalloc 100 155 imm\nalloc 100 156 imm\ngc_fast\n").unwrap();
+    compare(&script, &mut NativeGc, &mut WatGc::load(GC_WAT)).unwrap_or_else(|err| panic!("{err}"));
+}
@@ -0,0 +1,76 @@
+;; A program that uses the GC as a separate module, like Tel-compiled code would:
+;; everything under "gc" (including the memory) comes from the exports of gc.wat.
+;; It has no data segments or memory of its own, since the GC owns all of memory.
+;;
+;; cargo run -- --gc gc.wat test tests/linked_prog.wat
+
+(module
+    (import "gc" "memory" (memory 0))
+    (import "gc" "alloc" (func $alloc (param $pointer_cnt i32) (param $data_size_32 i32) (param $pointers_mutable i32) (result i32)))
+    (import "gc" "stack_push" (func $stack_push (result i32)))
+    (import "gc" "stack_pop" (func $stack_pop (param $frame i32)))
+    (import "gc" "stack_alloc" (func $stack_alloc (param $pointer_cnt i32) (param $data_size_32 i32) (result i32)))
+    (import "gc" "gc_fast" (func $gc_fast))
+    (import "gc" "get_young_size" (func $get_young_size (result i32)))
+    (import "host" "assert_eq_i32" (func $assert_eq_i32 (param $code i32) (param $expected i32) (param $actual i32)))
+    (import "host" "assert_ne_i32" (func $assert_ne_i32 (param $code i32) (param $unexpected i32) (param $actual i32)))
+    (import "host" "assert_ptr_in_region" (func $assert_ptr_in_region (param $code i32) (param $pointer i32) (param $region i32)))
+
+    ;; same ids as gc.wat's $region_* functions
+    (func $region_young (result i32) i32.const 1)
+
+    ;; allocate a pair of a pointer and a number, as immutable young data
+    (func $cons
+            (param $tail i32)
+            (param $nr i32)
+            (result i32)
+            (local $addr i32)
+        (local.set $addr (call $alloc (i32.const 1) (i32.const 1) (i32.const 0)))
+        (i32.store (local.get $addr) (local.get $tail))
+        (i32.store offset=4 (local.get $addr) (local.get $nr))
+        local.get $addr
+    )
+
+    (func $sum
+            (param $list i32)
+            (result i32)
+            (local $total i32)
+        (block $done (loop $next
+            (br_if $done (i32.eqz (local.get $list)))
+            (local.set $total (i32.add (local.get $total) (i32.load offset=4 (local.get $list))))
+            (local.set $list (i32.load (local.get $list)))
+            (br $next)))
+        local.get $total
+    )
+
+    (func $test_alloc_through_gc_module (export "test_alloc_through_gc_module")
+            (local $list i32)
+        (local.set $list (call $cons (call $cons (i32.const 0) (i32.const 3)) (i32.const 4)))
+        ;; two objects of a header word, a pointer and a data word each
+        (call $assert_eq_i32 (i32.const 200) (i32.const 6) (call $get_young_size))
+        (call $assert_ptr_in_region (i32.const 201) (local.get $list) (call $region_young))
+        (call $assert_eq_i32 (i32.const 202) (i32.const 7) (call $sum (local.get $list)))
+    )
+
+    ;; a list that is only referenced from a stack slot survives collection, and the slot is updated
+    (func $test_stack_root_survives_gc (export "test_stack_root_survives_gc")
+            (local $frame i32)
+            (local $slot i32)
+            (local $before i32)
+        (local.set $frame (call $stack_push))
+        (local.set $slot (call $stack_alloc (i32.const 1) (i32.const 0)))
+        (i32.store (local.get $slot) (call $cons (call $cons (i32.const 0) (i32.const 5)) (i32.const 6)))
+        ;; garbage, which should not be copied
+        (drop (call $cons (i32.const 0) (i32.const 100)))
+        (local.set $before (i32.load (local.get $slot)))
+
+        (call $gc_fast)
+
+        (call $assert_eq_i32 (i32.const 203) (i32.const 6) (call $get_young_size))
+        (call $assert_ptr_in_region (i32.const 204) (i32.load (local.get $slot)) (call $region_young))
+        ;; the other young half is used after collection, so the list must have moved
+        (call $assert_ne_i32 (i32.const 205) (local.get $before) (i32.load (local.get $slot)))
+        (call $assert_eq_i32 (i32.const 206) (i32.const 11) (call $sum (i32.load (local.get $slot))))
+        (call $stack_pop (local.get $frame))
+    )
+)
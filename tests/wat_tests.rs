@@ -0,0 +1,23 @@
+use ::wasm_gc_test::wasm_prog::WasmProg;
+use ::wasm_gc_test::wat_tests;
+
+#[test]
+fn gc_wat() {
+    let mut prog = WasmProg::load(concat!(env!("CARGO_MANIFEST_DIR"), "/gc.wat"));
+    let results = wat_tests::run_tests(&mut prog);
+    println!("{}", wat_tests::report(&results));
+    assert!(!results.is_empty(), "no test_* exports found");
+    assert!(results.iter().all(|result| result.outcome.is_ok()), "some WAT tests failed, see output");
+}
+
+#[test]
+fn program_linked_against_gc_wat() {
+    let mut prog = WasmProg::try_link(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/gc.wat"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/linked_prog.wat"),
+    ).unwrap_or_else(|err| panic!("{err}"));
+    let results = wat_tests::run_tests(&mut prog);
+    println!("{}", wat_tests::report(&results));
+    assert_eq!(results.len(), 2, "expected the test_* exports of linked_prog.wat");
+    assert!(results.iter().all(|result| result.outcome.is_ok()), "some linked WAT tests failed, see output");
+}